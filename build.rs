@@ -0,0 +1,8 @@
+// Shared with the server, which uses the rest of it; the build only needs `mime_for_path`
+#[allow(dead_code)]
+#[path = "src/packcake/mime.rs"] mod mime;
+#[path = "src/packcake/embed_build.rs"] mod embed_build;
+
+fn main() {
+    embed_build::embed_dir("static", "static.rs");
+}
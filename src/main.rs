@@ -1,12 +1,19 @@
-use std::{fs, thread, time::Duration};
+use std::{thread, time::Duration};
+use std::io::Write;
 use crate::packcake::*;
 
 #[path = "packcake/packcake.rs"] mod packcake;
 
+static STATIC: EmbeddedDir = EmbeddedDir::new(include!(concat!(env!("OUT_DIR"), "/static.rs")));
+
 fn main() {
     Packcake::new(4)
         .port(2121)
         .debug()
+        .trusted_proxies(&["10.0.0.0/8"], ProxyHeaders::XForwarded)
+        .static_embedded("/static", STATIC)
+        .compression(Compression::new())
+        .auto_etag()
         .path("/api",
                Some(Vec::from([
                    Middleware::new(middleware_v1),
@@ -19,7 +26,7 @@ fn main() {
                                  get("", get_team),
                                  post("", post_team),
                                  put("", put_team),
-                                 delete("", delete_team)
+                                 delete("", delete_team).require_query::<u64>("user_id")
                              ])),
                              group_me("/user", Vec::from([
                                  Middleware::new(middleware_auth)
@@ -31,14 +38,19 @@ fn main() {
                              ]))
                          ]))
             ]), Vec::from([
-                get("", get_docs)
+                get("", get_docs),
+                post("/upload", post_upload),
+                get("/export", get_export),
+                get("/events", get_events),
+                ws("/echo", ws_echo)
             ]))
         ])), None)
         .start();
 }
 
-fn middleware_api(_request: &Request, _response: &mut Response) -> bool {
-    println!("(/api)");
+fn middleware_api(request: &Request, _response: &mut Response) -> bool {
+    println!("(/api) #{} from {} (client {} over {})", request.connection().id(), request.peer_addr(),
+             request.client_ip(), request.scheme());
     /*let token = request.get_header("Token");
     if token.is_none() {
         response.status(StatusCode::BadRequest);
@@ -66,29 +78,107 @@ fn get_docs(req: &Request, res: &mut Response) {
     }*/
     let body = req.get_body();
     println!("Body: {}", body);
-    let docs = fs::read_to_string("static/doc.json");
-    if docs.is_ok() {
-        res.status(StatusCode::Ok);
-        res.json(&docs.unwrap());
+    match STATIC.get("doc.json") {
+        Some(docs) => {
+            let _ = res.send_embedded(req, docs);
+        }
+        None => {
+            res.status(StatusCode::NotFound);
+            res.send("helllllo");
+        }
+    }
+}
+
+pub fn get_export(_request: &Request, response: &mut Response) {
+    response.content_type("text/csv");
+    response.header("Trailer", "X-Row-Count");
+    let Ok(mut body) = response.stream_body() else {
+        return;
+    };
+    let rows = 1000;
+    let result = (|| {
+        writeln!(body, "id,name")?;
+        for id in 0..rows {
+            writeln!(body, "{id},user{id}")?;
+        }
+        body.trailer("X-Row-Count", &rows.to_string());
+        body.finish()
+    })();
+    if let Err(error) = result {
+        println!("Export failed: {error}");
+    }
+}
+
+pub fn get_events(request: &Request, response: &mut Response) {
+    let mut next_id = request.last_event_id().and_then(|id| id.parse::<u64>().ok()).map_or(0, |id| id + 1);
+    let Ok(mut events) = response.event_stream() else {
+        return;
+    };
+    let _ = events.send(&Event::retry_only(Duration::from_secs(5)));
+    // Returns once the client is gone, which frees the worker thread
+    while events.is_connected() {
+        let event = Event::data(&format!("{{\"tick\":{next_id}}}")).event("tick").id(&next_id.to_string());
+        if events.send(&event).is_err() {
+            break;
+        }
+        next_id += 1;
+        thread::sleep(Duration::from_secs(1));
+    }
+    println!("Event stream closed after {next_id} events");
+}
+
+pub fn ws_echo(_request: &Request, mut socket: WebSocket) {
+    while let Ok(message) = socket.recv() {
+        let echoed = match message {
+            Message::Text(text) => socket.send_text(&text),
+            Message::Binary(data) => socket.send_binary(&data),
+            Message::Close(_) => break,
+            _ => Ok(()),
+        };
+        if echoed.is_err() {
+            break;
+        }
     }
-    res.status(StatusCode::NotFound);
-    res.send("helllllo");
+}
+
+pub fn post_upload(request: &Request, response: &mut Response) {
+    println!("post_upload");
+    let multipart = match request.multipart() {
+        Ok(multipart) => multipart,
+        Err(error) => {
+            response.status(error.status());
+            response.send(&error.to_string());
+            return;
+        }
+    };
+    let mut received = Vec::new();
+    for part in multipart {
+        match part {
+            Ok(part) if part.is_file() => received.push(format!("{} ({} bytes)", part.filename().unwrap(), part.size())),
+            Ok(part) => received.push(format!("{}={}", part.name(), part.text().unwrap_or_default())),
+            Err(error) => {
+                response.status(error.status());
+                response.send(&error.to_string());
+                return;
+            }
+        }
+    }
+    response.send(&received.join("\n"));
 }
 
 pub fn get_user(_request: &Request, response: &mut Response) {
     println!("get_user");
-    response.header("Content-Type","Application/json");
-    response.send("{\"message\":\"get_user\"}");
+    response.json_value(&Value::object([("message", Value::from("get_user"))]));
 }
 
 pub fn post_user(_request: &Request, response: &mut Response) {
     println!("post_user");
-    response.json("{\"message\":\"post_user\"}");
+    response.json_value(&Value::object([("message", Value::from("post_user"))]));
 }
 
-pub fn put_user(_request: &Request, response: &mut Response) {
+pub fn put_user(_request: &Request) -> (StatusCode, &'static str) {
     println!("put_user");
-    response.send("put_user");
+    (StatusCode::Created, "put_user")
 }
 
 pub fn delete_user(_request: &Request, response: &mut Response) {
@@ -101,28 +191,36 @@ pub fn get_team(_request: &Request, response: &mut Response) {
     response.send("get_team");
 }
 
-pub fn post_team(_request: &Request, response: &mut Response) {
+pub struct Team {
+    name: String,
+    members: Vec<String>,
+}
+
+impl FromJson for Team {
+    fn from_json(value: &Value) -> Result<Team, String> {
+        Ok(Team {
+            name: value.field("name")?,
+            members: value.field::<Option<Vec<String>>>("members")?.unwrap_or_default(),
+        })
+    }
+}
+
+pub fn post_team(_request: &Request, response: &mut Response, Json(team): Json<Team>) {
     println!("post_team");
-    response.send("post_team");
+    response.send(format!("post_team {} ({} members)", team.name, team.members.len()).as_str());
 }
 
-pub fn put_team(_request: &Request, response: &mut Response) {
+pub fn put_team(request: &Request, Json(team): Json<Team>) -> Result<Value, ParamError> {
     println!("put_team");
-    response.send("put_team");
+    let team_id = request.get_param_as::<u64>("team_id")?;
+    Ok(Value::object([("id", Value::from(team_id)), ("name", Value::from(team.name))]))
 }
 
-pub fn delete_team(request: &Request, response: &mut Response) {
+pub fn delete_team(request: &Request) -> Result<&'static str, ParamError> {
     println!("delete_team");
-    let user_id_key = "user_id";
-    let user_id = request.get_param(user_id_key);
-    if user_id.is_none() {
-        println!("Missing user_id");
-        response.status(StatusCode::BadRequest);
-        response.send(format!("Missing param [{user_id_key}]").as_str());
-        return;
-    }
-    println!("user_id: {}", user_id.unwrap());
-    response.send("delete_team");
+    let user_id = request.get_param_as::<u64>("user_id")?;
+    println!("user_id: {}", user_id);
+    Ok("delete_team")
 }
 
 pub fn sleep_for_5(_request: &Request, response: &mut Response) {
@@ -0,0 +1,80 @@
+/// The standard base64 alphabet, from RFC 4648
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes bytes as padded base64
+pub fn encode(input: &[u8]) -> String {
+    let mut output = String::with_capacity(input.len().div_ceil(3) * 4);
+    for chunk in input.chunks(3) {
+        let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let group = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                output.push(ALPHABET[(group >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                output.push('=');
+            }
+        }
+    }
+    output
+}
+
+/// Decodes padded base64; `None` if it is not valid
+pub fn decode(input: &str) -> Option<Vec<u8>> {
+    let input = input.as_bytes();
+    if !input.len().is_multiple_of(4) {
+        return None;
+    }
+    let mut output = Vec::with_capacity(input.len() / 4 * 3);
+    for (index, chunk) in input.chunks(4).enumerate() {
+        let last = index == input.len() / 4 - 1;
+        let padding = chunk.iter().rev().take_while(|c| **c == b'=').count();
+        if padding > 2 || (padding > 0 && !last) {
+            return None;
+        }
+        let mut group = 0u32;
+        for c in &chunk[..4 - padding] {
+            group = group << 6 | ALPHABET.iter().position(|a| a == c)? as u32;
+        }
+        group <<= 6 * padding as u32;
+        output.extend_from_slice(&group.to_be_bytes()[1..4 - padding]);
+    }
+    Some(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VECTORS: [(&str, &str); 7] = [
+        ("", ""),
+        ("f", "Zg=="),
+        ("fo", "Zm8="),
+        ("foo", "Zm9v"),
+        ("foob", "Zm9vYg=="),
+        ("fooba", "Zm9vYmE="),
+        ("foobar", "Zm9vYmFy"),
+    ];
+
+    #[test]
+    fn encodes_the_rfc_4648_vectors() {
+        for (decoded, encoded) in VECTORS {
+            assert_eq!(encode(decoded.as_bytes()), encoded);
+        }
+        assert_eq!(encode(&[0xFB, 0xFF]), "+/8=");
+    }
+
+    #[test]
+    fn decodes_the_rfc_4648_vectors() {
+        for (decoded, encoded) in VECTORS {
+            assert_eq!(decode(encoded), Some(decoded.as_bytes().to_vec()));
+        }
+        assert_eq!(decode("+/8="), Some(vec![0xFB, 0xFF]));
+    }
+
+    #[test]
+    fn rejects_invalid_base64() {
+        for invalid in ["Zg", "Zg=", "Zm9", "Z===", "Zg==Zg==", "Zm9v!A==", "Zm9 v", "Zg-_"] {
+            assert_eq!(decode(invalid), None, "{invalid:?}");
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use std::io::{Cursor, Read, Write, sink};
+use std::net::TcpStream;
+
+/// The unread part of a request body, limited to its `Content-Length`
+///
+/// The body is read lazily, when the handler first asks for it.
+pub struct BodyReader {
+    inner: Box<dyn Read + Send>,
+    remaining: u64,
+    /// Where to send `100 Continue` before the first read, for `Expect: 100-continue` requests
+    continue_stream: Option<TcpStream>,
+}
+
+impl BodyReader {
+    pub(crate) fn new(inner: Box<dyn Read + Send>, content_length: u64) -> BodyReader {
+        BodyReader {
+            inner,
+            remaining: content_length,
+            continue_stream: None,
+        }
+    }
+
+    pub(crate) fn from_bytes(body: Vec<u8>) -> BodyReader {
+        let length = body.len() as u64;
+        BodyReader::new(Box::new(Cursor::new(body)), length)
+    }
+
+    /// Sends `100 Continue` on `stream` before the body is first read
+    ///
+    /// The client holds back the body until then, so a request that is rejected
+    /// before its body is read never has to upload it.
+    pub(crate) fn expect_continue(&mut self, stream: TcpStream) {
+        if self.remaining > 0 {
+            self.continue_stream = Some(stream);
+        }
+    }
+
+    /// Whether the client is still waiting for `100 Continue`
+    pub fn is_awaiting_continue(&self) -> bool {
+        self.continue_stream.is_some()
+    }
+
+    /// Reads and discards the rest of the body
+    ///
+    /// Does nothing if the client was never told to send it.
+    pub(crate) fn drain(&mut self) -> std::io::Result<u64> {
+        if self.is_awaiting_continue() {
+            return Ok(0);
+        }
+        std::io::copy(self, &mut sink())
+    }
+
+    /// Hands over the rest of the connection, for a protocol that takes it over after an upgrade
+    pub(crate) fn take_upgraded(&mut self) -> Box<dyn Read + Send> {
+        let _ = self.drain();
+        self.continue_stream = None;
+        self.remaining = 0;
+        std::mem::replace(&mut self.inner, Box::new(std::io::empty()))
+    }
+}
+
+/// The body length from every `Content-Length` header of a request
+///
+/// `None` when a value is not a number, or values differ; repeats of the same value, as
+/// separate headers or a list, are allowed. No headers means no body.
+pub(crate) fn parse_content_length(values: &[&str]) -> Option<u64> {
+    let mut length = None;
+    for value in values.iter().flat_map(|value| value.split(',')).map(str::trim) {
+        if value.is_empty() || !value.bytes().all(|byte| byte.is_ascii_digit()) {
+            return None;
+        }
+        let value = value.parse::<u64>().ok()?;
+        if length.is_some_and(|length| length != value) {
+            return None;
+        }
+        length = Some(value);
+    }
+    Some(length.unwrap_or(0))
+}
+
+impl Read for BodyReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.remaining == 0 || buf.is_empty() {
+            return Ok(0);
+        }
+        if let Some(mut stream) = self.continue_stream.take() {
+            stream.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+            stream.flush()?;
+        }
+        let max = buf.len().min(self.remaining.min(usize::MAX as u64) as usize);
+        let read = self.inner.read(&mut buf[..max])?;
+        if read == 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "body ended before Content-Length"));
+        }
+        self.remaining -= read as u64;
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufReader, ErrorKind};
+    use std::time::Duration;
+    use crate::packcake::Request;
+    use crate::packcake::connection::ConnectionInfo;
+    use crate::packcake::testing::connection;
+
+    /// A request read from a connection after the client sent `head`, and the client's end
+    fn read_request(head: &str) -> (Request, TcpStream) {
+        let (server, mut client) = connection();
+        client.write_all(head.as_bytes()).unwrap();
+        client.set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+        let reader = BufReader::new(server.try_clone().unwrap());
+        (Request::from_stream(&server, reader, ConnectionInfo::default()).unwrap(), client)
+    }
+
+    /// Whatever the server has sent the client so far
+    fn received(client: &mut TcpStream) -> String {
+        let mut received = vec![0; 64];
+        match client.read(&mut received) {
+            Ok(read) => String::from_utf8_lossy(&received[..read]).into_owned(),
+            Err(error) if matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => String::new(),
+            Err(error) => panic!("{error}"),
+        }
+    }
+
+    #[test]
+    fn parses_content_length() {
+        assert_eq!(parse_content_length(&[]), Some(0));
+        assert_eq!(parse_content_length(&["42"]), Some(42));
+        assert_eq!(parse_content_length(&["42", "42"]), Some(42));
+        assert_eq!(parse_content_length(&["42, 42"]), Some(42));
+    }
+
+    #[test]
+    fn rejects_invalid_content_length() {
+        for value in ["", "abc", "-1", "+1", "1.5", "0x10", "99999999999999999999999"] {
+            assert_eq!(parse_content_length(&[value]), None, "{value:?}");
+        }
+        assert_eq!(parse_content_length(&["42", "43"]), None);
+        assert_eq!(parse_content_length(&["42, 43"]), None);
+    }
+
+    #[test]
+    fn reads_up_to_content_length() {
+        let mut reader = BodyReader::new(Box::new(Cursor::new(b"hello world".to_vec())), 5);
+        let mut body = String::new();
+        reader.read_to_string(&mut body).unwrap();
+        assert_eq!(body, "hello");
+        assert_eq!(reader.remaining, 0);
+    }
+
+    #[test]
+    fn fails_on_short_body() {
+        let mut reader = BodyReader::new(Box::new(Cursor::new(b"abc".to_vec())), 5);
+        let error = reader.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn sends_continue_only_when_the_body_is_read() {
+        let (request, mut client) = read_request("POST / HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 5\r\n\r\n");
+        assert!(request.body_reader().is_awaiting_continue());
+        assert_eq!(received(&mut client), "");
+        client.write_all(b"hello").unwrap();
+        assert_eq!(request.get_body(), "hello");
+        assert_eq!(received(&mut client), "HTTP/1.1 100 Continue\r\n\r\n");
+    }
+
+    #[test]
+    fn leaves_a_held_back_body_alone() {
+        let (request, mut client) = read_request("POST / HTTP/1.1\r\nExpect: 100-Continue\r\nContent-Length: 5\r\n\r\n");
+        request.discard_body();
+        assert_eq!(received(&mut client), "");
+        assert!(request.body_reader().is_awaiting_continue());
+    }
+
+    #[test]
+    fn does_not_send_continue_without_a_body_or_to_http_1_0() {
+        let (empty, _client) = read_request("POST / HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 0\r\n\r\n");
+        assert!(!empty.body_reader().is_awaiting_continue());
+        let (old, _client) = read_request("POST / HTTP/1.0\r\nExpect: 100-continue\r\nContent-Length: 5\r\n\r\n");
+        assert!(!old.body_reader().is_awaiting_continue());
+    }
+
+    #[test]
+    fn fails_unknown_expectations() {
+        let (known, _client) = read_request("POST / HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 5\r\n\r\n");
+        assert!(!known.has_unmet_expectation());
+        let (unknown, _client) = read_request("POST / HTTP/1.1\r\nExpect: 200-ok\r\nContent-Length: 5\r\n\r\n");
+        assert!(unknown.has_unmet_expectation());
+        assert!(!unknown.body_reader().is_awaiting_continue());
+        let (none, _client) = read_request("GET / HTTP/1.1\r\n\r\n");
+        assert!(!none.has_unmet_expectation());
+    }
+}
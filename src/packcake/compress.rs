@@ -0,0 +1,275 @@
+use crate::packcake::deflate::{adler32, crc32, Deflater};
+use crate::packcake::{mime, Response, StatusCode};
+
+/// A content coding the server can compress responses with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Gzip,
+    /// DEFLATE data in a zlib wrapper, which is what `deflate` means in HTTP
+    Deflate,
+}
+
+impl Encoding {
+    /// The name used in `Accept-Encoding` and `Content-Encoding`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+}
+
+/// Settings for compressing responses, set with `Packcake::compression`
+///
+/// Text bodies (HTML, CSS, JavaScript, JSON, XML, SVG, ...) of at least `threshold` bytes are
+/// compressed with gzip or deflate when the client accepts one of them. Smaller bodies are
+/// sent as they are, since compressing them saves little.
+#[derive(Clone, Debug)]
+pub struct Compression {
+    threshold: usize,
+}
+
+impl Compression {
+    /// Compresses text bodies of 1 KiB or more
+    pub fn new() -> Compression {
+        Compression { threshold: 1024 }
+    }
+
+    #[allow(dead_code)]
+    /// Only compresses bodies of at least this many bytes
+    pub fn threshold(mut self, bytes: usize) -> Compression {
+        self.threshold = bytes;
+        self
+    }
+}
+
+impl Default for Compression {
+    fn default() -> Compression {
+        Compression::new()
+    }
+}
+
+/// Decides whether to compress the response, with a body of `length` bytes if known
+///
+/// When it does, sets `Content-Encoding`, turns a strong `ETag` weak (the bytes differ from the
+/// uncompressed ones) and drops `Accept-Ranges`, since ranges are only served uncompressed.
+/// `Vary: Accept-Encoding` is added whenever the answer depends on the request's `Accept-Encoding`.
+pub(crate) fn prepare(response: &mut Response, length: Option<usize>) -> Option<Encoding> {
+    let compression = response.compression.clone()?;
+    if response.is_bodiless()
+        || matches!(response.status, StatusCode::PartialContent | StatusCode::RangeNotSatisfiable)
+        || response.get_header("Content-Encoding").is_some()
+        || !response.get_header("Content-Type").is_some_and(|media_type| mime::is_text(media_type))
+        || length.is_some_and(|length| length < compression.threshold) {
+        return None;
+    }
+    vary(response, "Accept-Encoding");
+    let encoding = negotiate(response.accept_encoding.as_deref()?)?;
+    response.header("Content-Encoding", encoding.name());
+    response.remove_header("Accept-Ranges");
+    if let Some(etag) = response.get_header("ETag").filter(|etag| !etag.starts_with("W/")) {
+        let weak = format!("W/{etag}");
+        response.header("ETag", &weak);
+    }
+    Some(encoding)
+}
+
+/// Adds `header` to the `Vary` header, unless it is listed already
+pub(crate) fn vary(response: &mut Response, header: &str) {
+    match response.get_header("Vary") {
+        Some(vary) if vary.split(',').any(|h| h.trim() == "*" || h.trim().eq_ignore_ascii_case(header)) => {}
+        Some(vary) => {
+            let vary = format!("{vary}, {header}");
+            response.header("Vary", &vary);
+        }
+        None => response.header("Vary", header),
+    }
+}
+
+/// Picks the encoding the client prefers from an `Accept-Encoding` header; gzip wins ties
+pub(crate) fn negotiate(accept_encoding: &str) -> Option<Encoding> {
+    let gzip = quality(accept_encoding, Encoding::Gzip.name());
+    let deflate = quality(accept_encoding, Encoding::Deflate.name());
+    if gzip > 0.0 && gzip >= deflate {
+        Some(Encoding::Gzip)
+    } else if deflate > 0.0 {
+        Some(Encoding::Deflate)
+    } else {
+        None
+    }
+}
+
+/// The q-value an `Accept-Encoding` header gives `coding`, 0 when the coding is refused
+///
+/// `*` stands for any coding not listed. A malformed q-value counts as a refusal.
+pub(crate) fn quality(accept_encoding: &str, coding: &str) -> f32 {
+    let mut wildcard = 0.0;
+    for item in accept_encoding.split(',') {
+        let mut parts = item.split(';');
+        let name = parts.next().unwrap_or("").trim();
+        let quality = parts
+            .filter_map(|param| param.split_once('='))
+            .find(|(key, _)| key.trim().eq_ignore_ascii_case("q"))
+            .map_or(Some(1.0), |(_, q)| q.trim().parse::<f32>().ok().filter(|q| (0.0..=1.0).contains(q)))
+            .unwrap_or(0.0);
+        if name.eq_ignore_ascii_case(coding) || (coding == "gzip" && name.eq_ignore_ascii_case("x-gzip")) {
+            return quality;
+        }
+        if name == "*" {
+            wildcard = quality;
+        }
+    }
+    wildcard
+}
+
+/// Compresses a body as it is written, with the header and trailer of its encoding
+pub(crate) struct Encoder {
+    encoding: Encoding,
+    deflater: Deflater,
+    /// CRC-32 for gzip, Adler-32 for deflate
+    check: u32,
+    size: u32,
+    output: Vec<u8>,
+}
+
+impl Encoder {
+    pub(crate) fn new(encoding: Encoding) -> Encoder {
+        let (check, output) = match encoding {
+            // No name, no modification time, unknown OS
+            Encoding::Gzip => (0, vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff]),
+            // 32 KiB window, fastest compression level
+            Encoding::Deflate => (1, vec![0x78, 0x01]),
+        };
+        Encoder {
+            encoding,
+            deflater: Deflater::new(),
+            check,
+            size: 0,
+            output,
+        }
+    }
+
+    pub(crate) fn write(&mut self, data: &[u8]) {
+        self.check = match self.encoding {
+            Encoding::Gzip => crc32(self.check, data),
+            Encoding::Deflate => adler32(self.check, data),
+        };
+        self.size = self.size.wrapping_add(data.len() as u32);
+        self.deflater.write(data);
+    }
+
+    /// Makes everything written so far decompressable by the client
+    pub(crate) fn flush(&mut self) {
+        self.deflater.flush();
+    }
+
+    /// Ends the compressed data
+    pub(crate) fn finish(&mut self) {
+        self.deflater.finish();
+        let trailer = match self.encoding {
+            Encoding::Gzip => [self.check.to_le_bytes(), self.size.to_le_bytes()].concat(),
+            Encoding::Deflate => self.check.to_be_bytes().to_vec(),
+        };
+        self.output.extend(self.deflater.take_output());
+        self.output.extend(trailer);
+    }
+
+    /// Takes the compressed bytes produced so far
+    pub(crate) fn take_output(&mut self) -> Vec<u8> {
+        let mut output = std::mem::take(&mut self.output);
+        output.extend(self.deflater.take_output());
+        output
+    }
+}
+
+/// Compresses a whole body
+pub(crate) fn encode(encoding: Encoding, body: &[u8]) -> Vec<u8> {
+    let mut encoder = Encoder::new(encoding);
+    encoder.write(body);
+    encoder.finish();
+    encoder.take_output()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_gzip() {
+        assert_eq!(encode(Encoding::Gzip, b""), [
+            0x1F, 0x8B, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF,
+            0x03, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ]);
+        // Then the CRC-32 and the size, little-endian
+        assert_eq!(encode(Encoding::Gzip, b"a"), [
+            0x1F, 0x8B, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF,
+            0x4B, 0x04, 0x00,
+            0x43, 0xBE, 0xB7, 0xE8, 0x01, 0x00, 0x00, 0x00,
+        ]);
+    }
+
+    #[test]
+    fn wraps_deflate_in_zlib() {
+        assert_eq!(encode(Encoding::Deflate, b""), [0x78, 0x01, 0x03, 0x00, 0x00, 0x00, 0x00, 0x01]);
+        // Then the Adler-32, big-endian
+        assert_eq!(encode(Encoding::Deflate, b"a"), [0x78, 0x01, 0x4B, 0x04, 0x00, 0x00, 0x62, 0x00, 0x62]);
+        assert_eq!(encode(Encoding::Deflate, b"aaaaaaaaaa"), [0x78, 0x01, 0x4B, 0x84, 0x03, 0x00, 0x14, 0xE1, 0x03, 0xCB]);
+    }
+
+    #[test]
+    fn streams_with_flushes() {
+        let mut encoder = Encoder::new(Encoding::Deflate);
+        encoder.write(b"hi");
+        encoder.flush();
+        assert_eq!(encoder.take_output(), [0x78, 0x01, 0xCA, 0xC8, 0x04, 0x00, 0x00, 0x00, 0xFF, 0xFF]);
+        encoder.write(b"hi");
+        encoder.finish();
+        assert_eq!(encoder.take_output(), [0xCB, 0xC8, 0x04, 0x00, 0x04, 0x18, 0x01, 0xA3]);
+
+        let mut encoder = Encoder::new(Encoding::Gzip);
+        encoder.write(b"hel");
+        encoder.write(b"lo");
+        encoder.finish();
+        let output = encoder.take_output();
+        // The checksum and size cover both writes
+        assert!(output.ends_with(&[0x86, 0xA6, 0x10, 0x36, 0x05, 0x00, 0x00, 0x00]));
+        assert_eq!(output, encode(Encoding::Gzip, b"hello"));
+    }
+
+    #[test]
+    fn reads_q_values() {
+        assert_eq!(quality("gzip", "gzip"), 1.0);
+        assert_eq!(quality("gzip;q=0.5", "gzip"), 0.5);
+        assert_eq!(quality("GZIP ; Q=0.25", "gzip"), 0.25);
+        assert_eq!(quality("x-gzip;q=0.7", "gzip"), 0.7);
+        assert_eq!(quality("x-gzip", "deflate"), 0.0);
+        assert_eq!(quality("deflate", "gzip"), 0.0);
+        assert_eq!(quality("", "gzip"), 0.0);
+        // `*` covers codings that are not listed
+        assert_eq!(quality("*;q=0.3", "gzip"), 0.3);
+        assert_eq!(quality("gzip;q=0.8, *;q=0.1", "gzip"), 0.8);
+        assert_eq!(quality("*, gzip;q=0", "gzip"), 0.0);
+        // Malformed or out of range q-values refuse the coding
+        assert_eq!(quality("gzip;q=high", "gzip"), 0.0);
+        assert_eq!(quality("gzip;q=1.5", "gzip"), 0.0);
+        assert_eq!(quality("gzip;q=-1", "gzip"), 0.0);
+        assert_eq!(quality("gzip;q=", "gzip"), 0.0);
+    }
+
+    #[test]
+    fn negotiates_encodings() {
+        assert_eq!(negotiate("gzip, deflate, br"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("deflate, gzip"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("deflate"), Some(Encoding::Deflate));
+        assert_eq!(negotiate("gzip;q=0.5, deflate"), Some(Encoding::Deflate));
+        assert_eq!(negotiate("gzip;q=0, deflate;q=0.1"), Some(Encoding::Deflate));
+        assert_eq!(negotiate("x-gzip"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("*"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("*;q=0.5, gzip;q=0"), Some(Encoding::Deflate));
+        assert_eq!(negotiate("gzip;q=oops, deflate;q=0.2"), Some(Encoding::Deflate));
+        assert_eq!(negotiate("br, identity"), None);
+        assert_eq!(negotiate("*;q=0"), None);
+        assert_eq!(negotiate(""), None);
+    }
+}
@@ -0,0 +1,116 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpStream};
+use std::time::SystemTime;
+
+/// Details about the connection a request arrived on
+#[derive(Clone, Debug)]
+pub struct ConnectionInfo {
+    id: u64,
+    peer_addr: SocketAddr,
+    local_addr: SocketAddr,
+    client_addr: SocketAddr,
+    proxied: bool,
+    accepted_at: SystemTime,
+}
+
+impl Default for ConnectionInfo {
+    fn default() -> ConnectionInfo {
+        let unspecified = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0);
+        ConnectionInfo {
+            id: 0,
+            peer_addr: unspecified,
+            local_addr: unspecified,
+            client_addr: unspecified,
+            proxied: false,
+            accepted_at: SystemTime::now(),
+        }
+    }
+}
+
+impl ConnectionInfo {
+    pub(crate) fn from_stream(stream: &TcpStream, id: u64) -> ConnectionInfo {
+        let default = ConnectionInfo::default();
+        let peer_addr = stream.peer_addr().unwrap_or(default.peer_addr);
+        ConnectionInfo {
+            id,
+            peer_addr,
+            local_addr: stream.local_addr().unwrap_or(default.local_addr),
+            client_addr: peer_addr,
+            proxied: false,
+            accepted_at: default.accepted_at,
+        }
+    }
+
+    /// Records the source address of a PROXY protocol header
+    pub(crate) fn set_proxied_source(&mut self, source: SocketAddr) {
+        self.client_addr = source;
+        self.proxied = true;
+    }
+
+    /// The sequence number of the connection, counting from 1 since the server started
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// The address of the other end of the TCP connection
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer_addr
+    }
+
+    /// The address of the client; the source of the PROXY protocol header if there was one,
+    /// otherwise the peer address
+    pub fn client_addr(&self) -> SocketAddr {
+        self.client_addr
+    }
+
+    /// Whether the connection started with a PROXY protocol header carrying the client address
+    pub fn is_proxied(&self) -> bool {
+        self.proxied
+    }
+
+    /// The address the connection was accepted on
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn accepted_at(&self) -> SystemTime {
+        self.accepted_at
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packcake::testing::connection;
+
+    #[test]
+    fn reads_addresses_from_the_stream() {
+        let (server, client) = connection();
+        let info = ConnectionInfo::from_stream(&server, 7);
+        assert_eq!(info.id(), 7);
+        assert_eq!(info.peer_addr(), client.local_addr().unwrap());
+        assert_eq!(info.local_addr(), server.local_addr().unwrap());
+        assert_eq!(info.client_addr(), info.peer_addr());
+        assert!(!info.is_proxied());
+        assert!(info.accepted_at() <= SystemTime::now());
+    }
+
+    #[test]
+    fn uses_the_proxied_source_as_the_client() {
+        let (server, _client) = connection();
+        let mut info = ConnectionInfo::from_stream(&server, 1);
+        let source: SocketAddr = "203.0.113.9:40000".parse().unwrap();
+        info.set_proxied_source(source);
+        assert!(info.is_proxied());
+        assert_eq!(info.client_addr(), source);
+        assert_eq!(info.peer_addr(), server.peer_addr().unwrap());
+    }
+
+    #[test]
+    fn defaults_to_unspecified_addresses() {
+        let info = ConnectionInfo::default();
+        assert_eq!(info.id(), 0);
+        assert!(info.peer_addr().ip().is_unspecified());
+        assert!(info.client_addr().ip().is_unspecified());
+        assert!(!info.is_proxied());
+    }
+}
@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::packcake::date::http_date;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    None,
+}
+
+/// Why a cookie could not be built
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CookieError {
+    /// The name is empty or not an RFC 6265 token
+    Name(String),
+    /// The value has characters a cookie value can't hold, like `;`, `,`, spaces or control characters
+    Value(String),
+    /// The value of an attribute like `Path` or `Domain` has control characters or `;`
+    Attribute(&'static str, String),
+}
+
+impl Display for CookieError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CookieError::Name(name) => write!(f, "Invalid cookie name {name:?}"),
+            CookieError::Value(value) => write!(f, "Invalid cookie value {value:?}"),
+            CookieError::Attribute(attribute, value) => write!(f, "Invalid cookie {attribute} {value:?}"),
+        }
+    }
+}
+
+/// A cookie to send with `Response::cookie`, rendered as a `Set-Cookie` header
+///
+/// Names, values, paths and domains are checked against RFC 6265 when they are set, so they
+/// can't add attributes or headers of their own.
+///
+/// ```ignore
+/// response.cookie(Cookie::new("session", "abc123")?.path("/")?.http_only().secure());
+/// ```
+#[derive(Clone, Debug)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    path: Option<String>,
+    domain: Option<String>,
+    max_age: Option<i64>,
+    expires: Option<SystemTime>,
+    secure: bool,
+    http_only: bool,
+    same_site: Option<SameSite>,
+}
+
+impl Cookie {
+    /// A cookie named `name`; values that need other characters have to be encoded first, like with base64
+    pub fn new(name: &str, value: &str) -> Result<Cookie, CookieError> {
+        if !is_token(name) {
+            return Err(CookieError::Name(name.to_string()));
+        }
+        if !is_cookie_value(value) {
+            return Err(CookieError::Value(value.to_string()));
+        }
+        Ok(Cookie {
+            name: name.to_string(),
+            value: value.to_string(),
+            path: None,
+            domain: None,
+            max_age: None,
+            expires: None,
+            secure: false,
+            http_only: false,
+            same_site: None,
+        })
+    }
+
+    /// A cookie that tells the client to remove `name`
+    pub fn removal(name: &str) -> Result<Cookie, CookieError> {
+        Ok(Cookie::new(name, "")?.max_age(0).expires(UNIX_EPOCH))
+    }
+
+    pub fn path(mut self, path: &str) -> Result<Cookie, CookieError> {
+        if !is_attribute_value(path) {
+            return Err(CookieError::Attribute("Path", path.to_string()));
+        }
+        self.path = Some(path.to_string());
+        Ok(self)
+    }
+
+    pub fn domain(mut self, domain: &str) -> Result<Cookie, CookieError> {
+        if domain.is_empty() || !is_attribute_value(domain) {
+            return Err(CookieError::Attribute("Domain", domain.to_string()));
+        }
+        self.domain = Some(domain.to_string());
+        Ok(self)
+    }
+
+    /// Seconds until the cookie expires
+    pub fn max_age(mut self, seconds: i64) -> Cookie {
+        self.max_age = Some(seconds);
+        self
+    }
+
+    pub fn expires(mut self, time: SystemTime) -> Cookie {
+        self.expires = Some(time);
+        self
+    }
+
+    pub fn secure(mut self) -> Cookie {
+        self.secure = true;
+        self
+    }
+
+    pub fn http_only(mut self) -> Cookie {
+        self.http_only = true;
+        self
+    }
+
+    pub fn same_site(mut self, same_site: SameSite) -> Cookie {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_value(&self) -> &str {
+        &self.value
+    }
+}
+
+impl Display for Cookie {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}={}", self.name, self.value)?;
+        if let Some(path) = &self.path {
+            write!(f, "; Path={path}")?;
+        }
+        if let Some(domain) = &self.domain {
+            write!(f, "; Domain={domain}")?;
+        }
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={max_age}")?;
+        }
+        if let Some(expires) = self.expires {
+            write!(f, "; Expires={}", http_date(expires))?;
+        }
+        if self.secure {
+            write!(f, "; Secure")?;
+        }
+        if self.http_only {
+            write!(f, "; HttpOnly")?;
+        }
+        match self.same_site {
+            Some(SameSite::Strict) => write!(f, "; SameSite=Strict"),
+            Some(SameSite::Lax) => write!(f, "; SameSite=Lax"),
+            Some(SameSite::None) => write!(f, "; SameSite=None"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A token: visible ASCII without separators
+pub(crate) fn is_token(name: &str) -> bool {
+    !name.is_empty() && name.bytes().all(|byte| byte.is_ascii_graphic() && !b"()<>@,;:\\\"/[]?={}".contains(&byte))
+}
+
+/// Cookie octets, optionally in double quotes: visible ASCII except `"`, `,`, `;` and `\`
+fn is_cookie_value(value: &str) -> bool {
+    let value = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')).unwrap_or(value);
+    value.bytes().all(|byte| byte.is_ascii_graphic() && !b"\",;\\".contains(&byte))
+}
+
+/// Any ASCII except control characters and `;`
+fn is_attribute_value(value: &str) -> bool {
+    value.bytes().all(|byte| (byte == b' ' || byte.is_ascii_graphic()) && byte != b';')
+}
+
+/// Parses a `Cookie` request header (`a=1; b="two"`) into its name-value pairs
+pub(crate) fn parse_cookie_header(header: &str) -> HashMap<String,String> {
+    let mut cookies = HashMap::new();
+    for pair in header.split(';') {
+        if let Some((name, value)) = pair.split_once('=') {
+            let name = name.trim();
+            if name.is_empty() {
+                continue;
+            }
+            let value = value.trim();
+            let value = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')).unwrap_or(value);
+            cookies.entry(name.to_string()).or_insert_with(|| value.to_string());
+        }
+    }
+    cookies
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_attributes() {
+        let cookie = Cookie::new("session", "abc123").unwrap()
+            .path("/").unwrap()
+            .domain("example.com").unwrap()
+            .max_age(3600)
+            .secure()
+            .http_only()
+            .same_site(SameSite::Lax);
+        assert_eq!(cookie.to_string(), "session=abc123; Path=/; Domain=example.com; Max-Age=3600; Secure; HttpOnly; SameSite=Lax");
+        assert_eq!(Cookie::removal("old").unwrap().to_string(), "old=; Max-Age=0; Expires=Thu, 01 Jan 1970 00:00:00 GMT");
+    }
+
+    #[test]
+    fn accepts_quoted_values() {
+        assert_eq!(Cookie::new("a", "\"b\"").unwrap().to_string(), "a=\"b\"");
+    }
+
+    #[test]
+    fn rejects_invalid_names() {
+        for name in ["", "a b", "a=b", "a;b", "a\r\nb", "\"a\"", "a\u{e9}"] {
+            assert_eq!(Cookie::new(name, "v").unwrap_err(), CookieError::Name(name.to_string()));
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        for value in ["a; Domain=evil.com", "a\r\nSet-Cookie: x=y", "a b", "a,b", "a\\b", "a\"b", "\u{e9}", "a\tb"] {
+            assert_eq!(Cookie::new("name", value).unwrap_err(), CookieError::Value(value.to_string()));
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_attributes() {
+        let cookie = Cookie::new("name", "value").unwrap();
+        assert!(matches!(cookie.clone().path("/; Domain=evil.com"), Err(CookieError::Attribute("Path", _))));
+        assert!(matches!(cookie.clone().path("/\r\nX-Evil: 1"), Err(CookieError::Attribute("Path", _))));
+        assert!(matches!(cookie.clone().domain(""), Err(CookieError::Attribute("Domain", _))));
+        assert!(matches!(cookie.domain("a.com\n"), Err(CookieError::Attribute("Domain", _))));
+    }
+
+    #[test]
+    fn parses_cookie_header() {
+        let cookies = parse_cookie_header("a=1; b=\"two\"; =skipped; a=3");
+        assert_eq!(cookies.get("a").map(String::as_str), Some("1"));
+        assert_eq!(cookies.get("b").map(String::as_str), Some("two"));
+        assert_eq!(cookies.len(), 2);
+    }
+}
@@ -0,0 +1,108 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+/// Formats a time as an HTTP date (IMF-fixdate), e.g. `Sun, 06 Nov 1994 08:49:37 GMT`
+pub fn http_date(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO).as_secs();
+    let days = secs / 86400;
+    let secs_of_day = secs % 86400;
+    let (year, month, day) = civil_from_days(days as i64);
+    format!("{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+            DAYS[(days % 7) as usize], day, MONTHS[(month - 1) as usize], year,
+            secs_of_day / 3600, secs_of_day / 60 % 60, secs_of_day % 60)
+}
+
+/// Converts days since 1970-01-01 to a (year, month, day) date
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Converts a (year, month, day) date to days since 1970-01-01
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Parses an HTTP date, in any of the three formats HTTP/1.1 allows:
+///
+/// * `Sun, 06 Nov 1994 08:49:37 GMT` (IMF-fixdate)
+/// * `Sunday, 06-Nov-94 08:49:37 GMT` (RFC 850)
+/// * `Sun Nov  6 08:49:37 1994` (asctime)
+pub fn parse_http_date(date: &str) -> Option<SystemTime> {
+    let parts: Vec<&str> = date.split_whitespace().collect();
+    let (day, month, year, time) = match parts.as_slice() {
+        [_, day, month, year, time, "GMT"] => (*day, *month, year.parse::<i64>().ok()?, *time),
+        [_, date, time, "GMT"] => {
+            let mut date = date.split('-');
+            let (day, month, year) = (date.next()?, date.next()?, date.next()?.parse::<i64>().ok()?);
+            // Two digit years are read as the nearest past or future year, as RFC 9110 says
+            let year = if year < 70 { 2000 + year } else if year < 100 { 1900 + year } else { year };
+            (day, month, year, *time)
+        }
+        [_, month, day, time, year] => (*day, *month, year.parse::<i64>().ok()?, *time),
+        _ => return None,
+    };
+    let day = day.parse::<u32>().ok().filter(|day| (1..=31).contains(day))?;
+    let month = MONTHS.iter().position(|m| *m == month)? as u32 + 1;
+    let mut time = time.split(':').map(|part| part.parse::<u64>().ok());
+    let (hour, minute, second) = (time.next()??, time.next()??, time.next()??);
+    if time.next().is_some() || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    let days = days_from_civil(year, month, day);
+    if days < 0 {
+        return None;
+    }
+    let secs = days as u64 * 86400 + hour * 3600 + minute * 60 + second;
+    Some(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_http_dates() {
+        assert_eq!(http_date(UNIX_EPOCH), "Thu, 01 Jan 1970 00:00:00 GMT");
+        assert_eq!(http_date(UNIX_EPOCH + Duration::from_secs(784111777)), "Sun, 06 Nov 1994 08:49:37 GMT");
+        // A leap day
+        assert_eq!(http_date(UNIX_EPOCH + Duration::from_secs(951782400)), "Tue, 29 Feb 2000 00:00:00 GMT");
+        assert_eq!(http_date(UNIX_EPOCH + Duration::from_secs(4102444799)), "Thu, 31 Dec 2099 23:59:59 GMT");
+    }
+
+    #[test]
+    fn parses_all_three_formats() {
+        let date = Some(UNIX_EPOCH + Duration::from_secs(784111777));
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), date);
+        assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), date);
+        assert_eq!(parse_http_date("Sun Nov  6 08:49:37 1994"), date);
+        assert_eq!(parse_http_date("Thu, 01-Jan-30 00:00:00 GMT"), Some(UNIX_EPOCH + Duration::from_secs(1893456000)));
+        let now = UNIX_EPOCH + Duration::from_secs(1700000000);
+        assert_eq!(parse_http_date(&http_date(now)), Some(now));
+    }
+
+    #[test]
+    fn rejects_invalid_dates() {
+        for date in ["", "yesterday", "Sun, 06 Nov 1994 08:49:37 UTC", "Sun, 32 Nov 1994 08:49:37 GMT",
+                     "Sun, 06 Foo 1994 08:49:37 GMT", "Sun, 06 Nov 1994 24:00:00 GMT", "Sun, 06 Nov 1994 08:49 GMT",
+                     "Sun, 06 Nov 1994 08:49:37:00 GMT", "Sun, 06 Nov 1969 08:49:37 GMT"] {
+            assert_eq!(parse_http_date(date), None, "{date:?}");
+        }
+    }
+}
@@ -0,0 +1,340 @@
+/// How far back a match may reach
+const WINDOW: usize = 32 * 1024;
+/// Input is compressed in blocks of this many bytes, or less when flushed
+const BLOCK: usize = 64 * 1024;
+const HASH_BITS: u32 = 15;
+const NONE: u32 = u32::MAX;
+/// How many earlier positions with the same hash are tried; more compresses better but slower
+const MAX_CHAIN: usize = 64;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097,
+    6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+/// A DEFLATE (RFC 1951) encoder that compresses as data comes in
+///
+/// Matches are found with hash chains over a 32 KiB window, which carries over between blocks, and
+/// written with the fixed Huffman codes. That is simpler than building codes for every block, and
+/// still shrinks text like JSON and HTML several times over.
+pub(crate) struct Deflater {
+    /// Up to a window of data already compressed, followed by the data that is not yet
+    data: Vec<u8>,
+    pending: usize,
+    bits: BitWriter,
+    finished: bool,
+}
+
+impl Deflater {
+    pub(crate) fn new() -> Deflater {
+        Deflater {
+            data: Vec::new(),
+            pending: 0,
+            bits: BitWriter::default(),
+            finished: false,
+        }
+    }
+
+    pub(crate) fn write(&mut self, mut input: &[u8]) {
+        while !input.is_empty() {
+            let room = BLOCK - (self.data.len() - self.pending);
+            let (now, later) = input.split_at(room.min(input.len()));
+            self.data.extend_from_slice(now);
+            if self.data.len() - self.pending == BLOCK {
+                self.compress(false);
+            }
+            input = later;
+        }
+    }
+
+    /// Compresses everything written so far and aligns the output to a byte, so the client
+    /// can decompress all of it (a sync flush)
+    pub(crate) fn flush(&mut self) {
+        if self.pending < self.data.len() {
+            self.compress(false);
+        }
+        // An empty stored block
+        self.bits.write(0, 3);
+        self.bits.align();
+        self.bits.bytes.extend_from_slice(&[0x00, 0x00, 0xFF, 0xFF]);
+    }
+
+    /// Compresses the rest and ends the stream
+    pub(crate) fn finish(&mut self) {
+        if !self.finished {
+            self.finished = true;
+            self.compress(true);
+            self.bits.align();
+        }
+    }
+
+    /// Takes the compressed bytes that are complete
+    pub(crate) fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.bits.bytes)
+    }
+
+    /// Compresses the pending data into one block
+    fn compress(&mut self, last: bool) {
+        let (start, end) = (self.pending, self.data.len());
+        let history = start.saturating_sub(WINDOW);
+        let data = &self.data;
+        let mut chains = Chains::new(history, end);
+        for i in history..start {
+            chains.insert(data, i);
+        }
+
+        self.bits.write(last as u32, 1);
+        self.bits.write(1, 2);
+        let mut i = start;
+        while i < end {
+            let (length, distance) = chains.longest_match(data, i);
+            if length >= MIN_MATCH {
+                self.bits.write_length(length);
+                self.bits.write_distance(distance);
+                for j in i..i + length {
+                    chains.insert(data, j);
+                }
+                i += length;
+            } else {
+                self.bits.write_literal(data[i] as u16);
+                chains.insert(data, i);
+                i += 1;
+            }
+        }
+        self.bits.write_literal(256);
+
+        // Keep a window of history for the next block
+        self.pending = end;
+        if self.data.len() > WINDOW {
+            self.data.drain(..self.data.len() - WINDOW);
+            self.pending = self.data.len();
+        }
+    }
+}
+
+/// Earlier positions with the same three bytes, most recent first
+struct Chains {
+    /// The first position in `prev`
+    offset: usize,
+    end: usize,
+    head: Vec<u32>,
+    prev: Vec<u32>,
+}
+
+impl Chains {
+    fn new(offset: usize, end: usize) -> Chains {
+        Chains {
+            offset,
+            end,
+            head: vec![NONE; 1 << HASH_BITS],
+            prev: vec![NONE; end - offset],
+        }
+    }
+
+    fn hash(data: &[u8], i: usize) -> usize {
+        let value = (data[i] as u32) << 16 | (data[i + 1] as u32) << 8 | data[i + 2] as u32;
+        (value.wrapping_mul(0x9E3779B1) >> (32 - HASH_BITS)) as usize
+    }
+
+    fn insert(&mut self, data: &[u8], i: usize) {
+        if i + MIN_MATCH <= self.end {
+            let hash = Chains::hash(data, i);
+            self.prev[i - self.offset] = self.head[hash];
+            self.head[hash] = i as u32;
+        }
+    }
+
+    /// The length and distance of the longest earlier match for the data at `i`
+    fn longest_match(&self, data: &[u8], i: usize) -> (usize, usize) {
+        let (mut best_length, mut best_distance) = (0, 0);
+        if i + MIN_MATCH > self.end {
+            return (0, 0);
+        }
+        let max_length = MAX_MATCH.min(self.end - i);
+        let mut candidate = self.head[Chains::hash(data, i)];
+        let mut chain = 0;
+        while candidate != NONE && chain < MAX_CHAIN {
+            let c = candidate as usize;
+            if i - c > WINDOW {
+                break;
+            }
+            let length = data[c..].iter().zip(&data[i..i + max_length]).take_while(|(a, b)| a == b).count();
+            if length > best_length {
+                (best_length, best_distance) = (length, i - c);
+                if length == max_length {
+                    break;
+                }
+            }
+            candidate = self.prev[c - self.offset];
+            chain += 1;
+        }
+        (best_length, best_distance)
+    }
+}
+
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    /// Writes the low `count` bits of `value`, least significant first
+    fn write(&mut self, value: u32, count: u32) {
+        self.buffer |= (value as u64) << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Writes a Huffman code, which goes most significant bit first
+    fn write_code(&mut self, code: u32, length: u32) {
+        self.write(code.reverse_bits() >> (32 - length), length);
+    }
+
+    /// Writes a literal byte, or 256 for the end of a block, with the fixed codes
+    fn write_literal(&mut self, symbol: u16) {
+        let symbol = symbol as u32;
+        match symbol {
+            0..=143 => self.write_code(0x30 + symbol, 8),
+            144..=255 => self.write_code(0x190 + symbol - 144, 9),
+            256..=279 => self.write_code(symbol - 256, 7),
+            _ => self.write_code(0xC0 + symbol - 280, 8),
+        }
+    }
+
+    fn write_length(&mut self, length: usize) {
+        let code = LENGTH_BASE.iter().rposition(|base| *base as usize <= length).unwrap_or(0);
+        self.write_literal(257 + code as u16);
+        self.write((length - LENGTH_BASE[code] as usize) as u32, LENGTH_EXTRA[code] as u32);
+    }
+
+    fn write_distance(&mut self, distance: usize) {
+        let code = DISTANCE_BASE.iter().rposition(|base| *base as usize <= distance).unwrap_or(0);
+        self.write_code(code as u32, 5);
+        self.write((distance - DISTANCE_BASE[code] as usize) as u32, DISTANCE_EXTRA[code] as u32);
+    }
+
+    fn align(&mut self) {
+        if self.count > 0 {
+            self.write(0, 8 - self.count);
+        }
+    }
+}
+
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { 0xEDB88320 ^ (crc >> 1) } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// CRC-32 as used by gzip, updated with more data
+pub(crate) fn crc32(crc: u32, data: &[u8]) -> u32 {
+    !data.iter().fold(!crc, |crc, byte| CRC_TABLE[((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8))
+}
+
+/// Adler-32 as used by zlib, updated with more data; starts at 1
+pub(crate) fn adler32(adler: u32, data: &[u8]) -> u32 {
+    let (mut a, mut b) = (adler & 0xFFFF, adler >> 16);
+    // Sums of 5552 bytes can't overflow before the modulo
+    for chunk in data.chunks(5552) {
+        for byte in chunk {
+            a += *byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    b << 16 | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deflate(input: &[u8]) -> Vec<u8> {
+        let mut deflater = Deflater::new();
+        deflater.write(input);
+        deflater.finish();
+        deflater.take_output()
+    }
+
+    #[test]
+    fn computes_crc32() {
+        assert_eq!(crc32(0, b""), 0);
+        assert_eq!(crc32(0, b"a"), 0xE8B7BE43);
+        assert_eq!(crc32(0, b"123456789"), 0xCBF43926);
+        assert_eq!(crc32(0, b"The quick brown fox jumps over the lazy dog"), 0x414FA339);
+        assert_eq!(crc32(crc32(0, b"12345"), b"6789"), 0xCBF43926);
+    }
+
+    #[test]
+    fn computes_adler32() {
+        assert_eq!(adler32(1, b""), 1);
+        assert_eq!(adler32(1, b"a"), 0x00620062);
+        assert_eq!(adler32(1, b"Wikipedia"), 0x11E60398);
+        assert_eq!(adler32(adler32(1, b"Wiki"), b"pedia"), 0x11E60398);
+        // Enough bytes for the sums to wrap several times
+        let data = vec![0xFF; 100_000];
+        let (a, b) = data.iter().fold((1u64, 0u64), |(a, b), byte| {
+            let a = (a + *byte as u64) % 65521;
+            (a, (b + a) % 65521)
+        });
+        assert_eq!(adler32(1, &data), (b << 16 | a) as u32);
+    }
+
+    #[test]
+    fn writes_fixed_huffman_blocks() {
+        // Checked against zlib's inflate
+        assert_eq!(deflate(b""), [0x03, 0x00]);
+        assert_eq!(deflate(b"a"), [0x4B, 0x04, 0x00]);
+        // A literal, then a match of 9 at distance 1
+        assert_eq!(deflate(b"aaaaaaaaaa"), [0x4B, 0x84, 0x03, 0x00]);
+        assert_eq!(deflate(b"abcabcabc"), [0x4B, 0x4C, 0x4A, 0x86, 0x20, 0x00]);
+        assert_eq!(deflate(b"hello hello hello"), [0xCB, 0x48, 0xCD, 0xC9, 0xC9, 0x57, 0x40, 0x22, 0x01]);
+    }
+
+    #[test]
+    fn flushes_to_a_byte_boundary() {
+        let mut deflater = Deflater::new();
+        deflater.write(b"hi");
+        deflater.flush();
+        assert_eq!(deflater.take_output(), [0xCA, 0xC8, 0x04, 0x00, 0x00, 0x00, 0xFF, 0xFF]);
+        // The next block can refer back to data before the flush
+        deflater.write(b"hi");
+        deflater.finish();
+        assert_eq!(deflater.take_output(), [0xCB, 0xC8, 0x04, 0x00]);
+        // Finishing twice adds nothing
+        deflater.finish();
+        assert!(deflater.take_output().is_empty());
+    }
+
+    #[test]
+    fn matches_across_blocks() {
+        let line = b"<li class=\"item\">packcake</li>\n";
+        let input = line.repeat(BLOCK / line.len() * 3);
+        let output = deflate(&input);
+        assert!(output.len() < input.len() / 20, "{} bytes", output.len());
+    }
+}
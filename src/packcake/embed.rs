@@ -0,0 +1,127 @@
+use std::fmt::{Debug, Formatter};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// One file as generated by the build helper: path, contents, `ETag`, media type and modification
+/// time in seconds since the epoch
+pub type EmbeddedEntry = (&'static str, &'static [u8], &'static str, &'static str, u64);
+
+/// A directory compiled into the binary by the build helper in `embed_build.rs`
+///
+/// ```ignore
+/// static ASSETS: EmbeddedDir = EmbeddedDir::new(include!(concat!(env!("OUT_DIR"), "/static.rs")));
+/// ```
+#[derive(Clone, Copy)]
+pub struct EmbeddedDir {
+    files: &'static [EmbeddedEntry],
+}
+
+impl EmbeddedDir {
+    pub const fn new(files: &'static [EmbeddedEntry]) -> EmbeddedDir {
+        EmbeddedDir { files }
+    }
+
+    /// Gets a file by its path in the directory, like `"css/site.css"`
+    pub fn get(&self, path: &str) -> Option<EmbeddedFile> {
+        let path = path.trim_start_matches('/');
+        self.files.iter().find(|entry| entry.0 == path).map(|entry| EmbeddedFile { entry })
+    }
+
+    #[allow(dead_code)]
+    pub fn files(&self) -> impl Iterator<Item = EmbeddedFile> {
+        self.files.iter().map(|entry| EmbeddedFile { entry })
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Whether `path` is a directory holding embedded files; `""` is the directory itself
+    pub(crate) fn is_dir(&self, path: &str) -> bool {
+        let path = path.trim_matches('/');
+        path.is_empty() || self.files.iter().any(|entry| entry.0.strip_prefix(path).is_some_and(|rest| rest.starts_with('/')))
+    }
+}
+
+impl Debug for EmbeddedDir {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "EmbeddedDir({} files)", self.files.len())
+    }
+}
+
+/// A file compiled into the binary
+#[derive(Clone, Copy)]
+pub struct EmbeddedFile {
+    entry: &'static EmbeddedEntry,
+}
+
+impl EmbeddedFile {
+    pub fn path(&self) -> &'static str {
+        self.entry.0
+    }
+
+    pub fn bytes(&self) -> &'static [u8] {
+        self.entry.1
+    }
+
+    /// The contents as text, if they are UTF-8
+    pub fn text(&self) -> Option<&'static str> {
+        std::str::from_utf8(self.entry.1).ok()
+    }
+
+    /// The `ETag`, computed from the contents when the binary was built
+    pub fn etag(&self) -> &'static str {
+        self.entry.2
+    }
+
+    pub fn media_type(&self) -> &'static str {
+        self.entry.3
+    }
+
+    /// When the file was last modified before it was embedded
+    pub fn modified(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.entry.4)
+    }
+}
+
+impl Debug for EmbeddedFile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "EmbeddedFile({}, {} bytes)", self.entry.0, self.entry.1.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packcake::testing::EMBEDDED as FILES;
+
+    #[test]
+    fn finds_files_by_path() {
+        let css = FILES.get("css/site.css").unwrap();
+        assert_eq!(css.path(), "css/site.css");
+        assert_eq!(css.text(), Some("body {}"));
+        assert_eq!(css.etag(), "\"a9b342a0ff2b8953\"");
+        assert_eq!(css.media_type(), "text/css");
+        assert_eq!(css.modified(), UNIX_EPOCH + Duration::from_secs(784111777));
+        assert_eq!(FILES.get("/index.html").map(|file| file.bytes()), Some(&b"<app>"[..]));
+        assert_eq!(FILES.get("logo.png").unwrap().text(), None);
+        assert!(FILES.get("css").is_none());
+        assert!(FILES.get("missing.txt").is_none());
+    }
+
+    #[test]
+    fn knows_its_directories() {
+        assert!(FILES.is_dir("") && FILES.is_dir("/"));
+        assert!(FILES.is_dir("css") && FILES.is_dir("/docs/"));
+        assert!(!FILES.is_dir("cs"));
+        assert!(!FILES.is_dir("index.html"));
+        assert_eq!(FILES.len(), 4);
+        assert!(!FILES.is_empty() && EmbeddedDir::new(&[]).is_empty());
+        assert_eq!(FILES.files().map(|file| file.path()).collect::<Vec<_>>(),
+                   ["css/site.css", "docs/index.html", "index.html", "logo.png"]);
+    }
+}
@@ -0,0 +1,117 @@
+//! Build script helper that compiles a directory into the binary
+//!
+//! It is not part of the server; include it, with the MIME registry it uses, from `build.rs`:
+//!
+//! ```ignore
+//! #[allow(dead_code)]
+//! #[path = "src/packcake/mime.rs"] mod mime;
+//! #[path = "src/packcake/embed_build.rs"] mod embed_build;
+//!
+//! fn main() {
+//!     embed_build::embed_dir("static", "static.rs");
+//! }
+//! ```
+//!
+//! and load the generated file in the crate:
+//!
+//! ```ignore
+//! static ASSETS: EmbeddedDir = EmbeddedDir::new(include!(concat!(env!("OUT_DIR"), "/static.rs")));
+//! ```
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use super::mime::mime_for_path;
+
+#[allow(dead_code)]
+/// Writes `$OUT_DIR/<output>`, a list of the files under `dir` for `EmbeddedDir::new`
+///
+/// Every file is included with `include_bytes!`, with an `ETag` hashed from its contents and
+/// its media type. Cargo reruns the build script when anything in `dir` changes.
+pub fn embed_dir(dir: impl AsRef<Path>, output: &str) {
+    let dir = dir.as_ref();
+    println!("cargo:rerun-if-changed={}", dir.display());
+    let root = dir.canonicalize().unwrap_or_else(|error| panic!("Could not embed {}: {error}", dir.display()));
+    let generated = generate(&root);
+
+    let out_dir = std::env::var("OUT_DIR").expect("embed_dir must run in a build script");
+    let output = Path::new(&out_dir).join(output);
+    fs::write(&output, generated).unwrap_or_else(|error| panic!("Could not write {}: {error}", output.display()));
+}
+
+/// The list of the files under `root`, as Rust source
+fn generate(root: &Path) -> String {
+    let mut files = Vec::new();
+    collect(root, &mut files);
+    files.sort();
+
+    let mut generated = format!("// Generated from {} by embed_build.rs\n&[\n", root.display());
+    for path in files {
+        let Some(relative) = path.strip_prefix(root).ok().and_then(|relative| relative.to_str()) else {
+            println!("cargo:warning=Not embedding {}: the path is not UTF-8", path.display());
+            continue;
+        };
+        let relative = relative.replace('\\', "/");
+        let bytes = fs::read(&path).unwrap_or_else(|error| panic!("Could not embed {}: {error}", path.display()));
+        let modified = fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |modified| modified.as_secs());
+        let etag = format!("\"{:016x}\"", fnv1a(&bytes));
+        let _ = writeln!(generated, "    ({relative:?}, include_bytes!({:?}), {etag:?}, {:?}, {modified}),",
+                         path.display().to_string(), mime_for_path(&path));
+    }
+    generated.push_str("]\n");
+    generated
+}
+
+fn collect(dir: &Path, files: &mut Vec<PathBuf>) {
+    let entries = fs::read_dir(dir).unwrap_or_else(|error| panic!("Could not embed {}: {error}", dir.display()));
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        if path.is_dir() {
+            collect(&path, files);
+        } else if path.is_file() {
+            files.push(path);
+        }
+    }
+}
+
+/// 64-bit FNV-1a, to tell versions of a file apart
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packcake::testing::temp_dir;
+
+    #[test]
+    fn hashes_with_fnv1a() {
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x85944171f73967e8);
+    }
+
+    #[test]
+    fn lists_every_file_under_the_root() {
+        let root = temp_dir("embed-build").canonicalize().unwrap();
+        fs::create_dir_all(root.join("css/vendor")).unwrap();
+        fs::write(root.join("index.html"), "<html>").unwrap();
+        fs::write(root.join("css/site.css"), "body {}").unwrap();
+        fs::write(root.join("css/vendor/reset.css"), "").unwrap();
+        let generated = generate(&root);
+        let lines = generated.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 6, "{generated}");
+        assert_eq!(lines[0], format!("// Generated from {} by embed_build.rs", root.display()));
+        assert_eq!(lines[1], "&[");
+        let entry = format!("    (\"css/site.css\", include_bytes!({:?}), \"\\\"{:016x}\\\"\", \"text/css\", ",
+                            root.join("css/site.css").display().to_string(), fnv1a(b"body {}"));
+        assert!(lines[2].starts_with(&entry), "{}", lines[2]);
+        assert!(lines[3].starts_with("    (\"css/vendor/reset.css\", "));
+        assert!(lines[4].starts_with("    (\"index.html\", ") && lines[4].contains("\"text/html\""));
+        assert_eq!(lines[5], "]");
+    }
+}
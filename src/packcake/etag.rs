@@ -0,0 +1,209 @@
+use std::fmt::Write;
+use std::time::SystemTime;
+use crate::packcake::compress;
+use crate::packcake::date::parse_http_date;
+use crate::packcake::sha1::sha1;
+use crate::packcake::{Request, Response, StatusCode};
+
+/// The conditional headers of a `GET` or `HEAD` request
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Validators {
+    pub(crate) if_match: Option<String>,
+    pub(crate) if_none_match: Option<String>,
+    pub(crate) if_modified_since: Option<String>,
+}
+
+impl Validators {
+    pub(crate) fn from_request(request: &Request) -> Validators {
+        Validators {
+            if_match: request.get_header("If-Match").cloned(),
+            if_none_match: request.get_header("If-None-Match").cloned(),
+            if_modified_since: request.get_header("If-Modified-Since").cloned(),
+        }
+    }
+}
+
+/// A strong `ETag` for a body, from the first 8 bytes of its SHA-1
+pub(crate) fn body_etag(body: &[u8]) -> String {
+    let hash = sha1(body);
+    let mut etag = String::from("\"");
+    for byte in &hash[..8] {
+        let _ = write!(etag, "{byte:02x}");
+    }
+    etag.push('"');
+    etag
+}
+
+/// Tags a buffered `200 OK` body with an `ETag`, unless the handler set one, and checks it
+/// against the validators the request sent
+///
+/// Returns true, after turning the response into `412 Precondition Failed` when `If-Match`
+/// rules the body out, or `304 Not Modified` when the client's copy is current; the caller
+/// then drops the body.
+pub(crate) fn apply(response: &mut Response, body: &[u8]) -> bool {
+    if !response.auto_etag || response.status != StatusCode::Ok {
+        return false;
+    }
+    let Some(validators) = response.validators.clone() else {
+        return false;
+    };
+    let etag = match response.get_header("ETag") {
+        Some(etag) => etag.clone(),
+        None => {
+            let etag = body_etag(body);
+            response.header("ETag", &etag);
+            etag
+        }
+    };
+    if is_precondition_failed(&validators, Some(&etag)) {
+        response.status(StatusCode::PreconditionFailed);
+        response.remove_header("Content-Type");
+        return true;
+    }
+    let modified = response.get_header("Last-Modified").and_then(|date| parse_http_date(date));
+    if !is_not_modified(&validators, Some(&etag), modified) {
+        return false;
+    }
+    // The 304 carries the `ETag` and `Vary` the full response would have had
+    compress::prepare(response, Some(body.len()));
+    response.status(StatusCode::NotModified);
+    response.remove_header("Content-Type");
+    response.remove_header("Content-Encoding");
+    true
+}
+
+/// Whether `If-Match` rules out the version tagged `etag`
+///
+/// `If-Match` needs a strong match, so weak tags never match; `*` matches any version there is.
+pub(crate) fn is_precondition_failed(validators: &Validators, etag: Option<&str>) -> bool {
+    let Some(if_match) = &validators.if_match else {
+        return false;
+    };
+    if if_match.trim() == "*" {
+        return etag.is_none();
+    }
+    let Some(etag) = etag.filter(|etag| !etag.starts_with("W/")) else {
+        return true;
+    };
+    !if_match.split(',').any(|tag| tag.trim() == etag)
+}
+
+/// Whether the client's copy, described by its conditional headers, is still current
+///
+/// `If-None-Match` takes precedence; `If-Modified-Since` is only used without it.
+pub(crate) fn is_not_modified(validators: &Validators, etag: Option<&str>, modified: Option<SystemTime>) -> bool {
+    if let Some(if_none_match) = &validators.if_none_match {
+        let Some(etag) = etag else {
+            return false;
+        };
+        // Weak comparison: `W/"x"` matches `"x"`
+        let strip = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+        return if_none_match.trim() == "*" || if_none_match.split(',').any(|tag| strip(tag) == strip(etag));
+    }
+    match (validators.if_modified_since.as_deref().and_then(parse_http_date), modified) {
+        // HTTP dates have whole seconds
+        (Some(since), Some(modified)) => modified.duration_since(since).map_or(true, |newer| newer.as_secs() == 0),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+    use crate::packcake::testing::{body, header, request, respond_text};
+
+    fn validators(headers: &[(&str, &str)]) -> Validators {
+        Validators::from_request(&request("GET / HTTP/1.1", headers, ""))
+    }
+
+    /// The response to `line` from a handler that sends `hello`, with `auto_etag` on
+    fn tagged(line: &str, headers: &[(&str, &str)]) -> String {
+        respond_text(&request(line, headers, ""), |_, response| {
+            response.auto_etag = true;
+            response.send("hello");
+        })
+    }
+
+    #[test]
+    fn tags_bodies_with_their_hash() {
+        assert_eq!(body_etag(b"abc"), "\"a9993e364706816a\"");
+        assert_ne!(body_etag(b"abc"), body_etag(b"abd"));
+    }
+
+    #[test]
+    fn compares_if_none_match_weakly() {
+        let etag = Some("\"v1\"");
+        assert!(is_not_modified(&validators(&[("If-None-Match", "\"v1\"")]), etag, None));
+        assert!(is_not_modified(&validators(&[("If-None-Match", "W/\"v1\"")]), etag, None));
+        assert!(is_not_modified(&validators(&[("If-None-Match", "\"v1\"")]), Some("W/\"v1\""), None));
+        assert!(is_not_modified(&validators(&[("If-None-Match", "\"v0\", \"v1\"")]), etag, None));
+        assert!(is_not_modified(&validators(&[("If-None-Match", "*")]), etag, None));
+        assert!(!is_not_modified(&validators(&[("If-None-Match", "\"v0\"")]), etag, None));
+        assert!(!is_not_modified(&validators(&[("If-None-Match", "*")]), None, None));
+    }
+
+    #[test]
+    fn compares_modification_dates_without_if_none_match() {
+        let modified = Some(UNIX_EPOCH + Duration::from_secs(784111777) + Duration::from_millis(500));
+        let since = |date: &str| validators(&[("If-Modified-Since", date)]);
+        assert!(is_not_modified(&since("Sun, 06 Nov 1994 08:49:37 GMT"), None, modified));
+        assert!(is_not_modified(&since("Sun, 06 Nov 1994 09:00:00 GMT"), None, modified));
+        assert!(!is_not_modified(&since("Sun, 06 Nov 1994 08:49:36 GMT"), None, modified));
+        assert!(!is_not_modified(&since("not a date"), None, modified));
+        assert!(!is_not_modified(&since("Sun, 06 Nov 1994 08:49:37 GMT"), None, None));
+        // If-None-Match wins when both are sent
+        let both = validators(&[("If-None-Match", "\"v0\""), ("If-Modified-Since", "Sun, 06 Nov 1994 09:00:00 GMT")]);
+        assert!(!is_not_modified(&both, Some("\"v1\""), modified));
+    }
+
+    #[test]
+    fn compares_if_match_strongly() {
+        let etag = Some("\"v1\"");
+        assert!(!is_precondition_failed(&validators(&[]), etag));
+        assert!(!is_precondition_failed(&validators(&[("If-Match", "\"v1\"")]), etag));
+        assert!(!is_precondition_failed(&validators(&[("If-Match", "\"v0\", \"v1\"")]), etag));
+        assert!(is_precondition_failed(&validators(&[("If-Match", "\"v0\"")]), etag));
+        assert!(is_precondition_failed(&validators(&[("If-Match", "W/\"v1\"")]), etag));
+        assert!(is_precondition_failed(&validators(&[("If-Match", "\"v1\"")]), Some("W/\"v1\"")));
+        assert!(!is_precondition_failed(&validators(&[("If-Match", "*")]), etag));
+        assert!(is_precondition_failed(&validators(&[("If-Match", "*")]), None));
+        assert!(is_precondition_failed(&validators(&[("If-Match", "\"v1\"")]), None));
+    }
+
+    #[test]
+    fn answers_conditional_requests_for_buffered_bodies() {
+        let etag = body_etag(b"hello");
+        let full = tagged("GET / HTTP/1.1", &[]);
+        assert_eq!(header(&full, "ETag"), Some(etag.as_str()));
+        assert_eq!(body(&full), "hello");
+        let cached = tagged("GET / HTTP/1.1", &[("If-None-Match", &etag)]);
+        assert!(cached.starts_with("HTTP/1.1 304 Not Modified\r\n"));
+        assert_eq!((header(&cached, "ETag"), header(&cached, "Content-Type"), body(&cached)), (Some(etag.as_str()), None, ""));
+        let matched = tagged("GET / HTTP/1.1", &[("If-Match", &etag)]);
+        assert!(matched.starts_with("HTTP/1.1 200 OK\r\n"));
+        let failed = tagged("GET / HTTP/1.1", &[("If-Match", "\"other\"")]);
+        assert!(failed.starts_with("HTTP/1.1 412 Precondition Failed\r\n"));
+        assert_eq!(body(&failed), "");
+        let post = tagged("POST / HTTP/1.1", &[("If-None-Match", "*")]);
+        assert!(post.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert_eq!(header(&post, "ETag"), None);
+    }
+
+    #[test]
+    fn keeps_the_handlers_tag_and_status() {
+        let own = respond_text(&request("GET / HTTP/1.1", &[("If-None-Match", "W/\"mine\"")], ""), |_, response| {
+            response.auto_etag = true;
+            response.header("ETag", "\"mine\"");
+            response.send("hello");
+        });
+        assert!(own.starts_with("HTTP/1.1 304 Not Modified\r\n"));
+        assert_eq!(header(&own, "ETag"), Some("\"mine\""));
+        let created = respond_text(&request("GET / HTTP/1.1", &[], ""), |_, response| {
+            response.auto_etag = true;
+            response.status(StatusCode::Created);
+            response.send("hello");
+        });
+        assert_eq!(header(&created, "ETag"), None);
+    }
+}
@@ -0,0 +1,395 @@
+use std::str::FromStr;
+use crate::packcake::form::media_type;
+use crate::packcake::json::Value;
+use crate::packcake::url::Params;
+use crate::packcake::{FormError, Request, Response, StatusCode};
+
+/// Why a value could not be extracted from a request; it is sent instead of running the handler
+///
+/// The body is `{"error":"<reason phrase>","message":"<message>"}`.
+pub struct Rejection {
+    status: StatusCode,
+    message: String,
+}
+
+impl Rejection {
+    pub fn new(status: StatusCode, message: &str) -> Rejection {
+        Rejection {
+            status,
+            message: message.to_string(),
+        }
+    }
+
+    /// A `400 Bad Request` rejection
+    pub fn bad_request(message: &str) -> Rejection {
+        Rejection::new(StatusCode::BadRequest, message)
+    }
+
+    /// A `415 Unsupported Media Type` rejection
+    pub fn unsupported_media_type(message: &str) -> Rejection {
+        Rejection::new(StatusCode::UnsupportedMediaType, message)
+    }
+
+    #[allow(dead_code)]
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub(crate) fn send(self, response: &mut Response) {
+        let error = self.status.reason().to_string();
+        response.status(self.status);
+        response.json_value(&Value::object([
+            ("error", Value::from(error)),
+            ("message", Value::from(self.message)),
+        ]));
+    }
+}
+
+/// Something a handler can take as an extra argument, extracted from the request before it runs
+///
+/// ```ignore
+/// fn post_team(request: &Request, response: &mut Response, Json(team): Json<Team>) { ... }
+/// ```
+pub trait FromRequest: Sized {
+    fn from_request(request: &Request) -> Result<Self, Rejection>;
+}
+
+/// A type that can be read from a JSON value
+pub trait FromJson: Sized {
+    /// Converts the value, or says what was wrong with it
+    fn from_json(value: &Value) -> Result<Self, String>;
+}
+
+#[allow(dead_code)]
+/// A type that can be read from query or form parameters
+pub trait FromParams: Sized {
+    /// Converts the parameters, or says what was wrong with them
+    fn from_params(params: &Params) -> Result<Self, String>;
+}
+
+#[allow(dead_code)]
+/// A header that can be extracted with `Header<T>`
+pub trait FromHeader: Sized {
+    const NAME: &'static str;
+
+    fn from_header(value: &str) -> Result<Self, String>;
+}
+
+/// Extracts a JSON body; requires an `application/json` (or `+json`) `Content-Type`
+pub struct Json<T>(pub T);
+
+#[allow(dead_code)]
+/// Extracts an `application/x-www-form-urlencoded` body
+pub struct Form<T>(pub T);
+
+#[allow(dead_code)]
+/// Extracts the query parameters
+pub struct Query<T>(pub T);
+
+#[allow(dead_code)]
+/// Extracts a header; missing or invalid headers are rejected
+pub struct Header<T>(pub T);
+
+impl<T: FromJson> FromRequest for Json<T> {
+    fn from_request(request: &Request) -> Result<Json<T>, Rejection> {
+        let content_type = request.get_header("Content-Type")
+            .ok_or_else(|| Rejection::unsupported_media_type("Missing Content-Type, expected application/json"))?;
+        let media_type = media_type(content_type);
+        if media_type != "application/json" && !media_type.ends_with("+json") {
+            return Err(Rejection::unsupported_media_type(
+                &format!("Unsupported Content-Type {media_type}, expected application/json")));
+        }
+        let value = request.json().map_err(|error| Rejection::bad_request(&format!("Invalid JSON: {error}")))?;
+        T::from_json(&value).map(Json).map_err(|error| Rejection::bad_request(&error))
+    }
+}
+
+impl<T: FromParams> FromRequest for Form<T> {
+    fn from_request(request: &Request) -> Result<Form<T>, Rejection> {
+        let params = request.form().map_err(|error| match error {
+            FormError::MissingContentType | FormError::UnsupportedMediaType(_) =>
+                Rejection::unsupported_media_type(&error.to_string()),
+        })?;
+        T::from_params(&params).map(Form).map_err(|error| Rejection::bad_request(&error))
+    }
+}
+
+impl<T: FromParams> FromRequest for Query<T> {
+    fn from_request(request: &Request) -> Result<Query<T>, Rejection> {
+        T::from_params(request.get_query_params()).map(Query).map_err(|error| Rejection::bad_request(&error))
+    }
+}
+
+impl<T: FromHeader> FromRequest for Header<T> {
+    fn from_request(request: &Request) -> Result<Header<T>, Rejection> {
+        let value = request.get_header(T::NAME)
+            .ok_or_else(|| Rejection::bad_request(&format!("Missing header [{}]", T::NAME)))?;
+        T::from_header(value)
+            .map(Header)
+            .map_err(|error| Rejection::bad_request(&format!("Invalid header [{}]: {error}", T::NAME)))
+    }
+}
+
+/// The whole body as JSON, whatever its shape
+impl FromRequest for Value {
+    fn from_request(request: &Request) -> Result<Value, Rejection> {
+        Json::<Value>::from_request(request).map(|Json(value)| value)
+    }
+}
+
+/// Never rejects; `None` when the inner extractor would
+impl<T: FromRequest> FromRequest for Option<T> {
+    fn from_request(request: &Request) -> Result<Option<T>, Rejection> {
+        Ok(T::from_request(request).ok())
+    }
+}
+
+/// Never rejects; lets the handler decide what to do with the rejection
+impl<T: FromRequest> FromRequest for Result<T, Rejection> {
+    fn from_request(request: &Request) -> Result<Result<T, Rejection>, Rejection> {
+        Ok(T::from_request(request))
+    }
+}
+
+impl<A: FromRequest, B: FromRequest> FromRequest for (A, B) {
+    fn from_request(request: &Request) -> Result<(A, B), Rejection> {
+        Ok((A::from_request(request)?, B::from_request(request)?))
+    }
+}
+
+impl<A: FromRequest, B: FromRequest, C: FromRequest> FromRequest for (A, B, C) {
+    fn from_request(request: &Request) -> Result<(A, B, C), Rejection> {
+        Ok((A::from_request(request)?, B::from_request(request)?, C::from_request(request)?))
+    }
+}
+
+#[allow(dead_code)]
+/// The `User-Agent` header
+pub struct UserAgent(pub String);
+
+impl FromHeader for UserAgent {
+    const NAME: &'static str = "User-Agent";
+
+    fn from_header(value: &str) -> Result<UserAgent, String> {
+        Ok(UserAgent(value.to_string()))
+    }
+}
+
+#[allow(dead_code)]
+/// The token of an `Authorization: Bearer <token>` header
+pub struct BearerToken(pub String);
+
+impl FromHeader for BearerToken {
+    const NAME: &'static str = "Authorization";
+
+    fn from_header(value: &str) -> Result<BearerToken, String> {
+        match value.split_once(' ') {
+            Some((scheme, token)) if scheme.eq_ignore_ascii_case("Bearer") && !token.trim().is_empty() =>
+                Ok(BearerToken(token.trim().to_string())),
+            _ => Err(String::from("expected \"Bearer <token>\"")),
+        }
+    }
+}
+
+impl FromJson for Value {
+    fn from_json(value: &Value) -> Result<Value, String> {
+        Ok(value.clone())
+    }
+}
+
+impl FromJson for String {
+    fn from_json(value: &Value) -> Result<String, String> {
+        value.as_str().map(String::from).ok_or_else(|| String::from("expected a string"))
+    }
+}
+
+impl FromJson for bool {
+    fn from_json(value: &Value) -> Result<bool, String> {
+        value.as_bool().ok_or_else(|| String::from("expected a boolean"))
+    }
+}
+
+impl FromJson for f64 {
+    fn from_json(value: &Value) -> Result<f64, String> {
+        value.as_f64().ok_or_else(|| String::from("expected a number"))
+    }
+}
+
+impl FromJson for i64 {
+    fn from_json(value: &Value) -> Result<i64, String> {
+        value.as_i64().ok_or_else(|| String::from("expected an integer"))
+    }
+}
+
+impl FromJson for u64 {
+    fn from_json(value: &Value) -> Result<u64, String> {
+        value.as_u64().ok_or_else(|| String::from("expected a non-negative integer"))
+    }
+}
+
+impl<T: FromJson> FromJson for Option<T> {
+    fn from_json(value: &Value) -> Result<Option<T>, String> {
+        match value {
+            Value::Null => Ok(None),
+            value => T::from_json(value).map(Some),
+        }
+    }
+}
+
+impl<T: FromJson> FromJson for Vec<T> {
+    fn from_json(value: &Value) -> Result<Vec<T>, String> {
+        let values = value.as_array().ok_or_else(|| String::from("expected an array"))?;
+        values.iter()
+            .enumerate()
+            .map(|(i, value)| T::from_json(value).map_err(|error| format!("[{i}]: {error}")))
+            .collect()
+    }
+}
+
+impl Value {
+    /// Reads a member of an object, for use in `FromJson` implementations
+    ///
+    /// Missing members are read as `null`, so `Option` fields may be left out.
+    pub fn field<T: FromJson>(&self, key: &str) -> Result<T, String> {
+        if self.as_object().is_none() {
+            return Err(String::from("expected an object"));
+        }
+        T::from_json(&self[key]).map_err(|error| format!("field [{key}]: {error}"))
+    }
+}
+
+impl FromParams for Params {
+    fn from_params(params: &Params) -> Result<Params, String> {
+        Ok(params.clone())
+    }
+}
+
+impl Params {
+    /// Reads a parameter parsed as `T`, for use in `FromParams` implementations
+    pub fn require<T: FromStr>(&self, key: &str) -> Result<T, String> {
+        let value = self.get(key).ok_or_else(|| format!("Missing param [{key}]"))?;
+        value.parse::<T>().map_err(|_| format!("Malformed param [{key}]: \"{value}\""))
+    }
+
+    /// Reads an optional parameter parsed as `T`
+    pub fn optional<T: FromStr>(&self, key: &str) -> Result<Option<T>, String> {
+        match self.get(key) {
+            None => Ok(None),
+            Some(_) => self.require(key).map(Some),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packcake::handler::IntoHandler;
+    use crate::packcake::testing::{body, request, respond_text};
+
+    struct Team {
+        name: String,
+        size: u64,
+    }
+
+    impl FromJson for Team {
+        fn from_json(value: &Value) -> Result<Team, String> {
+            Ok(Team {
+                name: value.field("name")?,
+                size: value.field::<Option<u64>>("size")?.unwrap_or(1),
+            })
+        }
+    }
+
+    impl FromParams for Team {
+        fn from_params(params: &Params) -> Result<Team, String> {
+            Ok(Team {
+                name: params.require("name")?,
+                size: params.optional("size")?.unwrap_or(1),
+            })
+        }
+    }
+
+    fn json(body: &str) -> Request {
+        request("POST /team HTTP/1.1", &[("Content-Type", "application/json")], body)
+    }
+
+    fn rejection<T: FromRequest>(request: &Request) -> (StatusCode, String) {
+        match T::from_request(request) {
+            Ok(_) => panic!("not rejected"),
+            Err(rejection) => (rejection.status, rejection.message),
+        }
+    }
+
+    #[test]
+    fn extracts_json() {
+        let Json(team) = Json::<Team>::from_request(&json(r#"{"name":"core","size":3}"#)).ok().unwrap();
+        assert_eq!((team.name.as_str(), team.size), ("core", 3));
+        let request = request("POST / HTTP/1.1", &[("Content-Type", "application/merge-patch+json")], "[1]");
+        assert_eq!(Value::from_request(&request).ok().unwrap()[0].as_i64(), Some(1));
+    }
+
+    #[test]
+    fn rejects_bad_json() {
+        let no_type = request("POST /team HTTP/1.1", &[], "{}");
+        assert_eq!(rejection::<Json<Team>>(&no_type).0, StatusCode::UnsupportedMediaType);
+        let text = request("POST /team HTTP/1.1", &[("Content-Type", "text/plain")], "{}");
+        assert_eq!(rejection::<Json<Team>>(&text),
+                   (StatusCode::UnsupportedMediaType, String::from("Unsupported Content-Type text/plain, expected application/json")));
+        assert_eq!(rejection::<Json<Team>>(&json("{\"name\":")).0, StatusCode::BadRequest);
+        assert_eq!(rejection::<Json<Team>>(&json("{\"size\":2}")),
+                   (StatusCode::BadRequest, String::from("field [name]: expected a string")));
+        assert_eq!(rejection::<Json<Team>>(&json("[]")).1, "expected an object");
+        assert_eq!(rejection::<Json<Vec<u64>>>(&json("[1,-2]")).1, "[1]: expected a non-negative integer");
+    }
+
+    #[test]
+    fn extracts_forms_and_queries() {
+        let form = request("POST /team HTTP/1.1", &[("Content-Type", "application/x-www-form-urlencoded")], "name=a+b&size=4");
+        let Form(team) = Form::<Team>::from_request(&form).ok().unwrap();
+        assert_eq!((team.name.as_str(), team.size), ("a b", 4));
+        let Query(team) = Query::<Team>::from_request(&request("GET /team?name=x HTTP/1.1", &[], "")).ok().unwrap();
+        assert_eq!((team.name.as_str(), team.size), ("x", 1));
+
+        let json = request("POST /team HTTP/1.1", &[("Content-Type", "application/json")], "{}");
+        assert_eq!(rejection::<Form<Team>>(&json).0, StatusCode::UnsupportedMediaType);
+        assert_eq!(rejection::<Query<Team>>(&request("GET /team?size=x HTTP/1.1", &[], "")),
+                   (StatusCode::BadRequest, String::from("Missing param [name]")));
+        assert_eq!(rejection::<Query<Team>>(&request("GET /team?name=x&size=big HTTP/1.1", &[], "")).1,
+                   "Malformed param [size]: \"big\"");
+    }
+
+    #[test]
+    fn extracts_headers() {
+        let curl = request("GET / HTTP/1.1", &[("User-Agent", "curl/8"), ("Authorization", "bearer abc ")], "");
+        let Header(UserAgent(agent)) = Header::<UserAgent>::from_request(&curl).ok().unwrap();
+        assert_eq!(agent, "curl/8");
+        let Header(BearerToken(token)) = Header::<BearerToken>::from_request(&curl).ok().unwrap();
+        assert_eq!(token, "abc");
+
+        let basic = request("GET / HTTP/1.1", &[("Authorization", "Basic dXNlcg==")], "");
+        assert_eq!(rejection::<Header<BearerToken>>(&basic),
+                   (StatusCode::BadRequest, String::from("Invalid header [Authorization]: expected \"Bearer <token>\"")));
+        assert_eq!(rejection::<Header<UserAgent>>(&basic).1, "Missing header [User-Agent]");
+    }
+
+    #[test]
+    fn combines_extractors() {
+        let request = request("GET /?name=q HTTP/1.1", &[("User-Agent", "curl/8")], "");
+        assert!(<(Query<Team>, Header<UserAgent>)>::from_request(&request).is_ok());
+        assert_eq!(rejection::<(Query<Team>, Header<BearerToken>)>(&request).1, "Missing header [Authorization]");
+        assert!(matches!(Option::<Header<BearerToken>>::from_request(&request), Ok(None)));
+        assert!(matches!(Result::<Header<BearerToken>, Rejection>::from_request(&request), Ok(Err(_))));
+    }
+
+    #[test]
+    fn sends_rejections_instead_of_running_the_handler() {
+        let handler = (|_: &Request, response: &mut Response, Json(team): Json<Team>| response.send(&team.name)).into_handler();
+        let sent = respond_text(&json("{\"name\":\"core\"}"), |request, response| handler(request, response));
+        assert!(sent.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert_eq!(body(&sent), "core");
+
+        let sent = respond_text(&json("{\"name\":1}"), |request, response| handler(request, response));
+        assert!(sent.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        assert_eq!(body(&sent), r#"{"error":"Bad Request","message":"field [name]: expected a string"}"#);
+    }
+}
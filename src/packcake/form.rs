@@ -0,0 +1,82 @@
+use std::fmt::{Display, Formatter};
+use crate::packcake::url::Params;
+
+pub const FORM_URLENCODED: &str = "application/x-www-form-urlencoded";
+
+/// Why a request body could not be read as a form
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FormError {
+    /// The request has no `Content-Type` header
+    MissingContentType,
+    /// The `Content-Type` is not `application/x-www-form-urlencoded`
+    UnsupportedMediaType(String),
+}
+
+impl Display for FormError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FormError::MissingContentType =>
+                write!(f, "Missing Content-Type, expected {FORM_URLENCODED}"),
+            FormError::UnsupportedMediaType(media_type) =>
+                write!(f, "Unsupported Content-Type {media_type}, expected {FORM_URLENCODED}"),
+        }
+    }
+}
+
+/// The media type of a `Content-Type` value, lowercased and without parameters
+///
+/// `Application/JSON; charset=utf-8` -> `application/json`
+pub(crate) fn media_type(content_type: &str) -> String {
+    content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase()
+}
+
+/// Parses an urlencoded body, checking that the `Content-Type` says it is one
+pub(crate) fn parse_form(content_type: Option<&String>, body: &str) -> Result<Params, FormError> {
+    let content_type = content_type.ok_or(FormError::MissingContentType)?;
+    let media_type = media_type(content_type);
+    if media_type != FORM_URLENCODED {
+        return Err(FormError::UnsupportedMediaType(media_type));
+    }
+    Ok(Params::parse(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::packcake::Request;
+
+    fn request(content_type: Option<&str>, body: &str) -> Request {
+        let headers = content_type.map(|value| (String::from("Content-Type"), value.to_string()));
+        Request::new("POST /form HTTP/1.1", headers.into_iter().collect::<HashMap<_, _>>(), body.to_string())
+    }
+
+    #[test]
+    fn reads_media_types() {
+        assert_eq!(media_type("Application/JSON; charset=utf-8"), "application/json");
+        assert_eq!(media_type(" text/plain "), "text/plain");
+        assert_eq!(media_type(""), "");
+    }
+
+    #[test]
+    fn parses_forms() {
+        let form = request(Some("application/x-www-form-urlencoded"), "name=Ada+Lovelace&tag=a&tag=b&note=50%25&agree")
+            .form()
+            .unwrap();
+        assert_eq!(form.get("name").unwrap(), "Ada Lovelace");
+        assert_eq!(form.get_all("tag"), ["a", "b"]);
+        assert_eq!(form.get("note").unwrap(), "50%");
+        assert!(form.contains("agree"));
+
+        let form = request(Some("Application/X-WWW-Form-Urlencoded; charset=UTF-8"), "").form().unwrap();
+        assert!(form.is_empty());
+    }
+
+    #[test]
+    fn checks_the_content_type() {
+        assert_eq!(request(None, "a=1").form().unwrap_err(), FormError::MissingContentType);
+        let error = request(Some("application/json"), "{}").form().unwrap_err();
+        assert_eq!(error, FormError::UnsupportedMediaType(String::from("application/json")));
+        assert_eq!(error.to_string(), "Unsupported Content-Type application/json, expected application/x-www-form-urlencoded");
+    }
+}
@@ -0,0 +1,66 @@
+use std::marker::PhantomData;
+use std::sync::Arc;
+use crate::packcake::extract::FromRequest;
+use crate::packcake::respond::IntoResponse;
+use crate::packcake::{Request, Response};
+
+/// A handler as stored on an endpoint
+pub type Handler = Arc<dyn Fn(&Request, &mut Response) + Send + Sync>;
+
+/// Anything that can handle requests for an endpoint
+///
+/// `M` only tells the implementations apart, and is inferred from the handler:
+///
+/// * `fn(&Request, &mut Response)`
+/// * `fn(&Request, &mut Response, T)` where `T: FromRequest` is extracted before the handler runs
+/// * `fn(&Request) -> R` where `R: IntoResponse` is written to the response
+/// * `fn(&Request, T) -> R` with both of the above
+pub trait IntoHandler<M> {
+    fn into_handler(self) -> Handler;
+}
+
+impl<F> IntoHandler<()> for F
+where F: Fn(&Request, &mut Response) + Send + Sync + 'static {
+    fn into_handler(self) -> Handler {
+        Arc::new(self)
+    }
+}
+
+impl<F, T> IntoHandler<(T,)> for F
+where F: Fn(&Request, &mut Response, T) + Send + Sync + 'static, T: FromRequest {
+    fn into_handler(self) -> Handler {
+        Arc::new(move |request: &Request, response: &mut Response| {
+            match T::from_request(request) {
+                Ok(value) => self(request, response, value),
+                Err(rejection) => rejection.send(response),
+            }
+        })
+    }
+}
+
+/// Marks handlers that return their response
+pub struct Returning<R>(PhantomData<R>);
+
+/// Marks handlers that take an extracted value and return their response
+pub struct ExtractingReturning<T, R>(PhantomData<(T, R)>);
+
+impl<F, R> IntoHandler<Returning<R>> for F
+where F: Fn(&Request) -> R + Send + Sync + 'static, R: IntoResponse {
+    fn into_handler(self) -> Handler {
+        Arc::new(move |request: &Request, response: &mut Response| {
+            self(request).into_response(response);
+        })
+    }
+}
+
+impl<F, T, R> IntoHandler<ExtractingReturning<T, R>> for F
+where F: Fn(&Request, T) -> R + Send + Sync + 'static, T: FromRequest, R: IntoResponse {
+    fn into_handler(self) -> Handler {
+        Arc::new(move |request: &Request, response: &mut Response| {
+            match T::from_request(request) {
+                Ok(value) => self(request, value).into_response(response),
+                Err(rejection) => rejection.send(response),
+            }
+        })
+    }
+}
@@ -0,0 +1,686 @@
+use std::fmt::{Display, Formatter, Write};
+use std::ops::Index;
+
+/// Nesting deeper than this is rejected, so hostile bodies cannot overflow the stack
+const MAX_DEPTH: usize = 128;
+
+static NULL: Value = Value::Null;
+
+/// A JSON value; objects keep their keys in insertion order
+#[derive(Clone, Debug, PartialEq, Default)]
+pub enum Value {
+    #[default]
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Builds an object from key-value pairs
+    ///
+    /// ```ignore
+    /// Value::object([("message", Value::from("get_user")), ("id", Value::from(42))])
+    /// ```
+    pub fn object<K: Into<String>, I: IntoIterator<Item = (K, Value)>>(pairs: I) -> Value {
+        Value::Object(pairs.into_iter().map(|(k, v)| (k.into(), v)).collect())
+    }
+
+    pub fn array<T: Into<Value>, I: IntoIterator<Item = T>>(values: I) -> Value {
+        Value::Array(values.into_iter().map(Into::into).collect())
+    }
+
+    /// Parses a JSON document
+    pub fn parse(input: &str) -> Result<Value, JsonError> {
+        let mut parser = Parser { input: input.as_bytes(), position: 0 };
+        parser.skip_whitespace();
+        let value = parser.parse_value(0)?;
+        parser.skip_whitespace();
+        if parser.position < parser.input.len() {
+            return Err(parser.error("unexpected data after the JSON value"));
+        }
+        Ok(value)
+    }
+
+    /// Sets `key` on an object, replacing an earlier value; does nothing on other values
+    pub fn insert<K: Into<String>, V: Into<Value>>(&mut self, key: K, value: V) {
+        if let Value::Object(pairs) = self {
+            let key = key.into();
+            let value = value.into();
+            match pairs.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, existing)) => *existing = value,
+                None => pairs.push((key, value)),
+            }
+        }
+    }
+
+    /// Appends to an array; does nothing on other values
+    pub fn push<V: Into<Value>>(&mut self, value: V) {
+        if let Value::Array(values) = self {
+            values.push(value.into());
+        }
+    }
+
+    /// Gets a member of an object
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(pairs) => pairs.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null)
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// The number as an `i64`, if it is a whole number in range
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::Number(n) if n.fract() == 0.0 && *n >= i64::MIN as f64 && *n < i64::MAX as f64 => Some(*n as i64),
+            _ => None,
+        }
+    }
+
+    /// The number as a `u64`, if it is a whole, non-negative number in range
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Value::Number(n) if n.fract() == 0.0 && *n >= 0.0 && *n < u64::MAX as f64 => Some(*n as u64),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<Value>> {
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&Vec<(String, Value)>> {
+        match self {
+            Value::Object(pairs) => Some(pairs),
+            _ => None,
+        }
+    }
+
+    /// Serializes the value with two-space indentation
+    pub fn to_pretty_string(&self) -> String {
+        let mut out = String::new();
+        self.write(&mut out, Some(0)).unwrap();
+        out
+    }
+
+    fn write<W: Write>(&self, out: &mut W, indent: Option<usize>) -> std::fmt::Result {
+        let newline = |out: &mut W, depth: usize| -> std::fmt::Result {
+            if indent.is_some() {
+                out.write_char('\n')?;
+                for _ in 0..depth {
+                    out.write_str("  ")?;
+                }
+            }
+            Ok(())
+        };
+        let depth = indent.unwrap_or(0);
+        match self {
+            Value::Null => out.write_str("null"),
+            Value::Bool(b) => write!(out, "{b}"),
+            Value::Number(n) if n.is_finite() => write!(out, "{n}"),
+            // JSON has no NaN or infinity
+            Value::Number(_) => out.write_str("null"),
+            Value::String(s) => write_string(out, s),
+            Value::Array(values) if values.is_empty() => out.write_str("[]"),
+            Value::Array(values) => {
+                out.write_char('[')?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        out.write_char(',')?;
+                    }
+                    newline(out, depth + 1)?;
+                    value.write(out, indent.map(|d| d + 1))?;
+                }
+                newline(out, depth)?;
+                out.write_char(']')
+            }
+            Value::Object(pairs) if pairs.is_empty() => out.write_str("{}"),
+            Value::Object(pairs) => {
+                out.write_char('{')?;
+                for (i, (key, value)) in pairs.iter().enumerate() {
+                    if i > 0 {
+                        out.write_char(',')?;
+                    }
+                    newline(out, depth + 1)?;
+                    write_string(out, key)?;
+                    out.write_str(if indent.is_some() { ": " } else { ":" })?;
+                    value.write(out, indent.map(|d| d + 1))?;
+                }
+                newline(out, depth)?;
+                out.write_char('}')
+            }
+        }
+    }
+}
+
+/// Serializes the value compactly
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.write(f, None)
+    }
+}
+
+/// `value["key"]` gives `Value::Null` when the key is missing or the value is not an object
+impl Index<&str> for Value {
+    type Output = Value;
+
+    fn index(&self, key: &str) -> &Value {
+        self.get(key).unwrap_or(&NULL)
+    }
+}
+
+/// `value[0]` gives `Value::Null` when the index is out of range or the value is not an array
+impl Index<usize> for Value {
+    type Output = Value;
+
+    fn index(&self, index: usize) -> &Value {
+        match self {
+            Value::Array(values) => values.get(index).unwrap_or(&NULL),
+            _ => &NULL,
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Value {
+        Value::Bool(b)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Value {
+        Value::String(s)
+    }
+}
+
+impl From<&String> for Value {
+    fn from(s: &String) -> Value {
+        Value::String(s.clone())
+    }
+}
+
+macro_rules! from_number {
+    ($($t:ty),*) => {
+        $(impl From<$t> for Value {
+            fn from(n: $t) -> Value {
+                Value::Number(n as f64)
+            }
+        })*
+    };
+}
+
+from_number!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize, f32, f64);
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(values: Vec<T>) -> Value {
+        Value::array(values)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Value {
+        value.map(Into::into).unwrap_or(Value::Null)
+    }
+}
+
+fn write_string<W: Write>(out: &mut W, s: &str) -> std::fmt::Result {
+    out.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => out.write_str("\\\"")?,
+            '\\' => out.write_str("\\\\")?,
+            '\n' => out.write_str("\\n")?,
+            '\r' => out.write_str("\\r")?,
+            '\t' => out.write_str("\\t")?,
+            '\u{08}' => out.write_str("\\b")?,
+            '\u{0c}' => out.write_str("\\f")?,
+            // Valid in JSON but not in JavaScript string literals
+            '\u{2028}' | '\u{2029}' => write!(out, "\\u{:04x}", c as u32)?,
+            c if (c as u32) < 0x20 || c == '\u{7f}' => write!(out, "\\u{:04x}", c as u32)?,
+            c => out.write_char(c)?,
+        }
+    }
+    out.write_char('"')
+}
+
+/// Where and why parsing failed; `line` and `column` start at 1
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JsonError {
+    pub message: String,
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Display for JsonError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at line {} column {}", self.message, self.line, self.column)
+    }
+}
+
+struct Parser<'a> {
+    input: &'a [u8],
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> JsonError {
+        let offset = self.position.min(self.input.len());
+        let before = &self.input[..offset];
+        let line = before.iter().filter(|b| **b == b'\n').count() + 1;
+        let line_start = before.iter().rposition(|b| *b == b'\n').map(|i| i + 1).unwrap_or(0);
+        let column = String::from_utf8_lossy(&before[line_start..]).chars().count() + 1;
+        JsonError {
+            message: message.to_string(),
+            offset,
+            line,
+            column,
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.position).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.position += 1;
+        }
+    }
+
+    fn expect_literal(&mut self, literal: &str, value: Value) -> Result<Value, JsonError> {
+        if self.input[self.position..].starts_with(literal.as_bytes()) {
+            self.position += literal.len();
+            Ok(value)
+        } else {
+            Err(self.error("invalid literal"))
+        }
+    }
+
+    fn parse_value(&mut self, depth: usize) -> Result<Value, JsonError> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("nesting is too deep"));
+        }
+        match self.peek() {
+            None => Err(self.error("unexpected end of input")),
+            Some(b'n') => self.expect_literal("null", Value::Null),
+            Some(b't') => self.expect_literal("true", Value::Bool(true)),
+            Some(b'f') => self.expect_literal("false", Value::Bool(false)),
+            Some(b'"') => self.parse_string().map(Value::String),
+            Some(b'[') => self.parse_array(depth),
+            Some(b'{') => self.parse_object(depth),
+            Some(b'-' | b'0'..=b'9') => self.parse_number(),
+            Some(_) => Err(self.error("expected a JSON value")),
+        }
+    }
+
+    fn parse_array(&mut self, depth: usize) -> Result<Value, JsonError> {
+        self.position += 1;
+        let mut values = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.position += 1;
+            return Ok(Value::Array(values));
+        }
+        loop {
+            self.skip_whitespace();
+            values.push(self.parse_value(depth + 1)?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.position += 1,
+                Some(b']') => {
+                    self.position += 1;
+                    return Ok(Value::Array(values));
+                }
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn parse_object(&mut self, depth: usize) -> Result<Value, JsonError> {
+        self.position += 1;
+        let mut pairs: Vec<(String, Value)> = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.position += 1;
+            return Ok(Value::Object(pairs));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some(b'"') {
+                return Err(self.error("expected a string key"));
+            }
+            let key = self.parse_string()?;
+            self.skip_whitespace();
+            if self.peek() != Some(b':') {
+                return Err(self.error("expected ':'"));
+            }
+            self.position += 1;
+            self.skip_whitespace();
+            let value = self.parse_value(depth + 1)?;
+            // The last of duplicate keys wins
+            match pairs.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, existing)) => *existing = value,
+                None => pairs.push((key, value)),
+            }
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.position += 1,
+                Some(b'}') => {
+                    self.position += 1;
+                    return Ok(Value::Object(pairs));
+                }
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<Value, JsonError> {
+        let start = self.position;
+        if self.peek() == Some(b'-') {
+            self.position += 1;
+        }
+        match self.peek() {
+            Some(b'0') => {
+                self.position += 1;
+                if let Some(b'0'..=b'9') = self.peek() {
+                    return Err(self.error("leading zeros are not allowed"));
+                }
+            }
+            Some(b'1'..=b'9') => self.skip_digits(),
+            _ => return Err(self.error("expected a digit")),
+        }
+        if self.peek() == Some(b'.') {
+            self.position += 1;
+            if !matches!(self.peek(), Some(b'0'..=b'9')) {
+                return Err(self.error("expected a digit after '.'"));
+            }
+            self.skip_digits();
+        }
+        if let Some(b'e' | b'E') = self.peek() {
+            self.position += 1;
+            if let Some(b'+' | b'-') = self.peek() {
+                self.position += 1;
+            }
+            if !matches!(self.peek(), Some(b'0'..=b'9')) {
+                return Err(self.error("expected a digit in the exponent"));
+            }
+            self.skip_digits();
+        }
+        let text = std::str::from_utf8(&self.input[start..self.position]).unwrap_or_default();
+        match text.parse::<f64>() {
+            // JSON can't write infinity back, so `1e400` is refused rather than turned into it
+            Ok(number) if number.is_finite() => Ok(Value::Number(number)),
+            Ok(_) => Err(self.error("number is out of range")),
+            Err(_) => Err(self.error("invalid number")),
+        }
+    }
+
+    fn skip_digits(&mut self) {
+        while let Some(b'0'..=b'9') = self.peek() {
+            self.position += 1;
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, JsonError> {
+        self.position += 1;
+        let mut bytes = Vec::new();
+        loop {
+            match self.peek() {
+                None => return Err(self.error("unterminated string")),
+                Some(b'"') => {
+                    self.position += 1;
+                    break;
+                }
+                Some(b'\\') => {
+                    self.position += 1;
+                    let escaped = match self.peek() {
+                        Some(b'u') => {
+                            self.position += 1;
+                            self.parse_unicode_escape()?
+                        }
+                        Some(byte) => {
+                            let escaped = match byte {
+                                b'"' => '"',
+                                b'\\' => '\\',
+                                b'/' => '/',
+                                b'b' => '\u{08}',
+                                b'f' => '\u{0c}',
+                                b'n' => '\n',
+                                b'r' => '\r',
+                                b't' => '\t',
+                                _ => return Err(self.error("invalid escape")),
+                            };
+                            self.position += 1;
+                            escaped
+                        }
+                        None => return Err(self.error("unterminated string")),
+                    };
+                    let mut buffer = [0; 4];
+                    bytes.extend_from_slice(escaped.encode_utf8(&mut buffer).as_bytes());
+                }
+                Some(byte) if byte < 0x20 => return Err(self.error("control character in string")),
+                Some(byte) => {
+                    bytes.push(byte);
+                    self.position += 1;
+                }
+            }
+        }
+        String::from_utf8(bytes).map_err(|_| self.error("invalid UTF-8 in string"))
+    }
+
+    /// Parses the digits of a `\u` escape, including a following low surrogate
+    fn parse_unicode_escape(&mut self) -> Result<char, JsonError> {
+        let high = self.parse_hex4()?;
+        if (0xD800..0xDC00).contains(&high) {
+            if !self.input[self.position..].starts_with(b"\\u") {
+                return Err(self.error("unpaired surrogate"));
+            }
+            self.position += 2;
+            let low = self.parse_hex4()?;
+            if !(0xDC00..0xE000).contains(&low) {
+                return Err(self.error("unpaired surrogate"));
+            }
+            let code = 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00);
+            return char::from_u32(code).ok_or_else(|| self.error("invalid unicode escape"));
+        }
+        if (0xDC00..0xE000).contains(&high) {
+            return Err(self.error("unpaired surrogate"));
+        }
+        char::from_u32(high).ok_or_else(|| self.error("invalid unicode escape"))
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, JsonError> {
+        let digits = self.input.get(self.position..self.position + 4)
+            .ok_or_else(|| self.error("invalid unicode escape"))?;
+        let text = std::str::from_utf8(digits).map_err(|_| self.error("invalid unicode escape"))?;
+        if !text.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(self.error("invalid unicode escape"));
+        }
+        self.position += 4;
+        u32::from_str_radix(text, 16).map_err(|_| self.error("invalid unicode escape"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(input: &str) -> JsonError {
+        Value::parse(input).unwrap_err()
+    }
+
+    #[test]
+    fn parses_values() {
+        assert_eq!(Value::parse(" null ").unwrap(), Value::Null);
+        assert_eq!(Value::parse("true").unwrap(), Value::Bool(true));
+        assert_eq!(Value::parse("[]").unwrap(), Value::Array(Vec::new()));
+        assert_eq!(Value::parse("{}").unwrap(), Value::Object(Vec::new()));
+        let value = Value::parse(r#"{"b": [1, "two", {"c": null}], "a": false}"#).unwrap();
+        assert_eq!(value["b"][1].as_str(), Some("two"));
+        assert!(value["b"][2]["c"].is_null());
+        assert_eq!(value["a"].as_bool(), Some(false));
+        // Keys keep their order
+        assert_eq!(value.to_string(), r#"{"b":[1,"two",{"c":null}],"a":false}"#);
+    }
+
+    #[test]
+    fn later_duplicate_keys_win() {
+        assert_eq!(Value::parse(r#"{"a": 1, "b": 2, "a": 3}"#).unwrap().to_string(), r#"{"a":3,"b":2}"#);
+    }
+
+    #[test]
+    fn parses_escapes() {
+        let value = Value::parse(r#""\" \\ \/ \b \f \n \r \t A é €""#).unwrap();
+        assert_eq!(value.as_str(), Some("\" \\ / \u{08} \u{0c} \n \r \t A \u{e9} \u{20ac}"));
+        assert_eq!(Value::parse("\"caf\u{e9} \u{1f600}\"").unwrap().as_str(), Some("caf\u{e9} \u{1f600}"));
+    }
+
+    #[test]
+    fn parses_surrogate_pairs() {
+        assert_eq!(Value::parse(r#""😀""#).unwrap().as_str(), Some("\u{1f600}"));
+        assert_eq!(Value::parse(r#""𝄞""#).unwrap().as_str(), Some("\u{1d11e}"));
+        for input in [r#""\ud83d""#, r#""\ud83d x""#, r#""\ud83dA""#, r#""\ude00""#] {
+            assert_eq!(error(input).message, "unpaired surrogate", "{input}");
+        }
+    }
+
+    #[test]
+    fn rejects_bad_strings() {
+        assert_eq!(error(r#""\x""#).message, "invalid escape");
+        assert_eq!(error(r#""\u12G4""#).message, "invalid unicode escape");
+        assert_eq!(error(r#""\u12""#).message, "invalid unicode escape");
+        assert_eq!(error("\"a\tb\"").message, "control character in string");
+        assert_eq!(error(r#""abc"#).message, "unterminated string");
+    }
+
+    #[test]
+    fn parses_numbers() {
+        let number = |input: &str| Value::parse(input).unwrap().as_f64().unwrap();
+        assert_eq!(number("0"), 0.0);
+        assert_eq!(number("-0"), 0.0);
+        assert_eq!(number("-12.5"), -12.5);
+        assert_eq!(number("1e3"), 1000.0);
+        assert_eq!(number("1E+2"), 100.0);
+        assert_eq!(number("25e-1"), 2.5);
+        assert_eq!(number("1.7976931348623157e308"), f64::MAX);
+        assert_eq!(number("1e-400"), 0.0);
+        assert_eq!(Value::parse("9007199254740993").unwrap().as_u64(), Some(9007199254740992));
+        assert_eq!(Value::parse("42").unwrap().as_i64(), Some(42));
+        assert_eq!(Value::parse("-1").unwrap().as_u64(), None);
+    }
+
+    #[test]
+    fn rejects_bad_numbers() {
+        assert_eq!(error("01").message, "leading zeros are not allowed");
+        assert_eq!(error("-01").message, "leading zeros are not allowed");
+        assert_eq!(error("1.").message, "expected a digit after '.'");
+        assert_eq!(error("1e").message, "expected a digit in the exponent");
+        assert_eq!(error("1e+").message, "expected a digit in the exponent");
+        assert_eq!(error("-").message, "expected a digit");
+        assert_eq!(error("1e400").message, "number is out of range");
+        assert_eq!(error("-1e400").message, "number is out of range");
+        for input in ["+1", ".5", "NaN", "Infinity", "0x10"] {
+            assert!(Value::parse(input).is_err(), "{input}");
+        }
+    }
+
+    #[test]
+    fn rejects_bad_structure() {
+        assert_eq!(error("").message, "unexpected end of input");
+        assert_eq!(error("[1,]").message, "expected a JSON value");
+        assert_eq!(error("[1 2]").message, "expected ',' or ']'");
+        assert_eq!(error(r#"{"a" 1}"#).message, "expected ':'");
+        assert_eq!(error(r#"{a: 1}"#).message, "expected a string key");
+        assert_eq!(error(r#"{"a": 1,}"#).message, "expected a string key");
+        assert_eq!(error(r#"{"a": 1 "b": 2}"#).message, "expected ',' or '}'");
+        assert_eq!(error("nul").message, "invalid literal");
+        assert_eq!(error("true false").message, "unexpected data after the JSON value");
+    }
+
+    #[test]
+    fn limits_nesting() {
+        let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(Value::parse(&nested(MAX_DEPTH + 1)).is_ok());
+        let error = error(&nested(MAX_DEPTH + 2));
+        assert_eq!(error.message, "nesting is too deep");
+        assert_eq!(error.offset, MAX_DEPTH + 1);
+        assert!(Value::parse(&"[".repeat(100_000)).is_err());
+    }
+
+    #[test]
+    fn reports_line_and_column() {
+        let error = error("{\n  \"a\": 1,\n  \"b\": tru\n}");
+        assert_eq!((error.line, error.column, error.offset), (3, 8, 19));
+        assert_eq!(error.to_string(), "invalid literal at line 3 column 8");
+        // Columns count characters, not bytes
+        let error = self::error("[\"\u{e9}\u{e9}\", x]");
+        assert_eq!((error.line, error.column), (1, 8));
+    }
+
+    #[test]
+    fn round_trips() {
+        for input in [
+            r#"{"name":"Ada","tags":["a","b"],"age":36,"ratio":0.25,"ok":true,"none":null}"#,
+            r#"["\"quoted\"","back\\slash","line\nbreak","tab\t","\u0001","\u2028"]"#,
+            r#"[-1.5,1e+300,0,-0,123456789]"#,
+            r#"{"nested":{"deeper":[[[]],{}]}}"#,
+        ] {
+            let value = Value::parse(input).unwrap();
+            assert_eq!(Value::parse(&value.to_string()).unwrap(), value, "{input}");
+            assert_eq!(Value::parse(&value.to_pretty_string()).unwrap(), value, "{input}");
+        }
+        // Escaped on output: control characters, and line separators that JavaScript can't hold
+        let escaped = r#"["\u0001\u2028\u007f"]"#;
+        assert_eq!(Value::parse(escaped).unwrap().as_array().unwrap()[0].as_str(), Some("\u{1}\u{2028}\u{7f}"));
+        assert_eq!(Value::parse(escaped).unwrap().to_string(), escaped);
+    }
+
+    #[test]
+    fn writes_non_finite_numbers_as_null() {
+        assert_eq!(Value::from(f64::NAN).to_string(), "null");
+        assert_eq!(Value::array([1.0, f64::INFINITY]).to_string(), "[1,null]");
+    }
+
+    #[test]
+    fn pretty_prints() {
+        let value = Value::object([("a", Value::array([1, 2])), ("b", Value::object::<&str, _>([]))]);
+        assert_eq!(value.to_pretty_string(), "{\n  \"a\": [\n    1,\n    2\n  ],\n  \"b\": {}\n}");
+    }
+}
@@ -0,0 +1,144 @@
+use std::path::Path;
+
+/// The media type for content that is not known
+pub const OCTET_STREAM: &str = "application/octet-stream";
+
+/// Media types by file extension, lowercase
+const TYPES: &[(&str, &str)] = &[
+    // Text
+    ("html", "text/html"),
+    ("htm", "text/html"),
+    ("css", "text/css"),
+    ("js", "text/javascript"),
+    ("mjs", "text/javascript"),
+    ("txt", "text/plain"),
+    ("text", "text/plain"),
+    ("md", "text/markdown"),
+    ("csv", "text/csv"),
+    ("tsv", "text/tab-separated-values"),
+    ("xml", "application/xml"),
+    ("json", "application/json"),
+    ("map", "application/json"),
+    ("webmanifest", "application/manifest+json"),
+    ("jsonld", "application/ld+json"),
+    ("yaml", "application/yaml"),
+    ("yml", "application/yaml"),
+    ("ics", "text/calendar"),
+    ("svg", "image/svg+xml"),
+    // Images
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("avif", "image/avif"),
+    ("ico", "image/vnd.microsoft.icon"),
+    ("bmp", "image/bmp"),
+    ("tif", "image/tiff"),
+    ("tiff", "image/tiff"),
+    // Fonts
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+    ("ttf", "font/ttf"),
+    ("otf", "font/otf"),
+    // Audio and video
+    ("mp3", "audio/mpeg"),
+    ("ogg", "audio/ogg"),
+    ("oga", "audio/ogg"),
+    ("wav", "audio/wav"),
+    ("weba", "audio/webm"),
+    ("flac", "audio/flac"),
+    ("mp4", "video/mp4"),
+    ("webm", "video/webm"),
+    ("ogv", "video/ogg"),
+    ("mov", "video/quicktime"),
+    // Documents and archives
+    ("pdf", "application/pdf"),
+    ("zip", "application/zip"),
+    ("gz", "application/gzip"),
+    ("tar", "application/x-tar"),
+    ("7z", "application/x-7z-compressed"),
+    ("wasm", "application/wasm"),
+    ("bin", OCTET_STREAM),
+    ("pb", "application/x-protobuf"),
+    ("doc", "application/msword"),
+    ("docx", "application/vnd.openxmlformats-officedocument.wordprocessingml.document"),
+    ("xls", "application/vnd.ms-excel"),
+    ("xlsx", "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"),
+    ("ppt", "application/vnd.ms-powerpoint"),
+    ("pptx", "application/vnd.openxmlformats-officedocument.presentationml.presentation"),
+    ("odt", "application/vnd.oasis.opendocument.text"),
+    ("epub", "application/epub+zip"),
+    ("rtf", "application/rtf"),
+];
+
+/// The media type for a file extension, without the dot, like `"png"`
+pub fn mime_for_extension(extension: &str) -> Option<&'static str> {
+    let extension = extension.trim_start_matches('.');
+    TYPES.iter()
+        .find(|(ext, _)| ext.eq_ignore_ascii_case(extension))
+        .map(|(_, media_type)| *media_type)
+}
+
+/// The media type for a file, by its extension; `application/octet-stream` when it is not known
+pub fn mime_for_path(path: impl AsRef<Path>) -> &'static str {
+    path.as_ref()
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .and_then(mime_for_extension)
+        .unwrap_or(OCTET_STREAM)
+}
+
+/// Whether a media type is text, and should say which charset it is in
+pub fn is_text(media_type: &str) -> bool {
+    let media_type = media_type.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+    media_type.starts_with("text/")
+        || media_type.ends_with("+json")
+        || media_type.ends_with("+xml")
+        || matches!(media_type.as_str(),
+            "application/json" | "application/xml" | "application/javascript" | "application/yaml" | "image/svg+xml")
+}
+
+/// Adds `; charset=utf-8` to text media types that don't have parameters yet
+pub fn with_charset(media_type: &str) -> String {
+    if is_text(media_type) && !media_type.contains(';') {
+        format!("{media_type}; charset=utf-8")
+    } else {
+        media_type.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_types_by_extension() {
+        assert_eq!(mime_for_extension("png"), Some("image/png"));
+        assert_eq!(mime_for_extension(".PNG"), Some("image/png"));
+        assert_eq!(mime_for_extension("woff2"), Some("font/woff2"));
+        assert_eq!(mime_for_extension("unknown"), None);
+        assert_eq!(mime_for_extension(""), None);
+    }
+
+    #[test]
+    fn finds_types_by_path() {
+        assert_eq!(mime_for_path("static/index.html"), "text/html");
+        assert_eq!(mime_for_path("archive.tar.gz"), "application/gzip");
+        assert_eq!(mime_for_path("Makefile"), OCTET_STREAM);
+        assert_eq!(mime_for_path(".env"), OCTET_STREAM);
+        assert_eq!(mime_for_path("notes.unknown"), OCTET_STREAM);
+    }
+
+    #[test]
+    fn adds_a_charset_to_text() {
+        assert_eq!(with_charset("text/html"), "text/html; charset=utf-8");
+        assert_eq!(with_charset("application/json"), "application/json; charset=utf-8");
+        assert_eq!(with_charset("application/ld+json"), "application/ld+json; charset=utf-8");
+        assert_eq!(with_charset("image/svg+xml"), "image/svg+xml; charset=utf-8");
+        assert_eq!(with_charset("text/plain; charset=latin1"), "text/plain; charset=latin1");
+        assert_eq!(with_charset("image/png"), "image/png");
+        assert!(is_text("Text/CSV"));
+        assert!(!is_text(OCTET_STREAM));
+    }
+}
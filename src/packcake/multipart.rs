@@ -0,0 +1,717 @@
+use std::fmt::{Display, Formatter};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::packcake::body::BodyReader;
+use crate::packcake::form::media_type;
+use crate::packcake::url::percent_decode;
+use crate::packcake::StatusCode;
+
+const READ_SIZE: usize = 16 * 1024;
+
+static TEMP_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Limits for parsing a `multipart/form-data` body
+#[derive(Clone, Debug)]
+pub struct MultipartConfig {
+    memory_limit: usize,
+    part_limit: u64,
+    header_limit: usize,
+    max_parts: usize,
+    body_limit: u64,
+    temp_dir: PathBuf,
+}
+
+impl Default for MultipartConfig {
+    fn default() -> MultipartConfig {
+        MultipartConfig {
+            memory_limit: 64 * 1024,
+            part_limit: 16 * 1024 * 1024,
+            header_limit: 8 * 1024,
+            max_parts: 128,
+            body_limit: 64 * 1024 * 1024,
+            temp_dir: std::env::temp_dir(),
+        }
+    }
+}
+
+impl MultipartConfig {
+    pub fn new() -> MultipartConfig {
+        MultipartConfig::default()
+    }
+
+    /// Parts larger than this many bytes are spooled to a temp file (default 64 KiB)
+    pub fn memory_limit(mut self, bytes: usize) -> MultipartConfig {
+        self.memory_limit = bytes;
+        self
+    }
+
+    /// The largest part that is accepted (default 16 MiB)
+    pub fn part_limit(mut self, bytes: u64) -> MultipartConfig {
+        self.part_limit = bytes;
+        self
+    }
+
+    /// The largest header block of a single part (default 8 KiB)
+    pub fn header_limit(mut self, bytes: usize) -> MultipartConfig {
+        self.header_limit = bytes;
+        self
+    }
+
+    /// The most parts a body may have (default 128)
+    pub fn max_parts(mut self, parts: usize) -> MultipartConfig {
+        self.max_parts = parts;
+        self
+    }
+
+    /// The largest body that is accepted, counting every part with its headers (default 64 MiB)
+    pub fn body_limit(mut self, bytes: u64) -> MultipartConfig {
+        self.body_limit = bytes;
+        self
+    }
+
+    /// Where spooled parts are written (default `std::env::temp_dir()`)
+    pub fn temp_dir(mut self, dir: &str) -> MultipartConfig {
+        self.temp_dir = PathBuf::from(dir);
+        self
+    }
+}
+
+#[derive(Debug)]
+pub enum MultipartError {
+    /// The `Content-Type` is missing or not `multipart/form-data`
+    UnsupportedMediaType(String),
+    MissingBoundary,
+    /// The body does not follow the multipart format
+    Malformed(&'static str),
+    PartTooLarge {
+        name: String,
+        limit: u64,
+    },
+    HeadersTooLarge,
+    TooManyParts {
+        limit: usize,
+    },
+    BodyTooLarge {
+        limit: u64,
+    },
+    Io(std::io::Error),
+}
+
+impl MultipartError {
+    /// The status to answer with: `413 Content Too Large` when a limit was hit, else `400 Bad Request`
+    pub fn status(&self) -> StatusCode {
+        match self {
+            MultipartError::PartTooLarge { .. }
+            | MultipartError::HeadersTooLarge
+            | MultipartError::TooManyParts { .. }
+            | MultipartError::BodyTooLarge { .. } => StatusCode::ContentTooLarge,
+            _ => StatusCode::BadRequest,
+        }
+    }
+}
+
+impl Display for MultipartError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MultipartError::UnsupportedMediaType(media_type) =>
+                write!(f, "Unsupported Content-Type {media_type}, expected multipart/form-data"),
+            MultipartError::MissingBoundary => write!(f, "Missing boundary in Content-Type"),
+            MultipartError::Malformed(reason) => write!(f, "Malformed multipart body: {reason}"),
+            MultipartError::PartTooLarge { name, limit } =>
+                write!(f, "Part [{name}] is larger than {limit} bytes"),
+            MultipartError::HeadersTooLarge => write!(f, "Part headers are too large"),
+            MultipartError::TooManyParts { limit } => write!(f, "Body has more than {limit} parts"),
+            MultipartError::BodyTooLarge { limit } => write!(f, "Body is larger than {limit} bytes"),
+            MultipartError::Io(error) => write!(f, "Could not read multipart body: {error}"),
+        }
+    }
+}
+
+impl From<std::io::Error> for MultipartError {
+    fn from(error: std::io::Error) -> MultipartError {
+        MultipartError::Io(error)
+    }
+}
+
+/// A temp file holding a spooled part; it is deleted when dropped unless persisted
+#[derive(Debug)]
+pub struct TempFile {
+    path: PathBuf,
+    persisted: bool,
+}
+
+impl TempFile {
+    fn create(dir: &Path) -> std::io::Result<(TempFile, File)> {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or(0);
+        let count = TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let path = dir.join(format!("packcake-{}-{count}-{nanos}.part", std::process::id()));
+        let file = OpenOptions::new().write(true).create_new(true).open(&path)?;
+        Ok((TempFile { path, persisted: false }, file))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn open(&self) -> std::io::Result<File> {
+        File::open(&self.path)
+    }
+
+    /// Moves the file to `to`, so it is kept after the request
+    pub fn persist(mut self, to: &str) -> std::io::Result<()> {
+        if std::fs::rename(&self.path, to).is_err() {
+            // Renaming fails across file systems
+            std::fs::copy(&self.path, to)?;
+            std::fs::remove_file(&self.path)?;
+        }
+        self.persisted = true;
+        Ok(())
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if !self.persisted {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Where the content of a part ended up
+#[derive(Debug)]
+pub enum PartData {
+    Memory(Vec<u8>),
+    File(TempFile),
+}
+
+/// A single field or file of a multipart body
+#[derive(Debug)]
+pub struct Part {
+    headers: Vec<(String,String)>,
+    name: String,
+    filename: Option<String>,
+    content_type: Option<String>,
+    size: u64,
+    data: PartData,
+}
+
+impl Part {
+    /// The `name` of the form field
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The `filename` sent with file uploads
+    pub fn filename(&self) -> Option<&String> {
+        self.filename.as_ref()
+    }
+
+    pub fn content_type(&self) -> Option<&String> {
+        self.content_type.as_ref()
+    }
+
+    pub fn header(&self, header: &str) -> Option<&String> {
+        self.headers.iter().find(|(h, _)| h.eq_ignore_ascii_case(header)).map(|(_, v)| v)
+    }
+
+    pub fn headers(&self) -> &Vec<(String,String)> {
+        &self.headers
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.filename.is_some()
+    }
+
+    /// Size of the content in bytes
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn data(&self) -> &PartData {
+        &self.data
+    }
+
+    pub fn into_data(self) -> PartData {
+        self.data
+    }
+
+    /// Reads the content, from memory or from the temp file
+    pub fn bytes(&self) -> std::io::Result<Vec<u8>> {
+        match &self.data {
+            PartData::Memory(bytes) => Ok(bytes.clone()),
+            PartData::File(file) => std::fs::read(file.path()),
+        }
+    }
+
+    /// Reads the content as text, replacing invalid UTF-8
+    pub fn text(&self) -> std::io::Result<String> {
+        Ok(String::from_utf8_lossy(&self.bytes()?).into_owned())
+    }
+
+    /// Saves the content to `to`, moving the temp file when the part was spooled
+    pub fn persist(self, to: &str) -> std::io::Result<()> {
+        match self.data {
+            PartData::Memory(bytes) => std::fs::write(to, bytes),
+            PartData::File(file) => file.persist(to),
+        }
+    }
+}
+
+/// Collects the content of a part, moving it to a temp file once it outgrows the memory limit
+struct PartSink<'a> {
+    config: &'a MultipartConfig,
+    name: &'a str,
+    memory: Vec<u8>,
+    file: Option<(TempFile, File)>,
+    size: u64,
+}
+
+impl PartSink<'_> {
+    fn write(&mut self, data: &[u8]) -> Result<(), MultipartError> {
+        self.size += data.len() as u64;
+        if self.size > self.config.part_limit {
+            return Err(MultipartError::PartTooLarge {
+                name: self.name.to_string(),
+                limit: self.config.part_limit,
+            });
+        }
+        if self.file.is_none() && self.memory.len() + data.len() > self.config.memory_limit {
+            let (temp, mut file) = TempFile::create(&self.config.temp_dir)?;
+            file.write_all(&self.memory)?;
+            self.memory = Vec::new();
+            self.file = Some((temp, file));
+        }
+        match &mut self.file {
+            Some((_, file)) => file.write_all(data)?,
+            None => self.memory.extend_from_slice(data),
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<PartData, MultipartError> {
+        match self.file {
+            Some((temp, mut file)) => {
+                file.flush()?;
+                Ok(PartData::File(temp))
+            }
+            None => Ok(PartData::Memory(self.memory)),
+        }
+    }
+}
+
+#[derive(PartialEq)]
+enum State {
+    Preamble,
+    Parts,
+    Done,
+}
+
+/// A streaming parser over a `multipart/form-data` body
+///
+/// Parts are read one at a time from the connection; iterate it or call `next_part`.
+pub struct Multipart {
+    /// The request's reader, which drains whatever is left after the handler
+    reader: Arc<Mutex<BodyReader>>,
+    delimiter: Vec<u8>,
+    buffer: Vec<u8>,
+    eof: bool,
+    /// Bytes read from the body so far
+    read: u64,
+    /// Parts started so far
+    parts: usize,
+    config: MultipartConfig,
+    state: State,
+}
+
+impl Multipart {
+    pub(crate) fn new(content_type: Option<&String>, reader: Arc<Mutex<BodyReader>>, config: MultipartConfig) -> Result<Multipart, MultipartError> {
+        let content_type = content_type.ok_or(MultipartError::UnsupportedMediaType(String::new()))?;
+        let media_type = media_type(content_type);
+        if media_type != "multipart/form-data" {
+            return Err(MultipartError::UnsupportedMediaType(media_type));
+        }
+        let boundary = header_param(content_type, "boundary").ok_or(MultipartError::MissingBoundary)?;
+        if boundary.is_empty() || boundary.len() > 70 {
+            return Err(MultipartError::MissingBoundary);
+        }
+        Ok(Multipart {
+            reader,
+            delimiter: format!("\r\n--{boundary}").into_bytes(),
+            // The first delimiter has no leading line break, so pretend there was one
+            buffer: b"\r\n".to_vec(),
+            eof: false,
+            read: 0,
+            parts: 0,
+            config,
+            state: State::Preamble,
+        })
+    }
+
+    /// Reads the next part, or `None` after the closing delimiter
+    pub fn next_part(&mut self) -> Result<Option<Part>, MultipartError> {
+        if self.state == State::Preamble {
+            self.skip_to_delimiter()?;
+            self.state = State::Parts;
+        }
+        if self.state == State::Done {
+            return Ok(None);
+        }
+        let part = self.read_part();
+        if part.is_err() {
+            self.state = State::Done;
+        }
+        part
+    }
+
+    fn read_part(&mut self) -> Result<Option<Part>, MultipartError> {
+        // Right after a delimiter: either `--` (the end) or the rest of the line
+        self.fill_to(2)?;
+        if self.buffer.starts_with(b"--") {
+            self.state = State::Done;
+            return Ok(None);
+        }
+        if self.parts == self.config.max_parts {
+            return Err(MultipartError::TooManyParts { limit: self.config.max_parts });
+        }
+        self.parts += 1;
+        let line_end = self.find_or_fill(b"\r\n", self.config.header_limit)?;
+        self.buffer.drain(..line_end + 2);
+
+        self.fill_to(2)?;
+        let headers = if self.buffer.starts_with(b"\r\n") {
+            self.buffer.drain(..2);
+            Vec::new()
+        } else {
+            let headers_end = self.find_or_fill(b"\r\n\r\n", self.config.header_limit)?;
+            let headers = parse_headers(&self.buffer[..headers_end]);
+            self.buffer.drain(..headers_end + 4);
+            headers
+        };
+
+        let disposition = headers.iter()
+            .find(|(h, _)| h.eq_ignore_ascii_case("Content-Disposition"))
+            .map(|(_, v)| v.clone())
+            .ok_or(MultipartError::Malformed("part without Content-Disposition"))?;
+        let name = header_param(&disposition, "name").unwrap_or_default();
+        let filename = header_param(&disposition, "filename*")
+            .and_then(|value| value.split_once("''").map(|(_, encoded)| percent_decode(encoded, false)))
+            .or_else(|| header_param(&disposition, "filename"));
+        let content_type = headers.iter()
+            .find(|(h, _)| h.eq_ignore_ascii_case("Content-Type"))
+            .map(|(_, v)| v.clone());
+
+        let config = self.config.clone();
+        let mut sink = PartSink {
+            config: &config,
+            name: &name,
+            memory: Vec::new(),
+            file: None,
+            size: 0,
+        };
+        self.stream_to_delimiter(|data| sink.write(data))?;
+        let size = sink.size;
+        let data = sink.finish()?;
+        Ok(Some(Part {
+            headers,
+            name,
+            filename,
+            content_type,
+            size,
+            data,
+        }))
+    }
+
+    fn skip_to_delimiter(&mut self) -> Result<(), MultipartError> {
+        self.stream_to_delimiter(|_| Ok(()))
+    }
+
+    /// Passes everything up to the next delimiter to `write`, and consumes the delimiter
+    fn stream_to_delimiter<F>(&mut self, mut write: F) -> Result<(), MultipartError>
+    where F: FnMut(&[u8]) -> Result<(), MultipartError> {
+        loop {
+            if let Some(index) = find(&self.buffer, &self.delimiter) {
+                write(&self.buffer[..index])?;
+                self.buffer.drain(..index + self.delimiter.len());
+                return Ok(());
+            }
+            // Keep enough of the tail to match a delimiter split across reads
+            let keep = self.delimiter.len() - 1;
+            if self.buffer.len() > keep {
+                let flush = self.buffer.len() - keep;
+                write(&self.buffer[..flush])?;
+                self.buffer.drain(..flush);
+            }
+            if !self.fill()? {
+                return Err(MultipartError::Malformed("body ended before the closing delimiter"));
+            }
+        }
+    }
+
+    /// Finds `needle` in the buffer, reading until it shows up within `limit` bytes
+    fn find_or_fill(&mut self, needle: &[u8], limit: usize) -> Result<usize, MultipartError> {
+        loop {
+            if let Some(index) = find(&self.buffer, needle) {
+                return Ok(index);
+            }
+            if self.buffer.len() > limit {
+                return Err(MultipartError::HeadersTooLarge);
+            }
+            if !self.fill()? {
+                return Err(MultipartError::Malformed("body ended inside part headers"));
+            }
+        }
+    }
+
+    fn fill_to(&mut self, length: usize) -> Result<(), MultipartError> {
+        while self.buffer.len() < length {
+            if !self.fill()? {
+                return Err(MultipartError::Malformed("body ended after a delimiter"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads more of the body into the buffer, returning false at the end of the body
+    fn fill(&mut self) -> Result<bool, MultipartError> {
+        if self.eof {
+            return Ok(false);
+        }
+        let mut chunk = [0; READ_SIZE];
+        let read = self.reader.lock().unwrap_or_else(|error| error.into_inner()).read(&mut chunk)?;
+        if read == 0 {
+            self.eof = true;
+            return Ok(false);
+        }
+        self.read += read as u64;
+        if self.read > self.config.body_limit {
+            return Err(MultipartError::BodyTooLarge { limit: self.config.body_limit });
+        }
+        self.buffer.extend_from_slice(&chunk[..read]);
+        Ok(true)
+    }
+}
+
+impl Iterator for Multipart {
+    type Item = Result<Part, MultipartError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_part().transpose()
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.len() > haystack.len() {
+        return None;
+    }
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+fn parse_headers(block: &[u8]) -> Vec<(String,String)> {
+    String::from_utf8_lossy(block)
+        .split("\r\n")
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect()
+}
+
+/// Gets a `key=value` parameter from a header value like `form-data; name="file"; filename="a.txt"`
+pub(crate) fn header_param(header: &str, key: &str) -> Option<String> {
+    let mut rest = header.split_once(';')?.1;
+    loop {
+        let (name, after_name) = rest.split_once('=')?;
+        let after_name = after_name.trim_start();
+        let (value, after_value) = if let Some(quoted) = after_name.strip_prefix('"') {
+            let mut value = String::new();
+            let mut chars = quoted.char_indices();
+            let mut end = quoted.len();
+            while let Some((i, c)) = chars.next() {
+                match c {
+                    '\\' => {
+                        if let Some((_, escaped)) = chars.next() {
+                            value.push(escaped);
+                        }
+                    }
+                    '"' => {
+                        end = i + 1;
+                        break;
+                    }
+                    c => value.push(c),
+                }
+            }
+            (value, &quoted[end..])
+        } else {
+            let end = after_name.find(';').unwrap_or(after_name.len());
+            (after_name[..end].trim().to_string(), &after_name[end..])
+        };
+        if name.trim().eq_ignore_ascii_case(key) {
+            return Some(value);
+        }
+        rest = after_value.split_once(';')?.1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTENT_TYPE: &str = "multipart/form-data; boundary=XyZ";
+
+    /// Hands out at most `step` bytes per read, so delimiters get split across reads
+    struct Trickle {
+        data: Vec<u8>,
+        position: usize,
+        step: usize,
+    }
+
+    impl Read for Trickle {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let end = (self.position + self.step.min(buf.len())).min(self.data.len());
+            let read = end - self.position;
+            buf[..read].copy_from_slice(&self.data[self.position..end]);
+            self.position = end;
+            Ok(read)
+        }
+    }
+
+    fn reader(body: &[u8], step: usize) -> Arc<Mutex<BodyReader>> {
+        let trickle = Trickle { data: body.to_vec(), position: 0, step };
+        Arc::new(Mutex::new(BodyReader::new(Box::new(trickle), body.len() as u64)))
+    }
+
+    fn multipart(body: &[u8], step: usize, config: MultipartConfig) -> Result<Multipart, MultipartError> {
+        Multipart::new(Some(&CONTENT_TYPE.to_string()), reader(body, step), config)
+    }
+
+    fn body() -> Vec<u8> {
+        b"preamble\r\n--XyZ\r\n\
+          Content-Disposition: form-data; name=\"title\"\r\n\r\n\
+          Hello\r\n--XyZ\r\n\
+          Content-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\
+          Content-Type: text/plain\r\n\r\n\
+          line one\r\n--XyY\r\nline two\r\n--XyZ--\r\n".to_vec()
+    }
+
+    #[test]
+    fn reads_parts() {
+        for step in [1, 2, 3, 7, READ_SIZE] {
+            let parts = multipart(&body(), step, MultipartConfig::new()).unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            assert_eq!(parts.len(), 2, "step {step}");
+            assert_eq!(parts[0].name(), "title");
+            assert_eq!(parts[0].text().unwrap(), "Hello");
+            assert!(!parts[0].is_file());
+            assert_eq!(parts[1].filename().map(String::as_str), Some("a.txt"));
+            assert_eq!(parts[1].content_type().map(String::as_str), Some("text/plain"));
+            assert_eq!(parts[1].text().unwrap(), "line one\r\n--XyY\r\nline two");
+        }
+    }
+
+    #[test]
+    fn spools_large_parts_to_a_file() {
+        let config = MultipartConfig::new().memory_limit(8);
+        let mut parts = multipart(&body(), 3, config).unwrap();
+        let title = parts.next().unwrap().unwrap();
+        assert!(matches!(title.data(), PartData::Memory(_)));
+        let file = parts.next().unwrap().unwrap();
+        let PartData::File(temp) = file.data() else {
+            panic!("part was not spooled");
+        };
+        let path = temp.path().to_path_buf();
+        assert!(path.exists());
+        assert_eq!(file.size(), 25);
+        assert_eq!(file.bytes().unwrap(), b"line one\r\n--XyY\r\nline two");
+        drop(file);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn enforces_limits() {
+        let mut parts = multipart(&body(), 5, MultipartConfig::new().part_limit(10)).unwrap();
+        assert!(parts.next().unwrap().is_ok());
+        assert!(matches!(parts.next(), Some(Err(MultipartError::PartTooLarge { limit: 10, .. }))));
+        assert!(parts.next().is_none());
+
+        let mut parts = multipart(&body(), 5, MultipartConfig::new().header_limit(16)).unwrap();
+        assert!(matches!(parts.next(), Some(Err(MultipartError::HeadersTooLarge))));
+    }
+
+    #[test]
+    fn limits_the_number_of_parts() {
+        let mut parts = multipart(&body(), 7, MultipartConfig::new().max_parts(1)).unwrap();
+        assert!(parts.next().unwrap().is_ok());
+        let error = parts.next().unwrap().unwrap_err();
+        assert!(matches!(error, MultipartError::TooManyParts { limit: 1 }));
+        assert_eq!(error.status(), StatusCode::ContentTooLarge);
+        assert!(parts.next().is_none());
+        assert_eq!(multipart(&body(), 7, MultipartConfig::new().max_parts(2)).unwrap().count(), 2);
+
+        // Many empty parts
+        let mut many = Vec::new();
+        for _ in 0..1000 {
+            many.extend_from_slice(b"--XyZ\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\n\r\n");
+        }
+        many.extend_from_slice(b"--XyZ--\r\n");
+        let results = multipart(&many, READ_SIZE, MultipartConfig::new()).unwrap().collect::<Vec<_>>();
+        assert_eq!(results.len(), 129);
+        assert!(matches!(results.last(), Some(Err(MultipartError::TooManyParts { limit: 128 }))));
+    }
+
+    #[test]
+    fn limits_the_whole_body() {
+        let mut parts = multipart(&body(), 16, MultipartConfig::new().body_limit(100)).unwrap();
+        assert!(parts.next().unwrap().is_ok());
+        let error = parts.next().unwrap().unwrap_err();
+        assert!(matches!(error, MultipartError::BodyTooLarge { limit: 100 }));
+        assert_eq!(error.status(), StatusCode::ContentTooLarge);
+
+        let exact = body().len() as u64;
+        assert_eq!(multipart(&body(), 16, MultipartConfig::new().body_limit(exact)).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn rejects_malformed_bodies() {
+        let unterminated = b"--XyZ\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\nno end";
+        let mut parts = multipart(unterminated, 4, MultipartConfig::new()).unwrap();
+        assert!(matches!(parts.next(), Some(Err(MultipartError::Malformed(_)))));
+
+        let no_disposition = b"--XyZ\r\nContent-Type: text/plain\r\n\r\nx\r\n--XyZ--";
+        let mut parts = multipart(no_disposition, 4, MultipartConfig::new()).unwrap();
+        let error = parts.next().unwrap().unwrap_err();
+        assert!(matches!(error, MultipartError::Malformed(_)));
+        assert_eq!(error.status(), StatusCode::BadRequest);
+    }
+
+    #[test]
+    fn checks_the_content_type() {
+        let config = MultipartConfig::new;
+        let form = "application/x-www-form-urlencoded".to_string();
+        assert!(matches!(Multipart::new(Some(&form), reader(b"", 1), config()), Err(MultipartError::UnsupportedMediaType(_))));
+        assert!(matches!(Multipart::new(None, reader(b"", 1), config()), Err(MultipartError::UnsupportedMediaType(_))));
+        let no_boundary = "multipart/form-data".to_string();
+        assert!(matches!(Multipart::new(Some(&no_boundary), reader(b"", 1), config()), Err(MultipartError::MissingBoundary)));
+    }
+
+    #[test]
+    fn leaves_the_rest_to_the_request() {
+        let shared = reader(&body(), 16);
+        let mut parts = Multipart::new(Some(&CONTENT_TYPE.to_string()), Arc::clone(&shared), MultipartConfig::new()).unwrap();
+        assert_eq!(parts.next().unwrap().unwrap().name(), "title");
+        drop(parts);
+        let mut reader = shared.lock().unwrap();
+        assert!(reader.drain().unwrap() > 0);
+        assert_eq!(reader.drain().unwrap(), 0);
+    }
+
+    #[test]
+    fn reads_header_params() {
+        let disposition = "form-data; name=\"a \\\"b\\\"\"; filename=c.txt";
+        assert_eq!(header_param(disposition, "name").as_deref(), Some("a \"b\""));
+        assert_eq!(header_param(disposition, "filename").as_deref(), Some("c.txt"));
+        assert_eq!(header_param(disposition, "missing"), None);
+    }
+}
@@ -1,9 +1,77 @@
+use std::cell::OnceCell;
 use std::collections::{HashMap};
-use std::io::{BufRead, BufReader, Read, Write};
-use std::net::{TcpListener, TcpStream};
+use std::io::{BufRead, BufReader, Cursor, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use crate::packcake::body::BodyReader;
+use crate::packcake::etag::Validators;
+use crate::packcake::range::RangeRequest;
 use crate::packcake::tp::ThreadPool;
 
 #[path = "./thread_pool.rs"] mod tp;
+#[path = "./url.rs"] mod url;
+#[path = "./params.rs"] mod params;
+#[path = "./date.rs"] mod date;
+#[path = "./cookie.rs"] mod cookie;
+#[path = "./form.rs"] mod form;
+#[path = "./body.rs"] mod body;
+#[path = "./multipart.rs"] mod multipart;
+#[path = "./json.rs"] mod json;
+#[path = "./extract.rs"] mod extract;
+#[path = "./handler.rs"] mod handler;
+#[cfg(test)]
+#[path = "./testing.rs"] mod testing;
+#[path = "./respond.rs"] mod respond;
+#[path = "./connection.rs"] mod connection;
+#[path = "./proxy.rs"] mod proxy;
+#[path = "./proxy_protocol.rs"] mod proxy_protocol;
+#[path = "./status.rs"] mod status;
+#[path = "./stream.rs"] mod stream;
+#[path = "./mime.rs"] mod mime;
+#[path = "./sse.rs"] mod sse;
+#[path = "./base64.rs"] mod base64;
+#[path = "./sha1.rs"] mod sha1;
+#[path = "./ws.rs"] mod ws;
+#[path = "./static_dir.rs"] mod static_dir;
+#[path = "./range.rs"] mod range;
+#[path = "./embed.rs"] mod embed;
+#[cfg(test)]
+#[path = "./embed_build.rs"] mod embed_build;
+#[path = "./deflate.rs"] mod deflate;
+#[path = "./compress.rs"] mod compress;
+#[path = "./etag.rs"] mod etag;
+
+pub use url::{Params, percent_decode};
+pub use params::{ParamError, ParamLocation, ParamSpec};
+#[allow(unused_imports)]
+pub use cookie::{Cookie, CookieError, SameSite};
+pub use form::FormError;
+#[allow(unused_imports)]
+pub use multipart::{Multipart, MultipartConfig, MultipartError, Part, PartData, TempFile};
+pub use json::{JsonError, Value};
+#[allow(unused_imports)]
+pub use extract::{BearerToken, Form, FromHeader, FromJson, FromParams, FromRequest, Header, Json, Query, Rejection, UserAgent};
+pub use handler::{Handler, IntoHandler};
+#[allow(unused_imports)]
+pub use respond::{IntoResponse, Redirect, ResponseError};
+pub use connection::ConnectionInfo;
+pub use proxy::{Cidr, ProxyHeaders};
+pub use status::StatusCode;
+#[allow(unused_imports)]
+pub use status::CustomStatus;
+pub use stream::BodyWriter;
+#[allow(unused_imports)]
+pub use mime::{mime_for_extension, mime_for_path};
+pub use sse::{Event, EventStream};
+#[allow(unused_imports)]
+pub use ws::{CloseFrame, Message, WebSocket, WsError};
+pub use static_dir::StaticDir;
+#[allow(unused_imports)]
+pub use embed::{EmbeddedDir, EmbeddedEntry, EmbeddedFile};
+#[allow(unused_imports)]
+pub use compress::{Compression, Encoding};
 
 // Consts
 #[allow(dead_code)]
@@ -17,25 +85,6 @@ pub const PATCH: &str    = "PATCH";
 #[allow(dead_code)]
 pub const DELETE: &str   = "DELETE";
 
-// Status codes
-pub enum StatusCode {
-    Ok,
-    Created,
-    NotFound,
-    BadRequest,
-}
-
-impl StatusCode {
-    fn to_str(&self) -> &str {
-        match self {
-            StatusCode::Ok => "HTTP/1.1 200 OK",
-            StatusCode::Created => "HTTP/1.1 201 CREATED",
-            StatusCode::NotFound => "HTTP/1.1 404 NOT FOUND",
-            StatusCode::BadRequest => "HTTP/1.1 400 BAD REQUEST",
-        }
-    }
-}
-
 #[derive(Clone, Debug)]
 pub struct Middleware {
     action: fn(&Request, &mut Response) -> bool,
@@ -56,14 +105,68 @@ impl Middleware {
 pub struct Endpoint {
     method: String,
     uri: String,
-    handler: fn(&Request, &mut Response),
+    handler: Handler,
     middleware: Option<Vec<Middleware>>,
+    params: Vec<ParamSpec>,
 }
 
 impl Endpoint {
     fn set_middleware(&mut self, middleware: Option<Vec<Middleware>>) {
         self.middleware = middleware;
     }
+
+    #[allow(dead_code)]
+    /// Requires a query parameter that parses as `T`, e.g. `.require_query::<u64>("user_id")`
+    pub fn require_query<T: FromStr>(mut self, name: &str) -> Endpoint {
+        self.params.push(ParamSpec::new::<T>(ParamLocation::Query, name));
+        self
+    }
+
+    #[allow(dead_code)]
+    /// Requires a path parameter (`/user/:id`) that parses as `T`
+    pub fn require_path<T: FromStr>(mut self, name: &str) -> Endpoint {
+        self.params.push(ParamSpec::new::<T>(ParamLocation::Path, name));
+        self
+    }
+
+    #[allow(dead_code)]
+    /// Requires a header that parses as `T`
+    pub fn require_header<T: FromStr>(mut self, name: &str) -> Endpoint {
+        self.params.push(ParamSpec::new::<T>(ParamLocation::Header, name));
+        self
+    }
+
+    /// Matches `path` against the uri of the endpoint, where `:name` segments capture path parameters
+    fn match_path(&self, path: &str) -> Option<HashMap<String,String>> {
+        let pattern: Vec<&str> = self.uri.split('/').collect();
+        let segments: Vec<&str> = path.split('/').collect();
+        if pattern.len() != segments.len() {
+            return None;
+        }
+        let mut path_params = HashMap::new();
+        for (expected, actual) in pattern.iter().zip(segments) {
+            if let Some(name) = expected.strip_prefix(':') {
+                path_params.insert(name.to_string(), percent_decode(actual, false));
+            } else if *expected != percent_decode(actual, false) {
+                return None;
+            }
+        }
+        Some(path_params)
+    }
+
+    /// Checks the required parameters of an endpoint against the request
+    fn validate_params(params: &[ParamSpec], request: &Request) -> Vec<ParamError> {
+        params.iter()
+            .filter_map(|spec| {
+                let value = match spec.location {
+                    ParamLocation::Query => request.get_param(&spec.name),
+                    ParamLocation::Path => request.get_path_param(&spec.name),
+                    ParamLocation::Header => request.get_header(&spec.name),
+                };
+                spec.validate(value)
+            })
+            .collect()
+    }
 }
 
 // Endpoint Group
@@ -124,147 +227,641 @@ impl Group {
 // Request
 pub struct Request {
     method: String,
+    version: String,
     uri: String,
-    params: HashMap<String,String>,
+    raw_uri: String,
+    query: String,
+    params: Params,
+    path_params: HashMap<String,String>,
     headers: HashMap<String,String>,
-    body: String,
+    cookies: HashMap<String,String>,
+    connection: ConnectionInfo,
+    client_ip: IpAddr,
+    scheme: String,
+    host: Option<String>,
+    /// Shared with a `Multipart` reading the body, so what it leaves can still be discarded
+    body_reader: Arc<Mutex<BodyReader>>,
+    body_bytes: OnceCell<Vec<u8>>,
+    body: OnceCell<String>,
+    /// Set when the `Content-Length` headers are not a number, or disagree
+    bad_content_length: bool,
 }
 
 impl Request {
+    #[allow(dead_code)]
     pub(crate) fn new(line: &str, headers: HashMap<String,String>, body: String) -> Request {
+        Request::with_body_reader(line, headers, BodyReader::from_bytes(body.into_bytes()))
+    }
+
+    fn with_body_reader(line: &str, headers: HashMap<String,String>, body_reader: BodyReader) -> Request {
         //println!("{}",line);
-        let split: Vec<&str> = line.split(" ").collect();
-        let method = split.get(0).unwrap().to_owned();
-        let path_split: Vec<&str> = split.get(1).unwrap().to_owned().split("?").collect();
-        let path = path_split.get(0).unwrap().to_owned();
-        let mut params: HashMap<_,_> = HashMap::new();
-        if path_split.len() >= 2 {
-            let queries: Vec<&str> = path_split.get(1).unwrap().to_owned().split("&").collect();
-            for query in queries {
-                let query_split: Vec<&str> = query.split("=").collect();
-                if query_split.len() >= 2 {
-                    let query_name = query_split.get(0).unwrap().to_owned().to_owned();
-                    let query_value = query_split.get(1).unwrap().to_owned().to_owned();
-                    //println!("?{query_name}={query_value}");
-                    params.insert(query_name, query_value);
-                }
-            }
-        }
+        let mut split = line.split(' ');
+        let method = split.next().unwrap_or_default();
+        let target = split.next().unwrap_or_default();
+        let version = match split.next() {
+            Some("HTTP/1.0") => "HTTP/1.0",
+            _ => "HTTP/1.1",
+        };
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let cookies = headers.iter()
+            .filter(|(key, _)| key.eq_ignore_ascii_case("Cookie"))
+            .map(|(_, value)| cookie::parse_cookie_header(value))
+            .next()
+            .unwrap_or_default();
 
         Request {
             method: method.to_string(),
-            uri: path.to_string(),
-            params,
+            version: version.to_string(),
+            uri: percent_decode(path, false),
+            raw_uri: path.to_string(),
+            query: query.to_string(),
+            params: Params::parse(query),
+            path_params: HashMap::new(),
             headers,
-            body
+            cookies,
+            connection: ConnectionInfo::default(),
+            client_ip: ConnectionInfo::default().peer_addr().ip(),
+            scheme: String::from("http"),
+            host: None,
+            body_reader: Arc::new(Mutex::new(body_reader)),
+            body_bytes: OnceCell::new(),
+            body: OnceCell::new(),
+            bad_content_length: false,
         }
     }
 
-    fn from_stream(stream: &TcpStream) -> Option<Request> {
+    /// Reads the request line and headers; the body is left on the stream until it is asked for
+    fn from_stream(stream: &TcpStream, mut reader: BufReader<TcpStream>, connection: ConnectionInfo) -> Option<Request> {
         let mut header_map = HashMap::<String,String>::new();
-        let mut reader = BufReader::new(stream.try_clone().unwrap());
         let mut request = String::new();
         loop {
-            let r = reader.read_line(&mut request).unwrap();
+            let r = reader.read_line(&mut request).unwrap_or(0);
             if r < 3 { //detect empty line
                 break;
             }
         }
-        let mut content_length = 0;
+        let mut content_lengths = Vec::new();
         let mut headers = request.split("\n");
-        let request_line = headers.next().unwrap();
+        let request_line = headers.next().unwrap_or_default().trim();
         for header in headers {
-            if header.len() > 3 {
-                let mut split = header.split(":");
-                let (key, value) =
-                    (String::from(split.next().unwrap()), String::from(split.next().unwrap_or_default().trim()));
-                header_map.insert(key,value);
-            }
-
-            if header.starts_with("Content-Length") {
-                let line = header.split(":");
-                for side in line {
-                    if !(side.starts_with("Content-Length")) {
-                        content_length = side.trim().parse::<usize>().unwrap(); //Get Content-Length
-                    }
+            if let Some((key, value)) = header.split_once(':') {
+                let (key, value) = (key.trim(), value.trim());
+                if key.eq_ignore_ascii_case("Content-Length") {
+                    content_lengths.push(value);
                 }
+                header_map.insert(key.to_string(), value.to_string());
             }
         }
 
-        let mut body_buffer = vec![0; content_length]; //New Vector with size of Content
-        reader.read_exact(&mut body_buffer).unwrap(); //Get the Body Content.
-        let body = String::from_utf8(body_buffer).unwrap(); //Convert to body string
         if request_line.is_empty() {
             return None;
         }
-        return Some(Request::new(request_line, header_map, body));
+        let content_length = body::parse_content_length(&content_lengths);
+        let mut body_reader = BodyReader::new(Box::new(reader), content_length.unwrap_or(0));
+        // HTTP/1.0 clients don't know 100 Continue, so Expect is ignored for them
+        let expects_continue = header_map.iter()
+            .any(|(key, value)| key.eq_ignore_ascii_case("Expect") && value.eq_ignore_ascii_case("100-continue"));
+        if expects_continue && !request_line.ends_with(" HTTP/1.0") {
+            body_reader.expect_continue(stream.try_clone().unwrap());
+        }
+        let mut request = Request::with_body_reader(request_line, header_map, body_reader);
+        request.client_ip = connection.client_addr().ip();
+        request.host = request.get_header("Host").cloned();
+        request.connection = connection;
+        // The body can't be told apart from what follows it, so it is never read
+        request.bad_content_length = content_length.is_none();
+        Some(request)
     }
 
     #[allow(dead_code)]
     pub(crate) fn display(&self) {
-        println!("Request:\r\nMethod: {:#?},\r\nURI: {:#?},\r\nParams: {:#?},\r\nHeaders: {:#?},\r\nBody: {:#?}",
-                 self.method, self.uri, self.params, self.headers, self.body);
+        println!("Request:\r\nMethod: {:#?},\r\nURI: {:#?},\r\nParams: {:#?},\r\nHeaders: {:#?},\r\nConnection: {:#?},\r\nBody: {:#?}",
+                 self.method, self.uri, self.params, self.headers, self.connection, self.body.get());
+    }
+
+    #[allow(dead_code)]
+    /// Details about the connection the request arrived on
+    pub fn connection(&self) -> &ConnectionInfo {
+        &self.connection
+    }
+
+    #[allow(dead_code)]
+    /// The address of the client that sent the request
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.connection.peer_addr()
+    }
+
+    #[allow(dead_code)]
+    /// The address of the client, looking past trusted proxies
+    ///
+    /// This is the peer address, unless the peer is one of `Packcake::trusted_proxies`.
+    pub fn client_ip(&self) -> IpAddr {
+        self.client_ip
+    }
+
+    #[allow(dead_code)]
+    /// `http`, or the scheme the client used as told by a trusted proxy
+    pub fn scheme(&self) -> &str {
+        &self.scheme
+    }
+
+    #[allow(dead_code)]
+    /// The `Host` header, or the host the client used as told by a trusted proxy
+    pub fn host(&self) -> Option<&String> {
+        self.host.as_ref()
     }
 
+    /// Applies the `headers` the proxies write if the peer is a trusted proxy
+    fn resolve_forwarding(&mut self, trusted_proxies: &[Cidr], headers: ProxyHeaders) {
+        if trusted_proxies.is_empty() {
+            return;
+        }
+        let forwarding = proxy::resolve(self.client_ip, trusted_proxies, headers, |header| self.get_header(header));
+        self.client_ip = forwarding.client_ip;
+        if let Some(scheme) = forwarding.scheme {
+            self.scheme = scheme;
+        }
+        if forwarding.host.is_some() {
+            self.host = forwarding.host;
+        }
+    }
+
+    /// Gets a header; names are matched case-insensitively
     pub fn get_header(&self, header: &str) -> Option<&String> {
-        self.headers.get(header)
+        self.headers.get(header).or_else(|| self.headers.iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(header))
+            .map(|(_, value)| value))
+    }
+
+    #[allow(dead_code)]
+    /// The id of the last Server-Sent Event the client got, sent when it reconnects to an event stream
+    pub fn last_event_id(&self) -> Option<&String> {
+        self.get_header("Last-Event-ID")
     }
 
+    #[allow(dead_code)]
+    /// Gets a cookie sent in the `Cookie` header
+    pub fn get_cookie(&self, name: &str) -> Option<&String> {
+        self.cookies.get(name)
+    }
+
+    #[allow(dead_code)]
+    pub fn get_cookies(&self) -> &HashMap<String,String> {
+        &self.cookies
+    }
+
+    /// Gets the first value of a query parameter; flags without a value (`?verbose`) give `""`
     pub fn get_param(&self, param: &str) -> Option<&String> {
         self.params.get(param)
     }
 
+    #[allow(dead_code)]
+    /// Gets a query parameter parsed as `T`, e.g. `request.get_param_as::<u64>("user_id")`
+    pub fn get_param_as<T: FromStr>(&self, param: &str) -> Result<T, ParamError> {
+        ParamError::check(ParamLocation::Query, param, self.get_param(param))
+    }
+
+    #[allow(dead_code)]
+    /// Gets a path parameter, captured by a `:name` segment in the endpoint uri
+    pub fn get_path_param(&self, param: &str) -> Option<&String> {
+        self.path_params.get(param)
+    }
+
+    #[allow(dead_code)]
+    pub fn get_path_param_as<T: FromStr>(&self, param: &str) -> Result<T, ParamError> {
+        ParamError::check(ParamLocation::Path, param, self.get_path_param(param))
+    }
+
+    #[allow(dead_code)]
+    pub fn get_header_as<T: FromStr>(&self, header: &str) -> Result<T, ParamError> {
+        ParamError::check(ParamLocation::Header, header, self.get_header(header))
+    }
+
+    #[allow(dead_code)]
+    /// Gets every value of a repeated query parameter (`?tag=a&tag=b`)
+    pub fn get_params(&self, param: &str) -> Vec<&String> {
+        self.params.get_all(param)
+    }
+
+    #[allow(dead_code)]
+    pub fn has_param(&self, param: &str) -> bool {
+        self.params.contains(param)
+    }
+
+    #[allow(dead_code)]
+    /// All decoded query parameters, in order
+    pub fn get_query_params(&self) -> &Params {
+        &self.params
+    }
+
+    #[allow(dead_code)]
+    /// The query string as it was received, without the leading `?`
+    pub fn get_query(&self) -> &str {
+        &self.query
+    }
+
+    #[allow(dead_code)]
+    pub fn get_method(&self) -> &str {
+        &self.method
+    }
+
+    #[allow(dead_code)]
+    /// The HTTP version of the request, `HTTP/1.0` or `HTTP/1.1`
+    pub fn get_version(&self) -> &str {
+        &self.version
+    }
+
+    #[allow(dead_code)]
+    /// The decoded path of the request
+    pub fn get_path(&self) -> &str {
+        &self.uri
+    }
+
+    #[allow(dead_code)]
+    /// The path as it was received, before percent-decoding
+    pub fn get_raw_path(&self) -> &str {
+        &self.raw_uri
+    }
+
+    /// Gets the body as text, reading it from the connection the first time
+    ///
+    /// Invalid UTF-8 is replaced; use `get_body_bytes` for binary bodies.
     pub fn get_body(&self) -> &String {
-        &self.body
+        self.body.get_or_init(|| String::from_utf8_lossy(self.get_body_bytes()).into_owned())
+    }
+
+    /// Gets the body as bytes, reading it from the connection the first time
+    pub fn get_body_bytes(&self) -> &Vec<u8> {
+        self.body_bytes.get_or_init(|| {
+            let mut body = Vec::new();
+            if let Err(error) = self.body_reader().read_to_end(&mut body) {
+                println!("Could not read request body: {error}");
+            }
+            body
+        })
+    }
+
+    #[allow(dead_code)]
+    /// Parses an `application/x-www-form-urlencoded` body, decoded the same way as the query string
+    pub fn form(&self) -> Result<Params, FormError> {
+        form::parse_form(self.get_header("Content-Type"), self.get_body())
+    }
+
+    #[allow(dead_code)]
+    /// Parses the body as JSON
+    pub fn json(&self) -> Result<Value, JsonError> {
+        Value::parse(self.get_body())
+    }
+
+    #[allow(dead_code)]
+    /// Parses a `multipart/form-data` body with the default limits
+    pub fn multipart(&self) -> Result<Multipart, MultipartError> {
+        self.multipart_with(MultipartConfig::default())
+    }
+
+    #[allow(dead_code)]
+    /// Parses a `multipart/form-data` body part by part, while it is read from the connection
+    ///
+    /// Reads the unread body, so `get_body` is empty afterwards. Parts the handler does not read
+    /// are discarded once it returns.
+    pub fn multipart_with(&self, config: MultipartConfig) -> Result<Multipart, MultipartError> {
+        Multipart::new(self.get_header("Content-Type"), Arc::clone(&self.body_reader), config)
+    }
+
+    fn body_reader(&self) -> MutexGuard<'_, BodyReader> {
+        // A handler that panicked while reading leaves a reader that is still usable
+        self.body_reader.lock().unwrap_or_else(|error| error.into_inner())
+    }
+
+    /// Whether the request has an `Expect` header other than `100-continue`
+    fn has_unmet_expectation(&self) -> bool {
+        self.get_header("Expect").is_some_and(|expect| !expect.eq_ignore_ascii_case("100-continue"))
+    }
+
+    /// Whether the `Content-Length` headers are not a number, or disagree
+    fn has_bad_content_length(&self) -> bool {
+        self.bad_content_length
+    }
+
+    /// Reads and discards whatever the handler left of the body, so the connection closes cleanly
+    ///
+    /// A body the client is still holding back for `100 Continue` is left alone.
+    fn discard_body(&self) {
+        let _ = self.body_reader().drain();
     }
 }
 
 // Response
+/// The response to a request
+///
+/// The response is buffered, and written to the connection once, after the handler returns.
 pub struct Response {
     stream: TcpStream,
+    version: String,
+    head: bool,
     status: StatusCode,
-    headers: HashMap<String,String>,
+    headers: Vec<(String,String)>,
+    body: Option<Vec<u8>>,
+    /// Set once the handler starts streaming the body with `stream_body`
+    streamed: bool,
+    /// The range the client asked for, for a `GET` request with a `Range` header
+    range: Option<RangeRequest>,
+    /// Whether the body may be sent in ranges; set by `send_bytes`
+    accept_ranges: bool,
+    /// Set when compression is enabled on the server
+    compression: Option<Arc<Compression>>,
+    accept_encoding: Option<String>,
+    /// Whether buffered bodies get an `ETag`; set when the layer is enabled on the server
+    auto_etag: bool,
+    /// The conditional headers of a `GET` or `HEAD` request
+    validators: Option<Validators>,
 }
 
 impl Response {
-    fn from_stream(stream: TcpStream) -> Response {
+    /// A response to `request`, answering in the HTTP version of the request
+    fn from_stream(stream: TcpStream, request: &Request) -> Response {
         Response {
             stream,
+            version: request.version.clone(),
+            head: request.method == "HEAD",
             status: StatusCode::Ok,
-            headers: HashMap::<String,String>::new(),
+            headers: Vec::new(),
+            body: None,
+            streamed: false,
+            range: request.get_header("Range")
+                .filter(|_| request.method == "GET")
+                .map(|range| RangeRequest {
+                    range: range.clone(),
+                    if_range: request.get_header("If-Range").cloned(),
+                }),
+            accept_ranges: false,
+            compression: None,
+            accept_encoding: request.get_header("Accept-Encoding").cloned(),
+            auto_etag: false,
+            validators: (request.method == "GET" || request.method == "HEAD").then(|| Validators::from_request(request)),
         }
     }
 
+    /// Sets a header, replacing any earlier values with the same name
+    ///
+    /// A header whose name isn't a token, or whose value has a line break or another
+    /// control character, would end the header line early; it is logged and ignored.
     pub fn header(&mut self, header: &str, value: &str) {
-        self.headers.insert(header.to_string(), value.to_string());
+        if !is_valid_header(header, value) {
+            println!("Ignoring header {header:?} with an invalid name or value {value:?}");
+            return;
+        }
+        self.headers.retain(|(h, _)| !h.eq_ignore_ascii_case(header));
+        self.headers.push((header.to_string(), value.to_string()));
+    }
+
+    #[allow(dead_code)]
+    /// Adds a header, keeping any earlier values with the same name; invalid headers are
+    /// ignored the same way as in `header`
+    pub fn append_header(&mut self, header: &str, value: &str) {
+        if !is_valid_header(header, value) {
+            println!("Ignoring header {header:?} with an invalid name or value {value:?}");
+            return;
+        }
+        self.headers.push((header.to_string(), value.to_string()));
+    }
+
+    #[allow(dead_code)]
+    /// Removes a header that has been set on the response
+    pub fn remove_header(&mut self, header: &str) {
+        self.headers.retain(|(h, _)| !h.eq_ignore_ascii_case(header));
+    }
+
+    #[allow(dead_code)]
+    /// Gets a header that has been set on the response
+    pub fn get_header(&self, header: &str) -> Option<&String> {
+        self.headers.iter().find(|(h, _)| h.eq_ignore_ascii_case(header)).map(|(_, v)| v)
+    }
+
+    #[allow(dead_code)]
+    /// Adds a `Set-Cookie` header; call it once per cookie
+    pub fn cookie(&mut self, cookie: Cookie) {
+        self.append_header("Set-Cookie", &cookie.to_string());
     }
 
     pub fn status(&mut self, status: StatusCode) {
         self.status = status;
     }
 
+    #[allow(dead_code)]
+    pub fn get_status(&self) -> &StatusCode {
+        &self.status
+    }
+
+    /// Sets the body of the response; it is written once the handler returns
+    ///
+    /// A response can only be sent once. Later calls are ignored and logged; use `try_send`
+    /// to handle that case yourself.
     pub fn send(&mut self, message: &str) {
-        let status = self.status.to_str();
-        let length = message.len();
-        self.headers.insert("Content-Length".to_string(),length.to_string());
-        //self.header("Content-Length",length.to_string().as_str());
+        if let Err(error) = self.set_body(message.as_bytes()) {
+            println!("{error}");
+        }
+    }
+
+    #[allow(dead_code)]
+    /// Sets the body of the response, or fails if it has already been sent
+    pub fn try_send(&mut self, message: &str) -> Result<(), ResponseError> {
+        self.set_body(message.as_bytes())
+    }
+
+    #[allow(dead_code)]
+    /// Whether a body has been sent
+    pub fn is_sent(&self) -> bool {
+        self.body.is_some() || self.streamed
+    }
+
+    /// Sets a binary body, like an image or a PDF; it is written once the handler returns
+    ///
+    /// Set the `Content-Type` with `content_type`; without one the client has to guess.
+    /// `Range` requests for the body are answered with the ranges they ask for.
+    pub fn send_bytes(&mut self, body: &[u8]) {
+        if let Err(error) = self.try_send_bytes(body) {
+            println!("{error}");
+        }
+    }
+
+    /// Sets a binary body, or fails if one has already been sent
+    pub fn try_send_bytes(&mut self, body: &[u8]) -> Result<(), ResponseError> {
+        self.set_body(body)?;
+        self.accept_ranges = true;
+        Ok(())
+    }
+
+    fn set_body(&mut self, body: &[u8]) -> Result<(), ResponseError> {
+        if self.is_sent() {
+            return Err(ResponseError::AlreadySent);
+        }
+        self.body = Some(body.to_vec());
+        Ok(())
+    }
+
+    #[allow(dead_code)]
+    /// Sets the `Content-Type`, adding `charset=utf-8` to text types that don't name a charset
+    ///
+    /// `response.content_type("text/csv")` sends `Content-Type: text/csv; charset=utf-8`.
+    pub fn content_type(&mut self, media_type: &str) {
+        self.header("Content-Type", &mime::with_charset(media_type));
+    }
+
+    #[allow(dead_code)]
+    /// Sets the `Content-Type` from a file extension, like `"png"` or `"pdf"`
+    pub fn content_type_for(&mut self, extension: &str) {
+        self.content_type(mime::mime_for_extension(extension).unwrap_or(mime::OCTET_STREAM));
+    }
+
+    #[allow(dead_code)]
+    /// Sends text as `text/plain; charset=utf-8`
+    pub fn text(&mut self, text: &str) {
+        self.content_type("text/plain");
+        self.send(text);
+    }
+
+    #[allow(dead_code)]
+    /// Sends HTML as `text/html; charset=utf-8`
+    pub fn html(&mut self, html: &str) {
+        self.content_type("text/html");
+        self.send(html);
+    }
+
+    /// Writes the response to the connection
+    ///
+    /// A response that was never sent gets `unsent_status` and an empty body.
+    fn commit(&mut self, unsent_status: &StatusCode) {
+        if self.streamed {
+            return;
+        }
+        let body = match self.body.take() {
+            Some(body) => body,
+            None => {
+                self.status = unsent_status.clone();
+                Vec::new()
+            }
+        };
+        let mut body = body;
+        if etag::apply(self, &body) {
+            body = Vec::new();
+        }
+        if self.accept_ranges && !self.is_bodiless() {
+            let plan = range::plan(self, body.len() as u64);
+            if !plan.is_whole(body.len() as u64) {
+                let mut ranges = Vec::with_capacity(plan.length() as usize);
+                if let Err(error) = plan.write(&mut Cursor::new(&body), &mut ranges) {
+                    println!("Could not send ranges: {error}");
+                }
+                body = ranges;
+            }
+        }
+        if let Some(encoding) = compress::prepare(self, Some(body.len())) {
+            body = compress::encode(encoding, &body);
+        }
+        let bodiless = self.is_bodiless();
+        if !bodiless {
+            self.header("Content-Length", &body.len().to_string());
+        }
+        let mut response = self.head_bytes();
+        if !bodiless && !self.head {
+            response.extend_from_slice(&body);
+        }
+        if let Err(error) = self.stream.write_all(&response).and_then(|_| self.stream.flush()) {
+            println!("Could not write response: {error}");
+        }
+    }
+
+    /// 1xx, 204 and 304 responses never have content
+    fn is_bodiless(&self) -> bool {
+        let code = self.status.code();
+        self.status.is_informational() || code == 204 || code == 304
+    }
+
+    /// The status line and headers, up to and including the empty line
+    fn head_bytes(&mut self) -> Vec<u8> {
+        // Each connection serves one request, unless it is handed over to another protocol
+        if self.status != StatusCode::SwitchingProtocols {
+            self.header("Connection", "close");
+        }
+        let status = self.status.status_line(&self.version);
         let headers = self.headers.iter().map(|(h,v)| format!("{h}: {v}\r\n")).collect::<String>();
-        //println!("Response:\r\nStatus: {:#?},\r\nHeaders: {:#?},\r\nBody: {:#?}", status, self.headers, message);
-        let response = format!("{status}\r\n{headers}\r\n{message}");
-        self.stream.write_all(response.as_bytes()).unwrap();
-        self.stream.flush().unwrap();
+        //println!("Response:\r\nStatus: {:#?},\r\nHeaders: {:#?}", status, self.headers);
+        format!("{status}\r\n{headers}\r\n").into_bytes()
+    }
+
+    #[allow(dead_code)]
+    /// Streams the body instead of sending it at once
+    ///
+    /// Status and headers are final from here on. The body is sent with `Transfer-Encoding: chunked`,
+    /// unless a `Content-Length` header was set or the request is HTTP/1.0, in which case it is sent
+    /// as is and ends when the connection closes.
+    pub fn stream_body(&mut self) -> Result<BodyWriter<'_>, ResponseError> {
+        if self.is_sent() {
+            return Err(ResponseError::AlreadySent);
+        }
+        self.streamed = true;
+        Ok(BodyWriter::new(self))
+    }
+
+    #[allow(dead_code)]
+    /// Sends a file with its media type, `ETag` and `Last-Modified`
+    ///
+    /// Answers conditional requests for a file the client already has with `304 Not Modified`.
+    /// Fails if the file can't be read, without sending anything.
+    pub fn send_file(&mut self, request: &Request, path: impl AsRef<Path>) -> std::io::Result<()> {
+        static_dir::send_file(request, self, path.as_ref())
     }
 
+    #[allow(dead_code)]
+    /// Sends a file compiled into the binary, like `send_file`
+    pub fn send_embedded(&mut self, request: &Request, file: EmbeddedFile) -> std::io::Result<()> {
+        static_dir::send_embedded(request, self, file)
+    }
+
+    #[allow(dead_code)]
+    /// Turns the response into a stream of Server-Sent Events
+    ///
+    /// Sends the `text/event-stream` head right away. The status and headers set before are
+    /// sent with it; the status is `200 OK` unless the handler set another.
+    pub fn event_stream(&mut self) -> Result<EventStream, ResponseError> {
+        if self.is_sent() {
+            return Err(ResponseError::AlreadySent);
+        }
+        self.streamed = true;
+        self.header("Content-Type", "text/event-stream");
+        self.header("Cache-Control", "no-cache");
+        // Keeps nginx from buffering the events
+        self.header("X-Accel-Buffering", "no");
+        let head = self.head_bytes();
+        let stream = self.stream.try_clone().map_err(|error| ResponseError::Io(error.kind()))?;
+        let mut events = EventStream::new(stream);
+        events.write_head(&head).map_err(|error| ResponseError::Io(error.kind()))?;
+        Ok(events)
+    }
+
+    /// Sends JSON text as `application/json; charset=utf-8`
     pub fn json(&mut self, json: &str) {
-        self.header("Content-Type","Application/json");
+        self.content_type("application/json");
         self.send(json);
     }
 
+    /// Sends a JSON value as the body
+    pub fn json_value(&mut self, json: &Value) {
+        self.json(&json.to_string());
+    }
+
     pub fn raw_stream(&self) -> &TcpStream {
         &self.stream
     }
 }
 
+/// Whether a header can be written as it is: the name is a token, and the value has no
+/// control characters other than tab
+fn is_valid_header(name: &str, value: &str) -> bool {
+    cookie::is_token(name) && value.bytes().all(|byte| byte == b'\t' || !byte.is_ascii_control())
+}
+
 // API (Packcake)
 pub struct Packcake {
     pub port: usize,
@@ -272,6 +869,16 @@ pub struct Packcake {
     //temp_uri: String,
     thread_pool_size: usize,
     do_print: bool,
+    trusted_proxies: Vec<Cidr>,
+    proxy_headers: ProxyHeaders,
+    proxy_protocol: bool,
+    unsent_status: StatusCode,
+    /// Directories served by prefix, without trailing slash
+    static_dirs: Vec<(String, Arc<StaticDir>)>,
+    compression: Option<Arc<Compression>>,
+    auto_etag: bool,
+    /// Handles requests that no route or static file matches
+    fallback: Handler,
 }
 
 impl Packcake {
@@ -282,6 +889,17 @@ impl Packcake {
             //temp_uri: "".to_string(),
             thread_pool_size: threads,
             do_print: false,
+            trusted_proxies: Vec::new(),
+            proxy_headers: ProxyHeaders::XForwarded,
+            proxy_protocol: false,
+            unsent_status: StatusCode::InternalServerError,
+            static_dirs: Vec::new(),
+            compression: None,
+            auto_etag: false,
+            fallback: Arc::new(|_request: &Request, response: &mut Response| {
+                response.status(StatusCode::NotFound);
+                response.send("Route is not mapped");
+            }),
         }
     }
 
@@ -305,6 +923,99 @@ impl Packcake {
         self
     }
 
+    #[allow(dead_code)]
+    /// The status sent when a handler returns without sending a response (default `500 Internal Server Error`)
+    pub fn unsent_status(mut self, status: StatusCode) -> Packcake {
+        self.unsent_status = status;
+        self
+    }
+
+    #[allow(dead_code)]
+    /// Handles requests that no route or static file matches (default `404 Not Found`)
+    pub fn fallback<M>(mut self, handler: impl IntoHandler<M>) -> Packcake {
+        self.fallback = handler.into_handler();
+        self
+    }
+
+    #[allow(dead_code)]
+    /// Serves the files under `root` at `prefix`, like `static_dir("/assets", "./public")`
+    ///
+    /// Routes take precedence over static files, and requests for files that don't exist go to the fallback.
+    pub fn static_dir(self, prefix: &str, root: impl Into<PathBuf>) -> Packcake {
+        self.static_dir_with(prefix, StaticDir::new(root))
+    }
+
+    #[allow(dead_code)]
+    /// Serves files compiled into the binary at `prefix`, like `static_dir` does for a directory
+    pub fn static_embedded(self, prefix: &str, files: EmbeddedDir) -> Packcake {
+        self.static_dir_with(prefix, StaticDir::embedded(files))
+    }
+
+    #[allow(dead_code)]
+    /// Serves a directory at `prefix`, with index and listing settings
+    pub fn static_dir_with(mut self, prefix: &str, dir: StaticDir) -> Packcake {
+        let prefix = prefix.trim_end_matches('/').to_string();
+        if self.do_print {
+            println!("Serving {} at {prefix}/", dir.describe());
+        }
+        self.static_dirs.retain(|(p, _)| *p != prefix);
+        self.static_dirs.push((prefix, Arc::new(dir)));
+        self
+    }
+
+    #[allow(dead_code)]
+    /// Compresses text responses for clients that accept gzip or deflate, like `compression(Compression::new())`
+    ///
+    /// Applies to buffered and streamed bodies. Bodies that already have a `Content-Encoding` are left alone.
+    pub fn compression(mut self, compression: Compression) -> Packcake {
+        self.compression = Some(Arc::new(compression));
+        self
+    }
+
+    #[allow(dead_code)]
+    /// Tags buffered `200 OK` responses to `GET` and `HEAD` requests with an `ETag` hashed from the body
+    ///
+    /// A client that sends the tag back in `If-None-Match` gets `304 Not Modified` without the body,
+    /// as does one whose `If-Modified-Since` is not older than a `Last-Modified` set by the handler.
+    /// Tags set by the handler are kept. The body is still produced on every request; this only
+    /// saves sending it.
+    pub fn auto_etag(mut self) -> Packcake {
+        self.auto_etag = true;
+        self
+    }
+
+    #[allow(dead_code)]
+    /// Trusts the forwarding headers of requests from these proxies
+    ///
+    /// # Arguments
+    ///
+    /// * `proxies` -> Addresses or CIDR ranges, like `"10.0.0.5"` or `"10.0.0.0/8"`
+    /// * `headers` -> The headers the proxies write; the other family is never read
+    ///
+    /// # Panics
+    ///
+    /// If one of the proxies is not a valid address or range
+    pub fn trusted_proxies(mut self, proxies: &[&str], headers: ProxyHeaders) -> Packcake {
+        self.proxy_headers = headers;
+        for proxy in proxies {
+            let cidr = proxy.parse::<Cidr>().unwrap_or_else(|error| panic!("{error}"));
+            self.trusted_proxies.push(cidr);
+        }
+        self
+    }
+
+    #[allow(dead_code)]
+    /// Expects every connection to start with a PROXY protocol (v1 or v2) header,
+    /// as sent by HAProxy and most TCP load balancers
+    ///
+    /// The source address in the header becomes the client address of the request.
+    /// Connections without a valid header within 5 seconds are dropped, so only enable this when every
+    /// connection comes through the load balancer.
+    pub fn proxy_protocol(mut self) -> Packcake {
+        self.proxy_protocol = true;
+        self
+    }
+
     #[allow(dead_code)]
     /// Adds a 'GET' endpoint
     ///
@@ -312,7 +1023,7 @@ impl Packcake {
     ///
     /// * `uri` -> The uri for the endpoint
     /// * `handler` -> The handler for request to this endpoint
-    pub fn get(mut self, uri: &str, handler: fn(&Request, &mut Response)) -> Packcake {
+    pub fn get<M>(mut self, uri: &str, handler: impl IntoHandler<M>) -> Packcake {
         let endpoint = get(uri, handler);
         self.add_endpoint(endpoint);
         self
@@ -325,7 +1036,7 @@ impl Packcake {
     ///
     /// * `uri` -> The uri for the endpoint
     /// * `handler` -> The handler for request to this endpoint
-    pub fn post(mut self, uri: &str, handler: fn(&Request, &mut Response)) -> Packcake {
+    pub fn post<M>(mut self, uri: &str, handler: impl IntoHandler<M>) -> Packcake {
         let endpoint = post(uri, handler);
         self.add_endpoint(endpoint);
         self
@@ -338,7 +1049,7 @@ impl Packcake {
     ///
     /// * `uri` -> The uri for the endpoint
     /// * `handler` -> The handler for request to this endpoint
-    pub fn put(mut self, uri: &str, handler: fn(&Request, &mut Response)) -> Packcake {
+    pub fn put<M>(mut self, uri: &str, handler: impl IntoHandler<M>) -> Packcake {
         let endpoint = put(uri, handler);
         self.add_endpoint(endpoint);
         self
@@ -351,7 +1062,7 @@ impl Packcake {
     ///
     /// * `uri` -> The uri for the endpoint
     /// * `handler` -> The handler for request to this endpoint
-    pub fn patch(mut self, uri: &str, handler: fn(&Request, &mut Response)) -> Packcake {
+    pub fn patch<M>(mut self, uri: &str, handler: impl IntoHandler<M>) -> Packcake {
         let endpoint = patch(uri, handler);
         self.add_endpoint(endpoint);
         self
@@ -364,7 +1075,7 @@ impl Packcake {
     ///
     /// * `uri` -> The uri for the endpoint
     /// * `handler` -> The handler for request to this endpoint
-    pub fn delete(mut self, uri: &str, handler: fn(&Request, &mut Response)) -> Packcake {
+    pub fn delete<M>(mut self, uri: &str, handler: impl IntoHandler<M>) -> Packcake {
         let endpoint = delete(uri, handler);
         self.add_endpoint(endpoint);
         self
@@ -401,27 +1112,74 @@ impl Packcake {
         self._path(&uri, middleware, groups, endpoints)
     }
 
+    /// Finds the endpoint for the request, along with the path parameters it captures
+    fn route(&self, request: &Request) -> Option<(&Endpoint, HashMap<String,String>)> {
+        let id = format!("{} {}", request.method, request.uri);
+        if let Some(endpoint) = self.endpoints.get(&id) {
+            return Some((endpoint, HashMap::new()));
+        }
+        self.endpoints.values()
+            .filter(|endpoint| endpoint.method == request.method && endpoint.uri.contains(':'))
+            .find_map(|endpoint| endpoint.match_path(&request.raw_uri).map(|params| (endpoint, params)))
+    }
+
+    /// The static directory mounted at the longest prefix of the request path
+    fn static_dir_for(&self, request: &Request) -> Option<&(String, Arc<StaticDir>)> {
+        self.static_dirs.iter()
+            .filter(|(prefix, _)| StaticDir::covers(prefix, request.get_raw_path()))
+            .max_by_key(|(prefix, _)| prefix.len())
+    }
+
     pub fn start(&self) {
         println!("Starting server...");
         let thread_pool = ThreadPool::new(self.thread_pool_size);
         let listener = TcpListener::bind(format!("127.0.0.1:{}",self.port)).unwrap();
         //let pool = ThreadPool::new(self.pool_size);
         println!("Server listening on port {}", self.port);
+        let mut connection_id: u64 = 0;
         for stream in listener.incoming() {
             let stream = stream.unwrap();
+            connection_id += 1;
             //Handle
-            let optional_request: Option<Request> = Request::from_stream(&stream);
-            if optional_request.is_some() {
-                let request = optional_request.unwrap();
+            let mut connection = ConnectionInfo::from_stream(&stream, connection_id);
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            if self.proxy_protocol {
+                // The header is read before the connection goes to a worker, so a peer that
+                // never sends one must not hold up the accept loop
+                let _ = stream.set_read_timeout(Some(proxy_protocol::HEADER_TIMEOUT));
+                let header = proxy_protocol::read_header(&mut reader);
+                let _ = stream.set_read_timeout(None);
+                match header {
+                    Ok(Some(addrs)) => connection.set_proxied_source(addrs.source),
+                    Ok(None) => {}
+                    Err(error) => {
+                        println!("Dropping connection from {}: {error}", connection.peer_addr());
+                        continue;
+                    }
+                }
+            }
+            let optional_request: Option<Request> = Request::from_stream(&stream, reader, connection);
+            if let Some(mut request) = optional_request {
+                request.resolve_forwarding(&self.trusted_proxies, self.proxy_headers);
                 //request.display();
-                let mut response = Response::from_stream(stream);
-                let id = format!("{} {}", request.method, request.uri);
-                let endpoint = self.endpoints.get(&id);
-                if endpoint.is_some() {
-                    let ep = endpoint.unwrap();
+                let mut response = Response::from_stream(stream, &request);
+                response.compression = self.compression.clone();
+                response.auto_etag = self.auto_etag;
+                if request.has_bad_content_length() {
+                    response.status(StatusCode::BadRequest);
+                    response.send("Invalid Content-Length");
+                    response.commit(&self.unsent_status);
+                } else if request.has_unmet_expectation() {
+                    response.status(StatusCode::ExpectationFailed);
+                    response.send("Only \"Expect: 100-continue\" is supported");
+                    response.commit(&self.unsent_status);
+                } else if let Some((ep, path_params)) = self.route(&request) {
+                    request.path_params = path_params;
                     //let mut passed_middleware_check = false;
                     let middleware = ep.middleware.clone();
-                    let handler = endpoint.unwrap().handler;
+                    let handler = ep.handler.clone();
+                    let params = ep.params.clone();
+                    let unsent_status = self.unsent_status.clone();
                     thread_pool.execute(move || {
                         let mut passed_middleware_check = true;
                         if middleware.is_some() {
@@ -434,13 +1192,30 @@ impl Packcake {
                             }
                         }
                         if passed_middleware_check {
-                            handler(&request, &mut response);
+                            let errors = Endpoint::validate_params(&params, &request);
+                            if errors.is_empty() {
+                                handler(&request, &mut response);
+                            } else {
+                                response.status(StatusCode::BadRequest);
+                                response.json_value(&params::error_body(&errors));
+                            }
                         }
+                        request.discard_body();
+                        response.commit(&unsent_status);
                     });
                 } else {
-                    println!("{} {} is not mapped", request.method, request.uri);
-                    response.status(StatusCode::BadRequest);
-                    response.send("Route is not mapped");
+                    let static_dir = self.static_dir_for(&request).map(|(prefix, dir)| (prefix.clone(), Arc::clone(dir)));
+                    let fallback = self.fallback.clone();
+                    let unsent_status = self.unsent_status.clone();
+                    thread_pool.execute(move || {
+                        let served = static_dir.is_some_and(|(prefix, dir)| dir.serve(&prefix, &request, &mut response));
+                        if !served {
+                            println!("{} {} is not mapped", request.method, request.uri);
+                            fallback(&request, &mut response);
+                        }
+                        request.discard_body();
+                        response.commit(&unsent_status);
+                    });
                 }
             }
         }
@@ -448,63 +1223,77 @@ impl Packcake {
     }
 }
 
-pub fn get(uri: &str, handler: fn(&Request, &mut Response) -> ()) -> Endpoint {
-    _get(uri, None, handler)
+pub fn get<M>(uri: &str, handler: impl IntoHandler<M>) -> Endpoint {
+    _get(uri, None, handler.into_handler())
 }
-fn _get(uri: &str, middleware: Option<Vec<Middleware>>, handler: fn(&Request, &mut Response) -> ()) -> Endpoint {
+fn _get(uri: &str, middleware: Option<Vec<Middleware>>, handler: Handler) -> Endpoint {
     Endpoint {
         method: String::from(GET),
         uri: String::from(uri),
         handler,
         middleware,
+        params: Vec::new(),
     }
 }
 
-pub fn post(uri: &str, handler: fn(&Request, &mut Response) -> ()) -> Endpoint {
-    _post(uri, None, handler)
+#[allow(dead_code)]
+/// A WebSocket endpoint; `GET` requests to `uri` are upgraded, and the handler gets the socket
+///
+/// The handler keeps its worker thread until it returns, which closes the connection.
+pub fn ws<F>(uri: &str, handler: F) -> Endpoint
+where F: Fn(&Request, WebSocket) + Send + Sync + 'static {
+    _get(uri, None, ws::into_handler(handler))
+}
+
+pub fn post<M>(uri: &str, handler: impl IntoHandler<M>) -> Endpoint {
+    _post(uri, None, handler.into_handler())
 }
-fn _post(uri: &str, middleware: Option<Vec<Middleware>>, handler: fn(&Request, &mut Response) -> ()) -> Endpoint {
+fn _post(uri: &str, middleware: Option<Vec<Middleware>>, handler: Handler) -> Endpoint {
     Endpoint {
         method: String::from(POST),
         uri: String::from(uri),
         handler,
         middleware,
+        params: Vec::new(),
     }
 }
 
-pub fn put(uri: &str, handler: fn(&Request, &mut Response) -> ()) -> Endpoint {
-    _put(uri, None, handler)
+pub fn put<M>(uri: &str, handler: impl IntoHandler<M>) -> Endpoint {
+    _put(uri, None, handler.into_handler())
 }
-fn _put(uri: &str, middleware: Option<Vec<Middleware>>, handler: fn(&Request, &mut Response) -> ()) -> Endpoint {
+fn _put(uri: &str, middleware: Option<Vec<Middleware>>, handler: Handler) -> Endpoint {
     Endpoint {
         method: String::from(PUT),
         uri: String::from(uri),
         handler,
         middleware,
+        params: Vec::new(),
     }
 }
 
-pub fn patch(uri: &str, handler: fn(&Request, &mut Response) -> ()) -> Endpoint {
-    _patch(uri, None, handler)
+pub fn patch<M>(uri: &str, handler: impl IntoHandler<M>) -> Endpoint {
+    _patch(uri, None, handler.into_handler())
 }
-fn _patch(uri: &str, middleware: Option<Vec<Middleware>>, handler: fn(&Request, &mut Response) -> ()) -> Endpoint {
+fn _patch(uri: &str, middleware: Option<Vec<Middleware>>, handler: Handler) -> Endpoint {
     Endpoint {
         method: String::from(PATCH),
         uri: String::from(uri),
         handler,
         middleware,
+        params: Vec::new(),
     }
 }
 
-pub fn delete(uri: &str, handler: fn(&Request, &mut Response) -> ()) -> Endpoint {
-    _delete(uri, None, handler)
+pub fn delete<M>(uri: &str, handler: impl IntoHandler<M>) -> Endpoint {
+    _delete(uri, None, handler.into_handler())
 }
-fn _delete(uri: &str, middleware: Option<Vec<Middleware>>, handler: fn(&Request, &mut Response) -> ()) -> Endpoint {
+fn _delete(uri: &str, middleware: Option<Vec<Middleware>>, handler: Handler) -> Endpoint {
     Endpoint {
         method: String::from(DELETE),
         uri: String::from(uri),
         handler,
         middleware,
+        params: Vec::new(),
     }
 }
 
@@ -0,0 +1,212 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use crate::packcake::json::Value;
+
+/// Where in the request a parameter is read from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParamLocation {
+    Query,
+    Path,
+    Header,
+}
+
+impl ParamLocation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ParamLocation::Query => "query",
+            ParamLocation::Path => "path",
+            ParamLocation::Header => "header",
+        }
+    }
+}
+
+/// Why a parameter could not be read
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParamError {
+    Missing {
+        location: ParamLocation,
+        name: String,
+    },
+    Malformed {
+        location: ParamLocation,
+        name: String,
+        value: String,
+        expected: &'static str,
+    },
+}
+
+impl ParamError {
+    pub(crate) fn check<T: FromStr>(location: ParamLocation, name: &str, value: Option<&String>) -> Result<T, ParamError> {
+        let value = value.ok_or_else(|| ParamError::Missing {
+            location,
+            name: name.to_string(),
+        })?;
+        value.parse::<T>().map_err(|_| ParamError::Malformed {
+            location,
+            name: name.to_string(),
+            value: value.to_string(),
+            expected: short_type_name::<T>(),
+        })
+    }
+}
+
+impl Display for ParamError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParamError::Missing { location, name } =>
+                write!(f, "Missing {} param [{name}]", location.as_str()),
+            ParamError::Malformed { location, name, value, expected } =>
+                write!(f, "Malformed {} param [{name}]: \"{value}\" is not a valid {expected}", location.as_str()),
+        }
+    }
+}
+
+/// A parameter an endpoint requires, checked before the handler runs
+#[derive(Clone, Debug)]
+pub struct ParamSpec {
+    pub(crate) location: ParamLocation,
+    pub(crate) name: String,
+    expected: &'static str,
+    check: fn(&str) -> bool,
+}
+
+impl ParamSpec {
+    pub fn new<T: FromStr>(location: ParamLocation, name: &str) -> ParamSpec {
+        ParamSpec {
+            location,
+            name: name.to_string(),
+            expected: short_type_name::<T>(),
+            check: parses_as::<T>,
+        }
+    }
+
+    pub(crate) fn validate(&self, value: Option<&String>) -> Option<ParamError> {
+        match value {
+            None => Some(ParamError::Missing {
+                location: self.location,
+                name: self.name.clone(),
+            }),
+            Some(value) if !(self.check)(value) => Some(ParamError::Malformed {
+                location: self.location,
+                name: self.name.clone(),
+                value: value.clone(),
+                expected: self.expected,
+            }),
+            Some(_) => None,
+        }
+    }
+}
+
+fn parses_as<T: FromStr>(value: &str) -> bool {
+    value.parse::<T>().is_ok()
+}
+
+/// `alloc::string::String` -> `String`
+fn short_type_name<T>() -> &'static str {
+    let name = std::any::type_name::<T>();
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+/// Renders the uniform error body sent when required parameters are missing or malformed:
+///
+/// `{"error":"Bad Request","missing":[{"in":"query","name":"id"}],"malformed":[...]}`
+pub(crate) fn error_body(errors: &[ParamError]) -> Value {
+    let mut missing = Value::Array(Vec::new());
+    let mut malformed = Value::Array(Vec::new());
+    for error in errors {
+        match error {
+            ParamError::Missing { location, name } => missing.push(Value::object([
+                ("in", Value::from(location.as_str())),
+                ("name", Value::from(name)),
+            ])),
+            ParamError::Malformed { location, name, value, expected } => malformed.push(Value::object([
+                ("in", Value::from(location.as_str())),
+                ("name", Value::from(name)),
+                ("value", Value::from(value)),
+                ("expected", Value::from(*expected)),
+            ])),
+        }
+    }
+    Value::object([
+        ("error", Value::from("Bad Request")),
+        ("missing", missing),
+        ("malformed", malformed),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::packcake::{Endpoint, Request};
+
+    fn request(target: &str, headers: &[(&str, &str)]) -> Request {
+        let headers = headers.iter().map(|(h, v)| (h.to_string(), v.to_string())).collect();
+        Request::new(&format!("GET {target} HTTP/1.1"), headers, String::new())
+    }
+
+    #[test]
+    fn reads_typed_params() {
+        let mut request = request("/team?user_id=42&ratio=0.5&flag&name=a+b", &[("X-Limit", "10")]);
+        request.path_params = HashMap::from([(String::from("id"), String::from("7"))]);
+        assert_eq!(request.get_param_as::<u64>("user_id"), Ok(42));
+        assert_eq!(request.get_param_as::<f64>("ratio"), Ok(0.5));
+        assert_eq!(request.get_param_as::<String>("name"), Ok(String::from("a b")));
+        assert_eq!(request.get_param_as::<String>("flag"), Ok(String::new()));
+        assert_eq!(request.get_path_param_as::<u8>("id"), Ok(7));
+        assert_eq!(request.get_header_as::<usize>("X-Limit"), Ok(10));
+    }
+
+    #[test]
+    fn reports_missing_and_malformed_params() {
+        let request = request("/team?user_id=abc&count=-1", &[]);
+        let missing = request.get_param_as::<u64>("team_id").unwrap_err();
+        assert_eq!(missing, ParamError::Missing { location: ParamLocation::Query, name: String::from("team_id") });
+        assert_eq!(missing.to_string(), "Missing query param [team_id]");
+
+        let malformed = request.get_param_as::<u64>("user_id").unwrap_err();
+        assert_eq!(malformed.to_string(), "Malformed query param [user_id]: \"abc\" is not a valid u64");
+        assert!(request.get_param_as::<u32>("count").is_err());
+        assert!(request.get_path_param_as::<u32>("id").is_err());
+        assert_eq!(request.get_header_as::<u32>("X-Limit").unwrap_err().to_string(), "Missing header param [X-Limit]");
+    }
+
+    #[test]
+    fn validates_required_params() {
+        let specs = [
+            ParamSpec::new::<u64>(ParamLocation::Query, "user_id"),
+            ParamSpec::new::<u64>(ParamLocation::Query, "page"),
+            ParamSpec::new::<bool>(ParamLocation::Header, "X-Dry-Run"),
+            ParamSpec::new::<String>(ParamLocation::Query, "q"),
+        ];
+        let errors = Endpoint::validate_params(&specs, &request("/?user_id=x&q=", &[("x-dry-run", "true")]));
+        assert_eq!(errors, [
+            ParamError::Malformed {
+                location: ParamLocation::Query,
+                name: String::from("user_id"),
+                value: String::from("x"),
+                expected: "u64",
+            },
+            ParamError::Missing { location: ParamLocation::Query, name: String::from("page") },
+        ]);
+        assert!(Endpoint::validate_params(&specs, &request("/?user_id=1&page=2&q=a", &[("X-Dry-Run", "false")])).is_empty());
+    }
+
+    #[test]
+    fn renders_the_error_body() {
+        let errors = [
+            ParamError::Missing { location: ParamLocation::Path, name: String::from("id") },
+            ParamError::Malformed {
+                location: ParamLocation::Query,
+                name: String::from("user_id"),
+                value: String::from("a\"b"),
+                expected: "u64",
+            },
+        ];
+        assert_eq!(
+            error_body(&errors).to_string(),
+            r#"{"error":"Bad Request","missing":[{"in":"path","name":"id"}],"malformed":[{"in":"query","name":"user_id","value":"a\"b","expected":"u64"}]}"#
+        );
+        assert_eq!(error_body(&[]).to_string(), r#"{"error":"Bad Request","missing":[],"malformed":[]}"#);
+    }
+}
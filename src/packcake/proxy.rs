@@ -0,0 +1,284 @@
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+
+/// A range of addresses like `10.0.0.0/8` or `fd00::/8`; a plain address is a range of one
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) =>
+                mask(u32::from(network) as u128, u32::from(ip) as u128, self.prefix, 32),
+            (IpAddr::V6(network), IpAddr::V6(ip)) =>
+                mask(u128::from(network), u128::from(ip), self.prefix, 128),
+            _ => false,
+        }
+    }
+}
+
+fn mask(network: u128, ip: u128, prefix: u8, bits: u8) -> bool {
+    if prefix == 0 {
+        return true;
+    }
+    let shift = (bits - prefix) as u32;
+    network >> shift == ip >> shift
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Cidr, String> {
+        let (address, prefix) = match s.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (s, None),
+        };
+        let network = IpAddr::from_str(address.trim())
+            .map_err(|_| format!("Invalid address in CIDR \"{s}\""))?
+            .to_canonical();
+        let bits = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.trim().parse::<u8>()
+                .ok()
+                .filter(|prefix| *prefix <= bits)
+                .ok_or_else(|| format!("Invalid prefix length in CIDR \"{s}\""))?,
+            None => bits,
+        };
+        Ok(Cidr { network, prefix })
+    }
+}
+
+/// The headers a trusted proxy writes
+///
+/// Only this family is read. A proxy passes on the headers it doesn't write untouched, so
+/// the client could have set them to anything.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProxyHeaders {
+    #[allow(dead_code)]
+    /// RFC 7239 `Forwarded`
+    Forwarded,
+    /// `X-Forwarded-For`, `X-Forwarded-Proto` and `X-Forwarded-Host`
+    XForwarded,
+}
+
+/// Where a request really came from, after looking past trusted proxies
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Forwarding {
+    pub(crate) client_ip: IpAddr,
+    pub(crate) scheme: Option<String>,
+    pub(crate) host: Option<String>,
+}
+
+/// One hop of a `Forwarded` or `X-Forwarded-*` chain
+#[derive(Default)]
+struct Hop {
+    for_ip: Option<IpAddr>,
+    proto: Option<String>,
+    host: Option<String>,
+}
+
+/// Resolves the client of a request that came from `peer`
+///
+/// The forwarding headers are only read when `peer` is a trusted proxy. The chain of addresses
+/// is then walked from the nearest hop, and the first address that is not trusted is the client.
+/// Only the headers of the `family` the proxies write are read.
+pub(crate) fn resolve<'a, F>(peer: IpAddr, trusted: &[Cidr], family: ProxyHeaders, header: F) -> Forwarding
+where F: Fn(&str) -> Option<&'a String> {
+    let is_trusted = |ip: IpAddr| trusted.iter().any(|cidr| cidr.contains(ip));
+    let untouched = Forwarding {
+        client_ip: peer,
+        scheme: None,
+        host: None,
+    };
+    if !is_trusted(peer) {
+        return untouched;
+    }
+    let hops = match family {
+        ProxyHeaders::Forwarded => header("Forwarded").map(|forwarded| parse_forwarded(forwarded)).unwrap_or_default(),
+        ProxyHeaders::XForwarded =>
+            parse_x_forwarded(header("X-Forwarded-For"), header("X-Forwarded-Proto"), header("X-Forwarded-Host")),
+    };
+    if hops.is_empty() {
+        return untouched;
+    }
+    let mut resolved = untouched;
+    for hop in hops.iter().rev() {
+        match hop.for_ip {
+            Some(ip) => {
+                resolved.client_ip = ip;
+                resolved.scheme = hop.proto.clone().or(resolved.scheme);
+                resolved.host = hop.host.clone().or(resolved.host);
+                if !is_trusted(ip) {
+                    break;
+                }
+            }
+            // `unknown` or an obfuscated identifier; nothing further along can be trusted
+            None => break,
+        }
+    }
+    resolved
+}
+
+fn parse_x_forwarded(for_header: Option<&String>, proto: Option<&String>, host: Option<&String>) -> Vec<Hop> {
+    let Some(for_header) = for_header else {
+        return Vec::new();
+    };
+    let addresses: Vec<&str> = for_header.split(',').map(str::trim).collect();
+    let protos: Vec<&str> = proto.map(|p| p.split(',').map(str::trim).collect()).unwrap_or_default();
+    let hosts: Vec<&str> = host.map(|h| h.split(',').map(str::trim).collect()).unwrap_or_default();
+    // With one value per hop the values line up with the addresses, otherwise the first value is used
+    let pick = |values: &Vec<&str>, index: usize| -> Option<String> {
+        let value = if values.len() == addresses.len() { values.get(index) } else { values.first() };
+        value.filter(|v| !v.is_empty()).map(|v| v.to_ascii_lowercase())
+    };
+    addresses.iter()
+        .enumerate()
+        .map(|(i, address)| Hop {
+            for_ip: parse_node(address),
+            proto: pick(&protos, i),
+            host: pick(&hosts, i),
+        })
+        .collect()
+}
+
+/// Parses an RFC 7239 `Forwarded` header: `for=192.0.2.60;proto=https;host=example.com, for="[2001:db8::1]:4711"`
+fn parse_forwarded(header: &str) -> Vec<Hop> {
+    split_unquoted(header, ',')
+        .into_iter()
+        .map(|element| {
+            let mut hop = Hop::default();
+            for pair in split_unquoted(element, ';') {
+                let Some((key, value)) = pair.split_once('=') else {
+                    continue;
+                };
+                let value = value.trim();
+                let value = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')).unwrap_or(value);
+                match key.trim().to_ascii_lowercase().as_str() {
+                    "for" => hop.for_ip = parse_node(value),
+                    "proto" => hop.proto = Some(value.to_ascii_lowercase()),
+                    "host" => hop.host = Some(value.to_string()),
+                    _ => {}
+                }
+            }
+            hop
+        })
+        .collect()
+}
+
+/// Parses a node like `192.0.2.43`, `192.0.2.43:47011`, `[2001:db8::17]:4711` or `2001:db8::17`
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim();
+    IpAddr::from_str(node).ok()
+        .or_else(|| SocketAddr::from_str(node).ok().map(|addr| addr.ip()))
+        .or_else(|| node.strip_prefix('[').and_then(|n| n.strip_suffix(']')).and_then(|n| IpAddr::from_str(n).ok()))
+        .map(|ip| ip.to_canonical())
+}
+
+fn split_unquoted(input: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut quoted = false;
+    let mut start = 0;
+    for (i, c) in input.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c == separator && !quoted => {
+                parts.push(input[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(input[start..].trim());
+    parts.into_iter().filter(|part| !part.is_empty()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    fn cidrs(cidrs: &[&str]) -> Vec<Cidr> {
+        cidrs.iter().map(|cidr| cidr.parse().unwrap()).collect()
+    }
+
+    fn resolve_with(peer: &str, family: ProxyHeaders, headers: &[(&str, &str)]) -> Forwarding {
+        let headers: HashMap<String, String> = headers.iter().map(|(h, v)| (h.to_string(), v.to_string())).collect();
+        resolve(ip(peer), &cidrs(&["10.0.0.0/8", "fd00::/8"]), family, |name| headers.get(name))
+    }
+
+    #[test]
+    fn parses_cidrs() {
+        assert_eq!("10.0.0.0/8".parse::<Cidr>(), Ok(Cidr { network: ip("10.0.0.0"), prefix: 8 }));
+        assert_eq!(" 192.0.2.1 ".parse::<Cidr>(), Ok(Cidr { network: ip("192.0.2.1"), prefix: 32 }));
+        assert_eq!("::ffff:10.1.2.3/16".parse::<Cidr>().map(|cidr| cidr.network), Ok(ip("10.1.2.3")));
+        assert_eq!("fd00::/8".parse::<Cidr>().map(|cidr| cidr.prefix), Ok(8));
+        for invalid in ["", "10.0.0", "10.0.0.0/33", "10.0.0.0/-1", "10.0.0.0/", "fd00::/129", "example.com"] {
+            assert!(invalid.parse::<Cidr>().is_err(), "{invalid:?}");
+        }
+    }
+
+    #[test]
+    fn checks_cidr_membership() {
+        let private = "10.0.0.0/8".parse::<Cidr>().unwrap();
+        assert!(private.contains(ip("10.255.0.1")));
+        assert!(private.contains(ip("::ffff:10.0.0.1")));
+        assert!(!private.contains(ip("11.0.0.1")));
+        assert!(!private.contains(ip("fd00::1")));
+        let single = "192.0.2.1".parse::<Cidr>().unwrap();
+        assert!(single.contains(ip("192.0.2.1")) && !single.contains(ip("192.0.2.2")));
+        assert!("0.0.0.0/0".parse::<Cidr>().unwrap().contains(ip("8.8.8.8")));
+        assert!("fd00::/8".parse::<Cidr>().unwrap().contains(ip("fdab::1")));
+    }
+
+    #[test]
+    fn walks_the_chain_past_trusted_hops() {
+        let resolved = resolve_with("10.0.0.1", ProxyHeaders::XForwarded, &[
+            ("X-Forwarded-For", "6.6.6.6, 203.0.113.7, 10.0.0.2"),
+            ("X-Forwarded-Proto", "https"),
+            ("X-Forwarded-Host", "example.com"),
+        ]);
+        assert_eq!(resolved, Forwarding {
+            client_ip: ip("203.0.113.7"),
+            scheme: Some(String::from("https")),
+            host: Some(String::from("example.com")),
+        });
+        let forwarded = resolve_with("10.0.0.1", ProxyHeaders::Forwarded, &[
+            ("Forwarded", r#"for=6.6.6.6, for="[2001:db8::1]:4711";proto=https, for=10.0.0.2:80"#),
+        ]);
+        assert_eq!((forwarded.client_ip, forwarded.scheme), (ip("2001:db8::1"), Some(String::from("https"))));
+    }
+
+    #[test]
+    fn stops_at_unknown_hops() {
+        let resolved = resolve_with("10.0.0.1", ProxyHeaders::Forwarded, &[("Forwarded", "for=6.6.6.6, for=unknown, for=10.0.0.2")]);
+        assert_eq!(resolved.client_ip, ip("10.0.0.2"));
+    }
+
+    #[test]
+    fn ignores_headers_from_untrusted_peers() {
+        let resolved = resolve_with("203.0.113.7", ProxyHeaders::XForwarded, &[
+            ("X-Forwarded-For", "6.6.6.6"),
+            ("X-Forwarded-Proto", "https"),
+        ]);
+        assert_eq!(resolved, Forwarding { client_ip: ip("203.0.113.7"), scheme: None, host: None });
+    }
+
+    #[test]
+    fn reads_only_the_configured_headers() {
+        let headers = [
+            ("Forwarded", "for=6.6.6.6;proto=https"),
+            ("X-Forwarded-For", "9.9.9.9"),
+        ];
+        let x_forwarded = resolve_with("10.0.0.1", ProxyHeaders::XForwarded, &headers);
+        assert_eq!((x_forwarded.client_ip, x_forwarded.scheme), (ip("9.9.9.9"), None));
+        let forwarded = resolve_with("10.0.0.1", ProxyHeaders::Forwarded, &headers[1..]);
+        assert_eq!(forwarded.client_ip, ip("10.0.0.1"));
+    }
+}
@@ -0,0 +1,260 @@
+use std::io::{BufRead, Error, ErrorKind};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+const V2_SIGNATURE: [u8; 12] = [0x0D, 0x0A, 0x0D, 0x0A, 0x00, 0x0D, 0x0A, 0x51, 0x55, 0x49, 0x54, 0x0A];
+/// The longest v1 header, including the line break
+const V1_MAX_LENGTH: usize = 107;
+/// How long to wait for the header before dropping the connection
+pub(crate) const HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The addresses carried by a PROXY protocol header
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct ProxiedAddrs {
+    pub(crate) source: SocketAddr,
+    pub(crate) destination: SocketAddr,
+}
+
+/// Reads a PROXY protocol v1 or v2 header from the start of a connection
+///
+/// Returns `None` for headers that carry no addresses (`PROXY UNKNOWN` or a v2 `LOCAL` command),
+/// and an error if the connection doesn't start with a valid header.
+pub(crate) fn read_header<R: BufRead>(reader: &mut R) -> std::io::Result<Option<ProxiedAddrs>> {
+    // Both versions are at least 12 bytes long (`PROXY UNKNOWN\r\n` is the shortest v1 header)
+    let mut start = [0; 12];
+    reader.read_exact(&mut start)?;
+    if start == V2_SIGNATURE {
+        read_v2(reader)
+    } else if start.starts_with(b"PROXY ") {
+        read_v1(reader, &start)
+    } else {
+        Err(invalid("connection does not start with a PROXY protocol header"))
+    }
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+/// `PROXY TCP4 192.168.0.1 192.168.0.11 56324 443\r\n`
+fn read_v1<R: BufRead>(reader: &mut R, start: &[u8]) -> std::io::Result<Option<ProxiedAddrs>> {
+    let mut line = start.to_vec();
+    while !line.ends_with(b"\r\n") {
+        if line.len() >= V1_MAX_LENGTH {
+            return Err(invalid("PROXY v1 header is too long"));
+        }
+        let mut byte = [0; 1];
+        reader.read_exact(&mut byte)?;
+        line.push(byte[0]);
+    }
+    let line = std::str::from_utf8(&line[..line.len() - 2]).map_err(|_| invalid("PROXY v1 header is not ASCII"))?;
+    let fields: Vec<&str> = line.split(' ').collect();
+    match fields.as_slice() {
+        ["PROXY", "UNKNOWN", ..] => Ok(None),
+        ["PROXY", family @ ("TCP4" | "TCP6"), source, destination, source_port, destination_port] => {
+            let parse_ip = |ip: &str| -> std::io::Result<IpAddr> {
+                let ip = ip.parse::<IpAddr>().map_err(|_| invalid("invalid address in PROXY v1 header"))?;
+                if ip.is_ipv4() != (*family == "TCP4") {
+                    return Err(invalid("address does not match the family in PROXY v1 header"));
+                }
+                Ok(ip)
+            };
+            let parse_port = |port: &str| -> std::io::Result<u16> {
+                port.parse::<u16>().map_err(|_| invalid("invalid port in PROXY v1 header"))
+            };
+            Ok(Some(ProxiedAddrs {
+                source: SocketAddr::new(parse_ip(source)?, parse_port(source_port)?),
+                destination: SocketAddr::new(parse_ip(destination)?, parse_port(destination_port)?),
+            }))
+        }
+        _ => Err(invalid("malformed PROXY v1 header")),
+    }
+}
+
+/// The binary header: signature, version and command, family and protocol, length, addresses, TLVs
+fn read_v2<R: BufRead>(reader: &mut R) -> std::io::Result<Option<ProxiedAddrs>> {
+    let mut fixed = [0; 4];
+    reader.read_exact(&mut fixed)?;
+    let version = fixed[0] >> 4;
+    let command = fixed[0] & 0x0F;
+    let family = fixed[1];
+    let length = u16::from_be_bytes([fixed[2], fixed[3]]) as usize;
+    if version != 2 {
+        return Err(invalid("unsupported PROXY protocol version"));
+    }
+    let mut payload = vec![0; length];
+    reader.read_exact(&mut payload)?;
+    match command {
+        // LOCAL: health checks from the proxy itself, the real addresses apply
+        0x0 => return Ok(None),
+        0x1 => {}
+        _ => return Err(invalid("unsupported PROXY v2 command")),
+    }
+    // The payload may continue with TLVs, which are skipped
+    match family {
+        // TCP or UDP over IPv4
+        0x11 | 0x12 if length >= 12 => {
+            let ip = |at: usize| IpAddr::V4(Ipv4Addr::new(payload[at], payload[at + 1], payload[at + 2], payload[at + 3]));
+            let port = |at: usize| u16::from_be_bytes([payload[at], payload[at + 1]]);
+            Ok(Some(ProxiedAddrs {
+                source: SocketAddr::new(ip(0), port(8)),
+                destination: SocketAddr::new(ip(4), port(10)),
+            }))
+        }
+        // TCP or UDP over IPv6
+        0x21 | 0x22 if length >= 36 => {
+            let ip = |at: usize| {
+                let mut octets = [0; 16];
+                octets.copy_from_slice(&payload[at..at + 16]);
+                IpAddr::V6(Ipv6Addr::from(octets))
+            };
+            let port = |at: usize| u16::from_be_bytes([payload[at], payload[at + 1]]);
+            Ok(Some(ProxiedAddrs {
+                source: SocketAddr::new(ip(0), port(32)),
+                destination: SocketAddr::new(ip(16), port(34)),
+            }))
+        }
+        0x11 | 0x12 | 0x21 | 0x22 => Err(invalid("PROXY v2 addresses are truncated")),
+        // UNSPEC and unix sockets carry no usable address
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Read};
+
+    fn read(bytes: &[u8]) -> (std::io::Result<Option<ProxiedAddrs>>, String) {
+        let mut reader = Cursor::new(bytes.to_vec());
+        let result = read_header(&mut reader);
+        let mut rest = String::new();
+        let _ = reader.read_to_string(&mut rest);
+        (result, rest)
+    }
+
+    fn addrs(source: &str, destination: &str) -> Option<ProxiedAddrs> {
+        Some(ProxiedAddrs {
+            source: source.parse().unwrap(),
+            destination: destination.parse().unwrap(),
+        })
+    }
+
+    fn v2(command: u8, family: u8, payload: &[u8]) -> Vec<u8> {
+        let mut header = V2_SIGNATURE.to_vec();
+        header.extend_from_slice(&[0x20 | command, family]);
+        header.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        header.extend_from_slice(payload);
+        header.extend_from_slice(b"GET / HTTP/1.1\r\n");
+        header
+    }
+
+    #[test]
+    fn reads_v1_headers() {
+        let (result, rest) = read(b"PROXY TCP4 192.168.0.1 192.168.0.11 56324 443\r\nGET / HTTP/1.1\r\n");
+        assert_eq!(result.unwrap(), addrs("192.168.0.1:56324", "192.168.0.11:443"));
+        assert_eq!(rest, "GET / HTTP/1.1\r\n");
+
+        let (result, _) = read(b"PROXY TCP6 2001:db8::1 ::1 56324 443\r\n");
+        assert_eq!(result.unwrap(), addrs("[2001:db8::1]:56324", "[::1]:443"));
+
+        let (result, rest) = read(b"PROXY UNKNOWN\r\nGET / HTTP/1.1\r\n");
+        assert_eq!(result.unwrap(), None);
+        assert_eq!(rest, "GET / HTTP/1.1\r\n");
+    }
+
+    #[test]
+    fn rejects_malformed_v1_headers() {
+        for header in [
+            &b"PROXY TCP4 192.168.0.1 192.168.0.11 56324\r\n"[..],
+            b"PROXY TCP4 192.168.0.1 192.168.0.11 56324 443 extra\r\n",
+            b"PROXY TCP4 ::1 192.168.0.11 56324 443\r\n",
+            b"PROXY TCP6 192.168.0.1 ::1 56324 443\r\n",
+            b"PROXY TCP4 192.168.0.300 192.168.0.11 56324 443\r\n",
+            b"PROXY TCP4 192.168.0.1 192.168.0.11 65536 443\r\n",
+            b"PROXY UDP4 192.168.0.1 192.168.0.11 56324 443\r\n",
+            b"PROXY TCP4  192.168.0.1 192.168.0.11 56324 443\r\n",
+            b"PROXY TCP4 192.168.0.1 192.168.0.11 56324 443\n",
+        ] {
+            let (result, _) = read(header);
+            assert!(result.is_err(), "{:?}", String::from_utf8_lossy(header));
+        }
+    }
+
+    #[test]
+    fn limits_v1_length() {
+        let mut header = b"PROXY UNKNOWN ".to_vec();
+        header.resize(200, b'x');
+        header.extend_from_slice(b"\r\n");
+        assert_eq!(read(&header).0.unwrap_err().to_string(), "PROXY v1 header is too long");
+    }
+
+    #[test]
+    fn rejects_other_protocols() {
+        assert_eq!(read(b"GET / HTTP/1.1\r\nHost: x\r\n\r\n").0.unwrap_err().kind(), ErrorKind::InvalidData);
+        assert_eq!(read(b"PROXY").0.unwrap_err().kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn reads_v2_headers() {
+        let ipv4 = [127, 0, 0, 1, 10, 0, 0, 2, 0x1F, 0x90, 0x01, 0xBB];
+        let (result, rest) = read(&v2(0x1, 0x11, &ipv4));
+        assert_eq!(result.unwrap(), addrs("127.0.0.1:8080", "10.0.0.2:443"));
+        assert_eq!(rest, "GET / HTTP/1.1\r\n");
+
+        let mut ipv6 = Vec::new();
+        ipv6.extend_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        ipv6.extend_from_slice(&Ipv6Addr::LOCALHOST.octets());
+        ipv6.extend_from_slice(&[0x1F, 0x90, 0x01, 0xBB]);
+        let (result, _) = read(&v2(0x1, 0x21, &ipv6));
+        assert_eq!(result.unwrap(), addrs("[2001:db8::1]:8080", "[::1]:443"));
+    }
+
+    #[test]
+    fn skips_v2_tlvs() {
+        let mut payload = vec![127, 0, 0, 1, 10, 0, 0, 2, 0x1F, 0x90, 0x01, 0xBB];
+        // PP2_TYPE_AUTHORITY "example.com"
+        payload.extend_from_slice(&[0x02, 0x00, 0x0B]);
+        payload.extend_from_slice(b"example.com");
+        let (result, rest) = read(&v2(0x1, 0x11, &payload));
+        assert_eq!(result.unwrap(), addrs("127.0.0.1:8080", "10.0.0.2:443"));
+        assert_eq!(rest, "GET / HTTP/1.1\r\n");
+    }
+
+    #[test]
+    fn reads_v2_headers_without_addresses() {
+        // LOCAL, with addresses that are ignored
+        let (result, rest) = read(&v2(0x0, 0x11, &[127, 0, 0, 1, 10, 0, 0, 2, 0x1F, 0x90, 0x01, 0xBB]));
+        assert_eq!(result.unwrap(), None);
+        assert_eq!(rest, "GET / HTTP/1.1\r\n");
+        // UNSPEC and unix sockets
+        assert_eq!(read(&v2(0x1, 0x00, &[])).0.unwrap(), None);
+        assert_eq!(read(&v2(0x1, 0x31, &[0; 216])).0.unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_malformed_v2_headers() {
+        let truncated = v2(0x1, 0x11, &[127, 0, 0, 1, 10, 0, 0, 2]);
+        assert_eq!(read(&truncated).0.unwrap_err().to_string(), "PROXY v2 addresses are truncated");
+        assert_eq!(read(&v2(0x1, 0x21, &[0; 12])).0.unwrap_err().to_string(), "PROXY v2 addresses are truncated");
+        assert_eq!(read(&v2(0x2, 0x11, &[0; 12])).0.unwrap_err().to_string(), "unsupported PROXY v2 command");
+
+        let mut version_one = v2(0x1, 0x11, &[0; 12]);
+        version_one[12] = 0x11;
+        assert_eq!(read(&version_one).0.unwrap_err().to_string(), "unsupported PROXY protocol version");
+
+        // The length claims more than was sent
+        let mut short = V2_SIGNATURE.to_vec();
+        short.extend_from_slice(&[0x21, 0x11, 0x00, 0x0C, 127, 0, 0, 1]);
+        assert_eq!(read(&short).0.unwrap_err().kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn times_out_waiting_for_a_header() {
+        let (server, _client) = crate::packcake::testing::connection();
+        server.set_read_timeout(Some(Duration::from_millis(20))).unwrap();
+        let mut reader = std::io::BufReader::new(server);
+        let error = read_header(&mut reader).unwrap_err();
+        assert!(matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut), "{error}");
+    }
+}
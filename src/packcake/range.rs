@@ -0,0 +1,329 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::packcake::date::parse_http_date;
+use crate::packcake::{Response, StatusCode};
+
+/// More ranges than this, after merging overlapping ones, are ignored and the whole body is sent
+const MAX_RANGES: usize = 32;
+
+/// The `Range` and `If-Range` headers of a `GET` request
+#[derive(Clone, Debug)]
+pub(crate) struct RangeRequest {
+    pub(crate) range: String,
+    pub(crate) if_range: Option<String>,
+}
+
+/// A piece of a body to send: `head`, then bytes `start..end` of the body
+struct Part {
+    head: Vec<u8>,
+    start: u64,
+    end: u64,
+}
+
+/// Which parts of a body a response sends
+pub(crate) struct Plan {
+    parts: Vec<Part>,
+    tail: Vec<u8>,
+}
+
+impl Plan {
+    fn whole(length: u64) -> Plan {
+        Plan {
+            parts: vec![Part { head: Vec::new(), start: 0, end: length }],
+            tail: Vec::new(),
+        }
+    }
+
+    /// Whether the plan sends the body as it is
+    pub(crate) fn is_whole(&self, length: u64) -> bool {
+        matches!(self.parts.as_slice(), [part] if part.head.is_empty() && part.start == 0 && part.end == length)
+    }
+
+    /// The number of bytes the plan sends, for `Content-Length`
+    pub(crate) fn length(&self) -> u64 {
+        self.parts.iter().map(|part| part.head.len() as u64 + part.end - part.start).sum::<u64>() + self.tail.len() as u64
+    }
+
+    /// Copies the planned parts of `source` to `writer`
+    pub(crate) fn write<S: Read + Seek, W: Write>(&self, source: &mut S, writer: &mut W) -> std::io::Result<()> {
+        for part in &self.parts {
+            writer.write_all(&part.head)?;
+            source.seek(SeekFrom::Start(part.start))?;
+            let copied = std::io::copy(&mut source.take(part.end - part.start), writer)?;
+            if copied != part.end - part.start {
+                return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "body is shorter than planned"));
+            }
+        }
+        writer.write_all(&self.tail)
+    }
+}
+
+/// Plans a `length` byte body for the range request the response answers, if any
+///
+/// Sets the status and headers to match: `Accept-Ranges` on `200 OK` responses, and for range
+/// requests `206 Partial Content` with `Content-Range` (and a `multipart/byteranges` type for
+/// several ranges), or `416 Range Not Satisfiable`. `Content-Length` is left to the caller.
+pub(crate) fn plan(response: &mut Response, length: u64) -> Plan {
+    if response.status != StatusCode::Ok {
+        return Plan::whole(length);
+    }
+    response.header("Accept-Ranges", "bytes");
+    let Some(request) = response.range.clone() else {
+        return Plan::whole(length);
+    };
+    if let Some(if_range) = &request.if_range {
+        if !if_range_matches(if_range, response.get_header("ETag"), response.get_header("Last-Modified")) {
+            return Plan::whole(length);
+        }
+    }
+    let Some(ranges) = parse_ranges(&request.range, length) else {
+        return Plan::whole(length);
+    };
+    match ranges.as_slice() {
+        [] => {
+            response.status(StatusCode::RangeNotSatisfiable);
+            response.header("Content-Range", &format!("bytes */{length}"));
+            response.remove_header("Content-Type");
+            Plan {
+                parts: Vec::new(),
+                tail: Vec::new(),
+            }
+        }
+        [(start, end)] => {
+            response.status(StatusCode::PartialContent);
+            response.header("Content-Range", &format!("bytes {start}-{}/{length}", end - 1));
+            Plan {
+                parts: vec![Part { head: Vec::new(), start: *start, end: *end }],
+                tail: Vec::new(),
+            }
+        }
+        ranges => {
+            let boundary = boundary();
+            let content_type = response.get_header("Content-Type")
+                .map(|content_type| format!("Content-Type: {content_type}\r\n"))
+                .unwrap_or_default();
+            response.status(StatusCode::PartialContent);
+            response.header("Content-Type", &format!("multipart/byteranges; boundary={boundary}"));
+            let parts = ranges.iter()
+                .map(|(start, end)| Part {
+                    head: format!("\r\n--{boundary}\r\n{content_type}Content-Range: bytes {start}-{}/{length}\r\n\r\n", end - 1)
+                        .into_bytes(),
+                    start: *start,
+                    end: *end,
+                })
+                .collect();
+            Plan {
+                parts,
+                tail: format!("\r\n--{boundary}--\r\n").into_bytes(),
+            }
+        }
+    }
+}
+
+/// Parses a `Range` header into merged `start..end` ranges of a `length` byte body
+///
+/// `None` when the header should be ignored: another unit, bad syntax or too many ranges.
+/// An empty list when no range is satisfiable.
+fn parse_ranges(header: &str, length: u64) -> Option<Vec<(u64, u64)>> {
+    let (unit, specs) = header.split_once('=')?;
+    if !unit.trim().eq_ignore_ascii_case("bytes") {
+        return None;
+    }
+    let mut ranges = Vec::new();
+    for spec in specs.split(',').map(str::trim).filter(|spec| !spec.is_empty()) {
+        let (first, last) = spec.split_once('-')?;
+        let (first, last) = (first.trim(), last.trim());
+        let range = if first.is_empty() {
+            // The last `n` bytes
+            let suffix = last.parse::<u64>().ok()?;
+            (suffix > 0 && length > 0).then(|| (length.saturating_sub(suffix), length))
+        } else {
+            let first = first.parse::<u64>().ok()?;
+            let last = if last.is_empty() { None } else { Some(last.parse::<u64>().ok()?) };
+            if last.is_some_and(|last| last < first) {
+                return None;
+            }
+            (first < length).then(|| (first, last.map_or(length, |last| (last + 1).min(length))))
+        };
+        ranges.extend(range);
+    }
+    ranges.sort();
+    let mut merged: Vec<(u64, u64)> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    (merged.len() <= MAX_RANGES).then_some(merged)
+}
+
+/// Whether the representation the client has, named by `If-Range`, is still the current one
+///
+/// An entity tag must match the strong `ETag` exactly; a date must equal `Last-Modified`.
+fn if_range_matches(if_range: &str, etag: Option<&String>, last_modified: Option<&String>) -> bool {
+    let if_range = if_range.trim();
+    if if_range.starts_with('"') || if_range.starts_with("W/") {
+        return etag.is_some_and(|etag| !etag.starts_with("W/") && etag == if_range);
+    }
+    match (parse_http_date(if_range), last_modified.and_then(|date| parse_http_date(date))) {
+        (Some(since), Some(modified)) => since == modified,
+        _ => false,
+    }
+}
+
+fn boundary() -> String {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos());
+    format!("packcake-{nanos:x}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::io::Cursor;
+    use std::net::{TcpListener, TcpStream};
+    use crate::packcake::Request;
+
+    const BODY: &[u8] = b"0123456789abcdefghij";
+    const MODIFIED: &str = "Sun, 06 Nov 1994 08:49:37 GMT";
+
+    /// A `GET` response with the given request headers, and an `ETag` and `Last-Modified`
+    fn response(headers: &[(&str, &str)]) -> Response {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let _client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let headers = headers.iter().map(|(h, v)| (h.to_string(), v.to_string())).collect::<HashMap<_, _>>();
+        let mut response = Response::from_stream(stream, &Request::new("GET /file HTTP/1.1", headers, String::new()));
+        response.header("Content-Type", "text/plain");
+        response.header("ETag", "\"v1\"");
+        response.header("Last-Modified", MODIFIED);
+        response
+    }
+
+    fn send(plan: &Plan) -> String {
+        let mut output = Vec::new();
+        plan.write(&mut Cursor::new(BODY), &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn parses_ranges() {
+        assert_eq!(parse_ranges("bytes=0-4", 20), Some(vec![(0, 5)]));
+        assert_eq!(parse_ranges("bytes=15-", 20), Some(vec![(15, 20)]));
+        assert_eq!(parse_ranges("bytes=10-99", 20), Some(vec![(10, 20)]));
+        assert_eq!(parse_ranges("BYTES = 1-1", 20), Some(vec![(1, 2)]));
+        assert_eq!(parse_ranges("items=0-4", 20), None);
+        assert_eq!(parse_ranges("bytes=4-0", 20), None);
+        assert_eq!(parse_ranges("bytes=a-b", 20), None);
+        assert_eq!(parse_ranges("bytes=5", 20), None);
+    }
+
+    #[test]
+    fn parses_suffix_ranges() {
+        assert_eq!(parse_ranges("bytes=-5", 20), Some(vec![(15, 20)]));
+        // Longer than the body: all of it
+        assert_eq!(parse_ranges("bytes=-50", 20), Some(vec![(0, 20)]));
+        assert_eq!(parse_ranges("bytes=-0", 20), Some(vec![]));
+        assert_eq!(parse_ranges("bytes=-5", 0), Some(vec![]));
+    }
+
+    #[test]
+    fn merges_overlapping_ranges() {
+        assert_eq!(parse_ranges("bytes=10-14,0-4,3-7", 20), Some(vec![(0, 8), (10, 15)]));
+        // Adjacent ranges become one
+        assert_eq!(parse_ranges("bytes=0-4,5-9", 20), Some(vec![(0, 10)]));
+        assert_eq!(parse_ranges("bytes=0-4,-18", 20), Some(vec![(0, 20)]));
+        // Unsatisfiable ranges are dropped
+        assert_eq!(parse_ranges("bytes=30-40,0-1", 20), Some(vec![(0, 2)]));
+    }
+
+    #[test]
+    fn caps_the_number_of_ranges() {
+        let ranges = |count: u64| (0..count).map(|i| format!("{}-{}", i * 2, i * 2)).collect::<Vec<_>>().join(",");
+        let header = format!("bytes={}", ranges(MAX_RANGES as u64));
+        assert_eq!(parse_ranges(&header, 1000).map(|ranges| ranges.len()), Some(MAX_RANGES));
+        let header = format!("bytes={}", ranges(MAX_RANGES as u64 + 1));
+        assert_eq!(parse_ranges(&header, 1000), None);
+        // Counted after merging
+        let header = format!("bytes={}", vec!["0-9"; 100].join(","));
+        assert_eq!(parse_ranges(&header, 1000), Some(vec![(0, 10)]));
+    }
+
+    #[test]
+    fn sends_single_ranges() {
+        let mut response = response(&[("Range", "bytes=-5")]);
+        let plan = plan(&mut response, BODY.len() as u64);
+        assert_eq!(response.status, StatusCode::PartialContent);
+        assert_eq!(response.get_header("Content-Range").unwrap(), "bytes 15-19/20");
+        assert_eq!(plan.length(), 5);
+        assert_eq!(send(&plan), "fghij");
+    }
+
+    #[test]
+    fn sends_several_ranges_as_multipart() {
+        let mut response = response(&[("Range", "bytes=0-1,10-11")]);
+        let plan = plan(&mut response, BODY.len() as u64);
+        assert_eq!(response.status, StatusCode::PartialContent);
+        let content_type = response.get_header("Content-Type").unwrap().clone();
+        let boundary = content_type.strip_prefix("multipart/byteranges; boundary=").unwrap();
+        let expected = format!(
+            "\r\n--{boundary}\r\nContent-Type: text/plain\r\nContent-Range: bytes 0-1/20\r\n\r\n01\
+             \r\n--{boundary}\r\nContent-Type: text/plain\r\nContent-Range: bytes 10-11/20\r\n\r\nab\
+             \r\n--{boundary}--\r\n"
+        );
+        assert_eq!(send(&plan), expected);
+        assert_eq!(plan.length(), expected.len() as u64);
+    }
+
+    #[test]
+    fn rejects_unsatisfiable_ranges() {
+        let mut response = response(&[("Range", "bytes=20-30")]);
+        let plan = plan(&mut response, BODY.len() as u64);
+        assert_eq!(response.status, StatusCode::RangeNotSatisfiable);
+        assert_eq!(response.get_header("Content-Range").unwrap(), "bytes */20");
+        assert!(response.get_header("Content-Type").is_none());
+        assert_eq!(plan.length(), 0);
+    }
+
+    #[test]
+    fn sends_the_whole_body_without_a_usable_range() {
+        for headers in [
+            &[][..],
+            &[("Range", "bytes=5-1")],
+            &[("Range", "lines=1-2")],
+        ] {
+            let mut response = response(headers);
+            let plan = plan(&mut response, BODY.len() as u64);
+            assert_eq!(response.status, StatusCode::Ok);
+            assert_eq!(response.get_header("Accept-Ranges").unwrap(), "bytes");
+            assert!(plan.is_whole(BODY.len() as u64));
+        }
+    }
+
+    #[test]
+    fn checks_if_range() {
+        let etag = String::from("\"v1\"");
+        let modified = String::from(MODIFIED);
+        assert!(if_range_matches("\"v1\"", Some(&etag), Some(&modified)));
+        assert!(!if_range_matches("\"v2\"", Some(&etag), Some(&modified)));
+        assert!(!if_range_matches("W/\"v1\"", Some(&etag), Some(&modified)));
+        assert!(!if_range_matches("W/\"v1\"", Some(&String::from("W/\"v1\"")), None));
+        assert!(if_range_matches(MODIFIED, Some(&etag), Some(&modified)));
+        assert!(!if_range_matches("Sun, 06 Nov 1994 08:49:38 GMT", Some(&etag), Some(&modified)));
+        assert!(!if_range_matches(MODIFIED, Some(&etag), None));
+    }
+
+    #[test]
+    fn sends_the_whole_body_when_if_range_does_not_match() {
+        let mut stale = response(&[("Range", "bytes=0-4"), ("If-Range", "\"v0\"")]);
+        let whole = plan(&mut stale, BODY.len() as u64);
+        assert_eq!(stale.status, StatusCode::Ok);
+        assert!(whole.is_whole(BODY.len() as u64));
+
+        let mut current = response(&[("Range", "bytes=0-4"), ("If-Range", "\"v1\"")]);
+        let partial = plan(&mut current, BODY.len() as u64);
+        assert_eq!(current.status, StatusCode::PartialContent);
+        assert_eq!(send(&partial), "01234");
+    }
+}
@@ -0,0 +1,274 @@
+use std::fmt::{Display, Formatter};
+use crate::packcake::json::{JsonError, Value};
+use crate::packcake::params::{error_body, ParamError};
+use crate::packcake::extract::Rejection;
+use crate::packcake::mime::OCTET_STREAM;
+use crate::packcake::{Response, StatusCode};
+
+/// A value a handler can return instead of writing to the `Response` itself
+///
+/// ```ignore
+/// fn get_team(request: &Request) -> Result<Value, ParamError> {
+///     let id = request.get_param_as::<u64>("id")?;
+///     Ok(Value::object([("id", Value::from(id))]))
+/// }
+/// ```
+pub trait IntoResponse {
+    fn into_response(self, response: &mut Response);
+}
+
+/// Sends text as `text/plain`
+impl IntoResponse for &str {
+    fn into_response(self, response: &mut Response) {
+        response.text(self);
+    }
+}
+
+impl IntoResponse for String {
+    fn into_response(self, response: &mut Response) {
+        self.as_str().into_response(response);
+    }
+}
+
+/// Sends bytes as `application/octet-stream`
+impl IntoResponse for Vec<u8> {
+    fn into_response(self, response: &mut Response) {
+        response.content_type(OCTET_STREAM);
+        response.send_bytes(&self);
+    }
+}
+
+impl IntoResponse for Value {
+    fn into_response(self, response: &mut Response) {
+        response.json_value(&self);
+    }
+}
+
+/// Sends the body with the given status
+impl<B: IntoResponse> IntoResponse for (StatusCode, B) {
+    fn into_response(self, response: &mut Response) {
+        response.status(self.0);
+        self.1.into_response(response);
+    }
+}
+
+impl<T: IntoResponse, E: IntoResponse> IntoResponse for Result<T, E> {
+    fn into_response(self, response: &mut Response) {
+        match self {
+            Ok(value) => value.into_response(response),
+            Err(error) => error.into_response(response),
+        }
+    }
+}
+
+impl IntoResponse for Rejection {
+    fn into_response(self, response: &mut Response) {
+        self.send(response);
+    }
+}
+
+/// Sends `400 Bad Request` with the same body as a failed `require_query`
+impl IntoResponse for ParamError {
+    fn into_response(self, response: &mut Response) {
+        response.status(StatusCode::BadRequest);
+        response.json_value(&error_body(&[self]));
+    }
+}
+
+impl IntoResponse for JsonError {
+    fn into_response(self, response: &mut Response) {
+        Rejection::bad_request(&format!("Invalid JSON: {self}")).send(response);
+    }
+}
+
+/// A redirect to another location
+pub struct Redirect {
+    status: StatusCode,
+    location: String,
+}
+
+impl Redirect {
+    #[allow(dead_code)]
+    /// `303 See Other`; the client follows with a GET
+    pub fn to(location: &str) -> Redirect {
+        Redirect {
+            status: StatusCode::SeeOther,
+            location: encode_location(location),
+        }
+    }
+
+    #[allow(dead_code)]
+    /// `307 Temporary Redirect`; the client repeats the request as it was
+    pub fn temporary(location: &str) -> Redirect {
+        Redirect {
+            status: StatusCode::TemporaryRedirect,
+            location: encode_location(location),
+        }
+    }
+
+    /// `308 Permanent Redirect`; the client repeats the request as it was
+    pub fn permanent(location: &str) -> Redirect {
+        Redirect {
+            status: StatusCode::PermanentRedirect,
+            location: encode_location(location),
+        }
+    }
+}
+
+/// Percent-encodes the bytes that can't appear in a `Location` header as they are: control
+/// characters, spaces and anything outside ASCII
+fn encode_location(location: &str) -> String {
+    let mut encoded = String::with_capacity(location.len());
+    for byte in location.bytes() {
+        if byte.is_ascii_graphic() {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
+}
+
+impl IntoResponse for Redirect {
+    fn into_response(self, response: &mut Response) {
+        response.status(self.status);
+        response.header("Location", &self.location);
+        response.send("");
+    }
+}
+
+/// Why a response could not be sent
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ResponseError {
+    /// The handler already sent a body; a response can only be sent once
+    AlreadySent,
+    /// The response could not be written to the connection
+    Io(std::io::ErrorKind),
+}
+
+impl Display for ResponseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResponseError::AlreadySent => write!(f, "Response was already sent; ignoring the second body"),
+            ResponseError::Io(kind) => write!(f, "Could not write response: {kind}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packcake::params::ParamLocation;
+    use std::io::Read;
+    use crate::packcake::testing::{body, connection, header, request, respond_text};
+
+    fn respond_with(value: impl IntoResponse) -> String {
+        respond_text(&request("GET / HTTP/1.1", &[], ""), |_, response| value.into_response(response))
+    }
+
+    #[test]
+    fn sends_text_and_bytes() {
+        let text = respond_with("hello");
+        assert!(text.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(header(&text, "Content-Type").unwrap().starts_with("text/plain"));
+        assert_eq!(body(&text), "hello");
+        assert_eq!(body(&respond_with(String::from("owned"))), "owned");
+        let bytes = respond_with(vec![1u8, 2, 3]);
+        assert_eq!(header(&bytes, "Content-Type"), Some(OCTET_STREAM));
+        assert_eq!(body(&bytes).as_bytes(), &[1, 2, 3]);
+    }
+
+    #[test]
+    fn sends_json_with_a_status() {
+        let created = respond_with((StatusCode::Created, Value::object([("id", Value::from(7u64))])));
+        assert!(created.starts_with("HTTP/1.1 201 Created\r\n"));
+        assert!(header(&created, "Content-Type").unwrap().starts_with("application/json"));
+        assert_eq!(body(&created), r#"{"id":7}"#);
+    }
+
+    #[test]
+    fn sends_either_side_of_a_result() {
+        let ok: Result<&str, Rejection> = Ok("fine");
+        assert_eq!(body(&respond_with(ok)), "fine");
+        let err: Result<&str, Rejection> = Err(Rejection::bad_request("no"));
+        let text = respond_with(err);
+        assert!(text.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        assert_eq!(body(&text), r#"{"error":"Bad Request","message":"no"}"#);
+    }
+
+    #[test]
+    fn sends_param_and_json_errors_as_400() {
+        let missing = ParamError::Missing { location: ParamLocation::Query, name: String::from("id") };
+        let text = respond_with(missing.clone());
+        assert!(text.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        assert_eq!(body(&text), error_body(&[missing]).to_string());
+        let invalid = respond_with(Value::parse("{").unwrap_err());
+        assert!(invalid.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        assert!(body(&invalid).contains("Invalid JSON: "));
+    }
+
+    #[test]
+    fn redirects() {
+        let see_other = respond_with(Redirect::to("/teams/1"));
+        assert!(see_other.starts_with("HTTP/1.1 303 See Other\r\n"));
+        assert_eq!(header(&see_other, "Location"), Some("/teams/1"));
+        assert!(respond_with(Redirect::temporary("/a")).starts_with("HTTP/1.1 307 Temporary Redirect\r\n"));
+        let permanent = respond_with(Redirect::permanent("https://example.com/b?c=d#e"));
+        assert!(permanent.starts_with("HTTP/1.1 308 Permanent Redirect\r\n"));
+        assert_eq!(header(&permanent, "Location"), Some("https://example.com/b?c=d#e"));
+    }
+
+    #[test]
+    fn encodes_control_characters_in_redirects() {
+        let text = respond_with(Redirect::to("/a\r\nSet-Cookie: session=evil"));
+        assert_eq!(header(&text, "Location"), Some("/a%0D%0ASet-Cookie:%20session=evil"));
+        assert_eq!(header(&text, "Set-Cookie"), None);
+        assert_eq!(header(&respond_with(Redirect::to("/café")), "Location"), Some("/caf%C3%A9"));
+    }
+
+    #[test]
+    fn ignores_headers_that_would_split_the_response() {
+        let text = respond_text(&request("GET / HTTP/1.1", &[], ""), |_, response| {
+            response.header("X-Name", "a\r\nSet-Cookie: session=evil");
+            response.append_header("X-Other\n", "b");
+            response.header("X-Tab", "a\tb");
+            response.send("");
+        });
+        assert_eq!(header(&text, "X-Name"), None);
+        assert_eq!(header(&text, "Set-Cookie"), None);
+        assert!(!text.contains("X-Other"));
+        assert_eq!(header(&text, "X-Tab"), Some("a\tb"));
+    }
+
+    #[test]
+    fn sends_a_response_only_once() {
+        let text = respond_text(&request("GET / HTTP/1.1", &[], ""), |_, response| {
+            assert!(!response.is_sent());
+            response.send("first");
+            assert!(response.is_sent());
+            response.send("second");
+            assert_eq!(response.try_send("third"), Err(ResponseError::AlreadySent));
+            assert_eq!(response.try_send_bytes(b"fourth"), Err(ResponseError::AlreadySent));
+            // Still buffered, so the head can change after the body
+            response.status(StatusCode::Accepted);
+            response.header("X-Late", "yes");
+        });
+        assert!(text.starts_with("HTTP/1.1 202 Accepted\r\n"));
+        assert_eq!(header(&text, "X-Late"), Some("yes"));
+        assert_eq!(body(&text), "first");
+    }
+
+    #[test]
+    fn sends_the_unsent_status_when_nothing_was_sent() {
+        let text = respond_text(&request("GET / HTTP/1.1", &[], ""), |_, response| response.status(StatusCode::Ok));
+        assert!(text.starts_with("HTTP/1.1 500 Internal Server Error\r\n"));
+        assert_eq!(body(&text), "");
+        let (server, mut client) = connection();
+        let mut response = Response::from_stream(server, &request("GET / HTTP/1.1", &[], ""));
+        response.commit(&StatusCode::NoContent);
+        drop(response);
+        let mut text = String::new();
+        client.read_to_string(&mut text).unwrap();
+        assert!(text.starts_with("HTTP/1.1 204 No Content\r\n"));
+    }
+}
@@ -0,0 +1,294 @@
+use std::fmt::{Display, Formatter};
+use std::io::{ErrorKind, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How long an event stream may stay quiet before a heartbeat comment is sent
+pub const DEFAULT_HEARTBEAT: Duration = Duration::from_secs(15);
+
+/// One Server-Sent Event
+///
+/// ```ignore
+/// events.send(&Event::data("{\"cpu\":0.42}").event("stats").id("17"))?;
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Event {
+    id: Option<String>,
+    event: Option<String>,
+    data: Option<String>,
+    retry: Option<Duration>,
+    comment: Option<String>,
+}
+
+impl Event {
+    /// An event carrying `data`; it may span several lines
+    pub fn data(data: &str) -> Event {
+        Event {
+            data: Some(data.to_string()),
+            ..Event::default()
+        }
+    }
+
+    /// A comment, which clients ignore
+    pub fn comment(comment: &str) -> Event {
+        Event {
+            comment: Some(comment.to_string()),
+            ..Event::default()
+        }
+    }
+
+    /// Only tells the client how long to wait before reconnecting
+    pub fn retry_only(retry: Duration) -> Event {
+        Event {
+            retry: Some(retry),
+            ..Event::default()
+        }
+    }
+
+    /// Sets the id, which the client sends back as `Last-Event-ID` when it reconnects
+    pub fn id(mut self, id: &str) -> Event {
+        self.id = Some(single_line(id));
+        self
+    }
+
+    /// Sets the event type; clients listen for it with `addEventListener(type, ...)`
+    pub fn event(mut self, event: &str) -> Event {
+        self.event = Some(single_line(event));
+        self
+    }
+
+    /// Tells the client how long to wait before reconnecting
+    pub fn retry(mut self, retry: Duration) -> Event {
+        self.retry = Some(retry);
+        self
+    }
+}
+
+/// Line breaks would end the field early
+fn single_line(value: &str) -> String {
+    value.replace(['\r', '\n'], "")
+}
+
+/// The lines of `text`, split at `\r\n`, `\r` or `\n` like the client splits the stream
+fn lines(text: &str) -> impl Iterator<Item = &str> {
+    text.split("\r\n").flat_map(|line| line.split(['\r', '\n']))
+}
+
+/// The event in the `text/event-stream` format, ending with the empty line that dispatches it
+impl Display for Event {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(comment) = &self.comment {
+            for line in lines(comment) {
+                writeln!(f, ": {line}")?;
+            }
+        }
+        if let Some(retry) = self.retry {
+            writeln!(f, "retry: {}", retry.as_millis())?;
+        }
+        if let Some(id) = &self.id {
+            writeln!(f, "id: {id}")?;
+        }
+        if let Some(event) = &self.event {
+            writeln!(f, "event: {event}")?;
+        }
+        if let Some(data) = &self.data {
+            // Each line is a data field; the client joins them back with `\n`
+            for line in lines(data) {
+                writeln!(f, "data: {line}")?;
+            }
+        }
+        writeln!(f)
+    }
+}
+
+/// The connection an event stream writes to, shared with its heartbeat thread
+struct Shared {
+    stream: TcpStream,
+    last_write: Instant,
+    heartbeat: Duration,
+    closed: bool,
+}
+
+impl Shared {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        if self.closed {
+            return Err(std::io::Error::new(ErrorKind::NotConnected, "client disconnected"));
+        }
+        let result = self.stream.write_all(bytes).and_then(|_| self.stream.flush());
+        match result {
+            Ok(()) => self.last_write = Instant::now(),
+            Err(_) => self.closed = true,
+        }
+        result
+    }
+
+    /// Peeks at the connection; a client that went away has closed its side
+    fn check_connected(&mut self) -> bool {
+        if self.closed {
+            return false;
+        }
+        if self.stream.set_nonblocking(true).is_err() {
+            return true;
+        }
+        let connected = match self.stream.peek(&mut [0; 1]) {
+            Ok(0) => false,
+            Ok(_) => true,
+            Err(error) => error.kind() == ErrorKind::WouldBlock || error.kind() == ErrorKind::Interrupted,
+        };
+        let _ = self.stream.set_nonblocking(false);
+        self.closed = !connected;
+        connected
+    }
+}
+
+/// A `text/event-stream` response, from `Response::event_stream`
+///
+/// Heartbeat comments are sent whenever the stream has been quiet for a while, so proxies keep
+/// the connection open and a client that went away is noticed. Once it has, `send` fails and
+/// `is_connected` is false, and the handler should return to free its worker thread.
+///
+/// ```ignore
+/// let mut events = response.event_stream()?;
+/// while events.is_connected() {
+///     events.send(&Event::data(&stats()).event("stats"))?;
+///     thread::sleep(Duration::from_secs(1));
+/// }
+/// ```
+pub struct EventStream {
+    shared: Arc<Mutex<Shared>>,
+    heartbeat: Option<JoinHandle<()>>,
+}
+
+impl EventStream {
+    pub(crate) fn new(stream: TcpStream) -> EventStream {
+        let shared = Arc::new(Mutex::new(Shared {
+            stream,
+            last_write: Instant::now(),
+            heartbeat: DEFAULT_HEARTBEAT,
+            closed: false,
+        }));
+        let heartbeat = {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || heartbeat(shared))
+        };
+        EventStream {
+            shared,
+            heartbeat: Some(heartbeat),
+        }
+    }
+
+    pub(crate) fn write_head(&mut self, head: &[u8]) -> std::io::Result<()> {
+        self.lock().write(head)
+    }
+
+    /// Sends an event right away
+    pub fn send(&mut self, event: &Event) -> std::io::Result<()> {
+        self.lock().write(event.to_string().as_bytes())
+    }
+
+    /// Sends `data` as an event without id or type
+    pub fn send_data(&mut self, data: &str) -> std::io::Result<()> {
+        self.send(&Event::data(data))
+    }
+
+    /// Whether the client is still there
+    pub fn is_connected(&self) -> bool {
+        self.lock().check_connected()
+    }
+
+    /// Changes how long the stream may stay quiet before a heartbeat is sent (default 15 seconds)
+    pub fn set_heartbeat(&mut self, interval: Duration) {
+        self.lock().heartbeat = interval;
+        if let Some(heartbeat) = &self.heartbeat {
+            heartbeat.thread().unpark();
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Shared> {
+        self.shared.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Drop for EventStream {
+    fn drop(&mut self) {
+        self.lock().closed = true;
+        if let Some(heartbeat) = self.heartbeat.take() {
+            heartbeat.thread().unpark();
+            let _ = heartbeat.join();
+        }
+    }
+}
+
+fn heartbeat(shared: Arc<Mutex<Shared>>) {
+    loop {
+        let wait = {
+            let mut shared = shared.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            if shared.closed {
+                return;
+            }
+            let quiet = shared.last_write.elapsed();
+            if quiet >= shared.heartbeat {
+                if shared.write(b": heartbeat\n\n").is_err() {
+                    return;
+                }
+                shared.heartbeat
+            } else {
+                shared.heartbeat - quiet
+            }
+        };
+        // Woken early when the stream is dropped or the interval changes
+        thread::park_timeout(wait);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use crate::packcake::testing::connection;
+
+    #[test]
+    fn writes_each_line_of_data_as_a_field() {
+        assert_eq!(Event::data("hello").to_string(), "data: hello\n\n");
+        assert_eq!(Event::data("a\nb\r\nc").to_string(), "data: a\ndata: b\ndata: c\n\n");
+        assert_eq!(Event::data("a\n").to_string(), "data: a\ndata: \n\n");
+        assert_eq!(Event::data("").to_string(), "data: \n\n");
+    }
+
+    #[test]
+    fn splits_at_bare_carriage_returns() {
+        assert_eq!(Event::data("a\rid: 6\revent: evil").to_string(), "data: a\ndata: id: 6\ndata: event: evil\n\n");
+        assert_eq!(Event::comment("a\rdata: evil").to_string(), ": a\n: data: evil\n\n");
+    }
+
+    #[test]
+    fn keeps_id_and_event_on_one_line() {
+        let event = Event::data("x").id("1\r\ndata: evil").event("a\rb\nc");
+        assert_eq!(event.to_string(), "id: 1data: evil\nevent: abc\ndata: x\n\n");
+    }
+
+    #[test]
+    fn writes_every_field() {
+        let event = Event::data("{}").event("stats").id("17").retry(Duration::from_millis(2500));
+        assert_eq!(event.to_string(), "retry: 2500\nid: 17\nevent: stats\ndata: {}\n\n");
+        assert_eq!(Event::retry_only(Duration::from_secs(3)).to_string(), "retry: 3000\n\n");
+        assert_eq!(Event::comment("one\ntwo").to_string(), ": one\n: two\n\n");
+    }
+
+    #[test]
+    fn sends_heartbeats_while_quiet() {
+        let (server, mut client) = connection();
+        let mut events = EventStream::new(server);
+        events.set_heartbeat(Duration::from_millis(20));
+        let mut heartbeat = [0; 13];
+        client.read_exact(&mut heartbeat).unwrap();
+        assert_eq!(&heartbeat, b": heartbeat\n\n");
+        events.send_data("hi").unwrap();
+        drop(events);
+        let mut rest = String::new();
+        client.read_to_string(&mut rest).unwrap();
+        assert!(rest.contains("data: hi\n\n"), "{rest:?}");
+    }
+}
@@ -0,0 +1,702 @@
+use std::fs::{self, File};
+use std::fmt::Write;
+use std::io::{Cursor, ErrorKind, Read, Seek};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::packcake::compress::{quality, vary};
+use crate::packcake::date::http_date;
+use crate::packcake::embed::{EmbeddedDir, EmbeddedFile};
+use crate::packcake::etag::{self, Validators};
+use crate::packcake::mime::mime_for_path;
+use crate::packcake::range;
+use crate::packcake::respond::{IntoResponse, Redirect};
+use crate::packcake::url::{percent_decode, percent_encode};
+use crate::packcake::{Request, Response, StatusCode};
+
+/// A directory served by `Packcake::static_dir`, from disk or embedded in the binary
+///
+/// ```ignore
+/// Packcake::new(4)
+///     .static_dir_with("/files", StaticDir::new("./public").index("home.html").listing(true))
+///     .static_dir_with("/", StaticDir::new("./dist").spa(true).exclude("/api"))
+/// ```
+#[derive(Clone, Debug)]
+pub struct StaticDir {
+    source: Source,
+    index: Option<String>,
+    listing: bool,
+    spa: bool,
+    /// Path prefixes that never get the index file in SPA mode
+    excluded: Vec<String>,
+    /// Whether `.br` and `.gz` files next to a file are served in its place
+    precompressed: bool,
+    /// Whether files and directories whose name starts with a dot are served
+    dotfiles: bool,
+}
+
+/// The codings of precompressed files and their extensions, preferred first
+const PRECOMPRESSED: [(&str, &str); 2] = [("br", "br"), ("gzip", "gz")];
+
+#[derive(Clone, Debug)]
+enum Source {
+    Disk(PathBuf),
+    Embedded(EmbeddedDir),
+}
+
+impl StaticDir {
+    /// Serves the files under `root`, with `index.html` as index file and no directory listings
+    pub fn new(root: impl Into<PathBuf>) -> StaticDir {
+        StaticDir::from_source(Source::Disk(root.into()))
+    }
+
+    /// Serves files compiled into the binary; they can't be listed
+    pub fn embedded(files: EmbeddedDir) -> StaticDir {
+        StaticDir::from_source(Source::Embedded(files))
+    }
+
+    fn from_source(source: Source) -> StaticDir {
+        StaticDir {
+            source,
+            index: Some(String::from("index.html")),
+            listing: false,
+            spa: false,
+            excluded: Vec::new(),
+            precompressed: false,
+            dotfiles: false,
+        }
+    }
+
+    #[allow(dead_code)]
+    /// The file served for a directory
+    pub fn index(mut self, file: &str) -> StaticDir {
+        self.index = Some(file.to_string());
+        self
+    }
+
+    #[allow(dead_code)]
+    /// Serves no file for directories
+    pub fn no_index(mut self) -> StaticDir {
+        self.index = None;
+        self
+    }
+
+    #[allow(dead_code)]
+    /// Lists the contents of directories that have no index file
+    pub fn listing(mut self, listing: bool) -> StaticDir {
+        self.listing = listing;
+        self
+    }
+
+    #[allow(dead_code)]
+    /// Serves a single-page app: pages that are not files get the index file, so the app can route them
+    ///
+    /// Use `exclude` to keep the API out of it, so its misses still get a real 404. SPA mode
+    /// serves the index file at the root, so it does nothing after `no_index`.
+    pub fn spa(mut self, spa: bool) -> StaticDir {
+        self.spa = spa;
+        self
+    }
+
+    #[allow(dead_code)]
+    /// Never serves the index file in SPA mode for paths under `prefix`, like `"/api"`
+    pub fn exclude(mut self, prefix: &str) -> StaticDir {
+        self.excluded.push(prefix.trim_end_matches('/').to_string());
+        self
+    }
+
+    #[allow(dead_code)]
+    /// Serves `file.br` or `file.gz` instead of `file` to clients that accept that encoding
+    ///
+    /// For assets compressed when they are built, so they aren't compressed on every request.
+    pub fn precompressed(mut self, precompressed: bool) -> StaticDir {
+        self.precompressed = precompressed;
+        self
+    }
+
+    #[allow(dead_code)]
+    /// Serves files and directories whose name starts with a dot, like `.well-known`
+    ///
+    /// They are hidden by default, as they tend to be things like `.env` or `.git` that were
+    /// never meant to be published.
+    pub fn dotfiles(mut self, dotfiles: bool) -> StaticDir {
+        self.dotfiles = dotfiles;
+        self
+    }
+
+    /// Where the files come from, for logging
+    pub fn describe(&self) -> String {
+        match &self.source {
+            Source::Disk(root) => root.display().to_string(),
+            Source::Embedded(files) => format!("{} embedded files", files.len()),
+        }
+    }
+
+    /// Whether the `prefix` this directory is mounted at covers `path`
+    pub(crate) fn covers(prefix: &str, path: &str) -> bool {
+        prefix.is_empty() || path == prefix || path.strip_prefix(prefix).is_some_and(|rest| rest.starts_with('/'))
+    }
+
+    /// Serves the file for `request`, mounted at `prefix`
+    ///
+    /// Returns false when there is nothing to serve, so the request goes on to the fallback.
+    /// Files and directories only answer `GET` and `HEAD`; other methods get `405`, and never
+    /// the index file of SPA mode.
+    pub(crate) fn serve(&self, prefix: &str, request: &Request, response: &mut Response) -> bool {
+        let raw_path = request.get_raw_path();
+        let segments = segments(&raw_path[prefix.len().min(raw_path.len())..])
+            .filter(|segments| self.dotfiles || !segments.iter().any(|segment| segment.starts_with('.')));
+        if request.get_method() != "GET" && request.get_method() != "HEAD" {
+            if !segments.is_some_and(|segments| self.exists(&segments)) {
+                return false;
+            }
+            response.header("Allow", "GET, HEAD");
+            response.status(StatusCode::MethodNotAllowed);
+            response.text("Method Not Allowed");
+            return true;
+        }
+        let served = match segments {
+            None => false,
+            Some(segments) => match &self.source {
+                Source::Disk(root) => self.serve_disk(root, &segments, request, response),
+                Source::Embedded(files) => self.serve_embedded(files, &segments.join("/"), request, response),
+            },
+        };
+        served || (self.spa && self.serve_app(request, response))
+    }
+
+    /// Whether there is a file or directory at the path made of `segments`
+    fn exists(&self, segments: &[String]) -> bool {
+        match &self.source {
+            Source::Disk(root) => inside_root(root, &segments.iter().collect::<PathBuf>()).is_some(),
+            Source::Embedded(files) => {
+                let path = segments.join("/");
+                files.get(&path).is_some() || files.is_dir(&path)
+            }
+        }
+    }
+
+    fn serve_disk(&self, root: &Path, segments: &[String], request: &Request, response: &mut Response) -> bool {
+        let Some(path) = inside_root(root, &segments.iter().collect::<PathBuf>()) else {
+            return false;
+        };
+        if !path.is_dir() {
+            return match self.send_disk_file(root, request, response, &path) {
+                Ok(()) => true,
+                Err(error) if error.kind() == ErrorKind::NotFound => false,
+                Err(error) => {
+                    println!("Could not serve {}: {error}", path.display());
+                    true
+                }
+            };
+        }
+        if redirect_to_dir(request, response) {
+            return true;
+        }
+        if let Some(index) = self.index.as_ref().and_then(|index| inside_root(root, &path.join(index))) {
+            if index.is_file() && self.send_disk_file(root, request, response, &index).is_ok() {
+                return true;
+            }
+        }
+        if !self.listing {
+            return false;
+        }
+        match list(&path, &percent_decode(request.get_raw_path(), false), self.dotfiles) {
+            Ok(html) => {
+                response.html(&html);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn serve_embedded(&self, files: &EmbeddedDir, path: &str, request: &Request, response: &mut Response) -> bool {
+        if let Some(file) = files.get(path) {
+            return send_and_log(self.send_embedded_file(files, request, response, file), path);
+        }
+        if !files.is_dir(path) {
+            return false;
+        }
+        if redirect_to_dir(request, response) {
+            return true;
+        }
+        let index = self.index.as_ref().and_then(|index| files.get(&format!("{path}/{index}")));
+        index.is_some_and(|index| send_and_log(self.send_embedded_file(files, request, response, index), index.path()))
+    }
+
+    /// Serves the index file for pages of a single-page app, which routes in the browser
+    ///
+    /// Only requests that accept HTML get it, so a missing script or image is still a 404.
+    fn serve_app(&self, request: &Request, response: &mut Response) -> bool {
+        let raw_path = request.get_raw_path();
+        if self.excluded.iter().any(|prefix| StaticDir::covers(prefix, raw_path)) || !accepts_html(request) {
+            return false;
+        }
+        let Some(index) = self.index.as_deref() else {
+            return false;
+        };
+        match &self.source {
+            Source::Disk(root) => inside_root(root, Path::new(index))
+                .is_some_and(|index| self.send_disk_file(root, request, response, &index).is_ok()),
+            Source::Embedded(files) => files.get(index)
+                .is_some_and(|index| send_and_log(self.send_embedded_file(files, request, response, index), index.path())),
+        }
+    }
+
+    /// Sends a file on disk, or its precompressed version
+    fn send_disk_file(&self, root: &Path, request: &Request, response: &mut Response, path: &Path) -> std::io::Result<()> {
+        let precompressed = self.find_precompressed(request, response, |extension| {
+            let mut name = path.file_name()?.to_os_string();
+            name.push(format!(".{extension}"));
+            inside_root(root, &path.with_file_name(name)).filter(|sidecar| sidecar.is_file())
+        });
+        match precompressed {
+            Some((encoding, sidecar)) => send_file_as(request, response, &sidecar, mime_for_path(path), Some(encoding)),
+            None => send_file(request, response, path),
+        }
+    }
+
+    /// Sends an embedded file, or its precompressed version
+    fn send_embedded_file(&self, files: &EmbeddedDir, request: &Request, response: &mut Response, file: EmbeddedFile) -> std::io::Result<()> {
+        let precompressed = self.find_precompressed(request, response, |extension| files.get(&format!("{}.{extension}", file.path())));
+        match precompressed {
+            Some((encoding, sidecar)) => send_embedded_as(request, response, sidecar, file.media_type(), Some(encoding)),
+            None => send_embedded(request, response, file),
+        }
+    }
+
+    /// The precompressed version of a file the client accepts, looked up by `find` from its extension
+    ///
+    /// Adds `Vary: Accept-Encoding` when the file has one, as other clients get another version.
+    fn find_precompressed<T>(&self, request: &Request, response: &mut Response,
+                             find: impl Fn(&str) -> Option<T>) -> Option<(&'static str, T)> {
+        if !self.precompressed {
+            return None;
+        }
+        let accept_encoding = request.get_header("Accept-Encoding").map_or("", String::as_str);
+        let mut best: Option<(f32, &'static str, T)> = None;
+        for (encoding, extension) in PRECOMPRESSED {
+            let Some(found) = find(extension) else {
+                continue;
+            };
+            vary(response, "Accept-Encoding");
+            let quality = quality(accept_encoding, encoding);
+            if quality > 0.0 && best.as_ref().is_none_or(|(best, _, _)| quality > *best) {
+                best = Some((quality, encoding, found));
+            }
+        }
+        best.map(|(_, encoding, found)| (encoding, found))
+    }
+}
+
+/// The decoded segments of a request path, without empty and `.` segments
+///
+/// Segments are decoded one by one, so an encoded slash can't add a segment. `None` for paths
+/// that try to climb out of the root with `..`.
+fn segments(raw_path: &str) -> Option<Vec<String>> {
+    let mut segments = Vec::new();
+    for segment in raw_path.split('/') {
+        let segment = percent_decode(segment, false);
+        match segment.as_str() {
+            "" | "." => continue,
+            ".." => return None,
+            segment if segment.contains(['/', '\\', '\0']) => return None,
+            _ => segments.push(segment),
+        }
+    }
+    Some(segments)
+}
+
+/// `relative` under `root`, if it exists there; symlinks that lead out of the root are caught
+/// by comparing canonical paths
+fn inside_root(root: &Path, relative: &Path) -> Option<PathBuf> {
+    let root = root.canonicalize().ok()?;
+    let path = root.join(relative).canonicalize().ok()?;
+    path.starts_with(&root).then_some(path)
+}
+
+/// Redirects a directory path without a trailing slash to the path with one, as relative links
+/// in its index only work from there
+///
+/// Leading slashes are collapsed into one, as `//host/dir` would send the client to another host.
+fn redirect_to_dir(request: &Request, response: &mut Response) -> bool {
+    let raw_path = request.get_raw_path();
+    if raw_path.ends_with('/') {
+        return false;
+    }
+    let path = raw_path.trim_start_matches('/');
+    let query = request.get_query();
+    let location = if query.is_empty() { format!("/{path}/") } else { format!("/{path}/?{query}") };
+    Redirect::permanent(&location).into_response(response);
+    true
+}
+
+fn send_and_log(result: std::io::Result<()>, path: &str) -> bool {
+    if let Err(error) = result {
+        println!("Could not serve {path}: {error}");
+    }
+    true
+}
+
+fn accepts_html(request: &Request) -> bool {
+    request.get_header("Accept").is_some_and(|accept| accept.split(',')
+        .any(|media_type| media_type.split(';').next().unwrap_or("").trim().eq_ignore_ascii_case("text/html")))
+}
+
+/// An ETag for a file, from its size and modification time
+fn file_etag(length: u64, modified: Option<SystemTime>) -> String {
+    let modified = modified.and_then(|time| time.duration_since(UNIX_EPOCH).ok()).map_or(0, |time| time.as_secs());
+    format!("\"{modified:x}-{length:x}\"")
+}
+
+/// Whether the client's copy, described by the conditional headers of `request`, is still current
+pub(crate) fn is_not_modified(request: &Request, etag: Option<&str>, modified: Option<SystemTime>) -> bool {
+    etag::is_not_modified(&Validators::from_request(request), etag, modified)
+}
+
+/// Sends a file with its media type, `ETag` and `Last-Modified`, or `304 Not Modified`
+///
+/// `Range` requests get the ranges they ask for.
+pub(crate) fn send_file(request: &Request, response: &mut Response, path: &Path) -> std::io::Result<()> {
+    send_file_as(request, response, path, mime_for_path(path), None)
+}
+
+/// Sends the file at `path` as a file of `media_type`, compressed with `encoding` if it is given
+fn send_file_as(request: &Request, response: &mut Response, path: &Path, media_type: &str,
+                encoding: Option<&str>) -> std::io::Result<()> {
+    let mut file = File::open(path)?;
+    let metadata = file.metadata()?;
+    if !metadata.is_file() {
+        return Err(std::io::Error::new(ErrorKind::NotFound, "not a file"));
+    }
+    let modified = metadata.modified().ok();
+    let etag = file_etag(metadata.len(), modified);
+    send_content(request, response, &etag, modified, media_type, encoding, metadata.len(), &mut file)
+}
+
+/// Sends an embedded file like `send_file`, with the `ETag` computed when it was embedded
+pub(crate) fn send_embedded(request: &Request, response: &mut Response, file: EmbeddedFile) -> std::io::Result<()> {
+    send_embedded_as(request, response, file, file.media_type(), None)
+}
+
+fn send_embedded_as(request: &Request, response: &mut Response, file: EmbeddedFile, media_type: &str,
+                    encoding: Option<&str>) -> std::io::Result<()> {
+    let bytes = file.bytes();
+    send_content(request, response, file.etag(), Some(file.modified()), media_type, encoding, bytes.len() as u64, &mut Cursor::new(bytes))
+}
+
+/// Sends `length` bytes from `source`; with an `encoding` they are a compressed version of the file
+#[allow(clippy::too_many_arguments)]
+fn send_content<S: Read + Seek>(request: &Request, response: &mut Response, etag: &str, modified: Option<SystemTime>,
+                                media_type: &str, encoding: Option<&str>, length: u64, source: &mut S) -> std::io::Result<()> {
+    // Each version of a file needs its own tag
+    let etag = match encoding {
+        Some(encoding) => format!("{}-{encoding}\"", etag.trim_end_matches('"')),
+        None => etag.to_string(),
+    };
+    let etag = etag.as_str();
+    response.header("ETag", etag);
+    if let Some(modified) = modified {
+        response.header("Last-Modified", &http_date(modified));
+    }
+    if etag::is_precondition_failed(&Validators::from_request(request), Some(etag)) {
+        response.status(StatusCode::PreconditionFailed);
+        response.send_bytes(&[]);
+        return Ok(());
+    }
+    if is_not_modified(request, Some(etag), modified) {
+        response.status(StatusCode::NotModified);
+        response.send_bytes(&[]);
+        return Ok(());
+    }
+    response.content_type(media_type);
+    if let Some(encoding) = encoding {
+        response.header("Content-Encoding", encoding);
+    }
+    let plan = range::plan(response, length);
+    response.header("Content-Length", &plan.length().to_string());
+    let head = response.head;
+    let mut body = response.stream_body().map_err(|error| std::io::Error::other(error.to_string()))?;
+    if !head {
+        plan.write(source, &mut body)?;
+    }
+    body.finish()
+}
+
+/// An HTML page listing the entries of `dir`, shown at `path`; names starting with a dot are
+/// only listed with `dotfiles`
+fn list(dir: &Path, path: &str, dotfiles: bool) -> std::io::Result<String> {
+    let mut entries = fs::read_dir(dir)?
+        .filter_map(Result::ok)
+        .map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let is_dir = entry.file_type().is_ok_and(|kind| kind.is_dir());
+            (name, is_dir)
+        })
+        .filter(|(name, _)| dotfiles || !name.starts_with('.'))
+        .collect::<Vec<_>>();
+    entries.sort();
+    let title = escape_html(path);
+    let mut html = String::new();
+    let _ = write!(html, "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Index of {title}</title></head>\n");
+    let _ = write!(html, "<body>\n<h1>Index of {title}</h1>\n<ul>\n<li><a href=\"../\">../</a></li>\n");
+    for (name, is_dir) in entries {
+        let slash = if is_dir { "/" } else { "" };
+        let _ = writeln!(html, "<li><a href=\"{}{slash}\">{}{slash}</a></li>", percent_encode(&name), escape_html(&name));
+    }
+    html.push_str("</ul>\n</body>\n</html>\n");
+    Ok(html)
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;").replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packcake::testing::{body, header, request, respond_text, temp_dir, EMBEDDED};
+
+    /// The response of `dir` mounted at `/files` to `line`, or `None` if it served nothing
+    fn serve(dir: &StaticDir, line: &str, headers: &[(&str, &str)]) -> Option<String> {
+        let mut served = false;
+        let text = respond_text(&request(line, headers, ""), |request, response| served = dir.serve("/files", request, response));
+        served.then_some(text)
+    }
+
+    /// A directory with `file.txt`, `docs/readme.md`, `.env` and `.git/config`, and a file next to it
+    fn public(name: &str) -> PathBuf {
+        let dir = temp_dir(name);
+        let public = dir.join("public");
+        fs::create_dir_all(public.join("docs")).unwrap();
+        fs::create_dir_all(public.join(".git")).unwrap();
+        fs::write(public.join("file.txt"), "hello").unwrap();
+        fs::write(public.join("docs/readme.md"), "# docs").unwrap();
+        fs::write(public.join(".env"), "SECRET=1").unwrap();
+        fs::write(public.join(".git/config"), "[core]").unwrap();
+        fs::write(dir.join("secret.txt"), "outside").unwrap();
+        public
+    }
+
+    #[test]
+    fn splits_paths_into_segments() {
+        assert_eq!(segments("/a/./b//c/"), Some(vec![String::from("a"), String::from("b"), String::from("c")]));
+        assert_eq!(segments("/caf%C3%A9/%2e"), Some(vec![String::from("café")]));
+        assert_eq!(segments(""), Some(Vec::new()));
+        for path in ["/..", "/a/../b", "/%2e%2e/secret", "/%2E%2E", "/a%2f..%2fb", "/a%2Fb", "/a\\b", "/a%5cb", "/a%00b"] {
+            assert_eq!(segments(path), None, "{path:?}");
+        }
+    }
+
+    #[test]
+    fn keeps_paths_inside_the_root() {
+        let root = public("inside-root");
+        assert_eq!(inside_root(&root, Path::new("file.txt")), Some(root.canonicalize().unwrap().join("file.txt")));
+        assert!(inside_root(&root, Path::new("docs")).is_some());
+        assert_eq!(inside_root(&root, Path::new("../secret.txt")), None);
+        assert_eq!(inside_root(&root, Path::new("missing.txt")), None);
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(root.join("../secret.txt"), root.join("escape.txt")).unwrap();
+            std::os::unix::fs::symlink(root.join("file.txt"), root.join("alias.txt")).unwrap();
+            assert_eq!(inside_root(&root, Path::new("escape.txt")), None);
+            assert!(inside_root(&root, Path::new("alias.txt")).is_some());
+            let dir = StaticDir::new(&root);
+            assert!(serve(&dir, "GET /files/escape.txt HTTP/1.1", &[]).is_none());
+            assert_eq!(body(&serve(&dir, "GET /files/alias.txt HTTP/1.1", &[]).unwrap()), "hello");
+        }
+    }
+
+    #[test]
+    fn serves_files() {
+        let dir = StaticDir::new(public("serves-files"));
+        let text = serve(&dir, "GET /files/file.txt HTTP/1.1", &[]).unwrap();
+        assert!(text.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert_eq!(header(&text, "Content-Type"), Some("text/plain; charset=utf-8"));
+        assert_eq!(header(&text, "Content-Length"), Some("5"));
+        assert_eq!(body(&text), "hello");
+        let head = serve(&dir, "HEAD /files/file.txt HTTP/1.1", &[]).unwrap();
+        assert_eq!((header(&head, "Content-Length"), body(&head)), (Some("5"), ""));
+        for path in ["/files/missing.txt", "/files/../secret.txt", "/files/%2e%2e/secret.txt"] {
+            assert!(serve(&dir, &format!("GET {path} HTTP/1.1"), &[]).is_none(), "{path}");
+        }
+    }
+
+    #[test]
+    fn answers_conditional_requests_with_304() {
+        let dir = StaticDir::new(public("not-modified"));
+        let text = serve(&dir, "GET /files/file.txt HTTP/1.1", &[]).unwrap();
+        let modified = header(&text, "Last-Modified").unwrap();
+        let etag = header(&text, "ETag").unwrap();
+        let cached = serve(&dir, "GET /files/file.txt HTTP/1.1", &[("If-Modified-Since", modified)]).unwrap();
+        assert!(cached.starts_with("HTTP/1.1 304 Not Modified\r\n"));
+        assert_eq!(body(&cached), "");
+        let tagged = serve(&dir, "GET /files/file.txt HTTP/1.1", &[("If-None-Match", etag)]).unwrap();
+        assert!(tagged.starts_with("HTTP/1.1 304 Not Modified\r\n"));
+        let stale = serve(&dir, "GET /files/file.txt HTTP/1.1", &[("If-Modified-Since", "Thu, 01 Jan 1970 00:00:00 GMT")]).unwrap();
+        assert!(stale.starts_with("HTTP/1.1 200 OK\r\n"));
+    }
+
+    #[test]
+    fn redirects_directories_to_a_trailing_slash() {
+        let dir = StaticDir::new(public("redirects"));
+        let text = serve(&dir, "GET /files/docs?page=2 HTTP/1.1", &[]).unwrap();
+        assert!(text.starts_with("HTTP/1.1 308 Permanent Redirect\r\n"));
+        assert_eq!(header(&text, "Location"), Some("/files/docs/?page=2"));
+        let root = StaticDir::new(public("redirects-root"));
+        let text = respond_text(&request("GET //docs HTTP/1.1", &[], ""), |request, response| {
+            assert!(root.serve("", request, response));
+        });
+        assert_eq!(header(&text, "Location"), Some("/docs/"));
+    }
+
+    #[test]
+    fn hides_dotfiles_unless_asked() {
+        let root = public("dotfiles");
+        let hidden = StaticDir::new(&root).listing(true);
+        for path in ["/files/.env", "/files/.git/config", "/files/%2eenv", "/files/docs/../.env"] {
+            assert!(serve(&hidden, &format!("GET {path} HTTP/1.1"), &[]).is_none(), "{path}");
+        }
+        assert!(serve(&hidden, "DELETE /files/.env HTTP/1.1", &[]).is_none());
+        let listing = serve(&hidden, "GET /files/ HTTP/1.1", &[]).unwrap();
+        assert!(listing.contains("file.txt") && !listing.contains(".env") && !listing.contains(".git"));
+        let shown = StaticDir::new(&root).listing(true).dotfiles(true);
+        assert_eq!(body(&serve(&shown, "GET /files/.git/config HTTP/1.1", &[]).unwrap()), "[core]");
+        assert!(serve(&shown, "GET /files/ HTTP/1.1", &[]).unwrap().contains(".env"));
+    }
+
+    #[test]
+    fn lists_directories_when_asked() {
+        let root = public("listing");
+        assert!(serve(&StaticDir::new(&root), "GET /files/docs/ HTTP/1.1", &[]).is_none());
+        let listing = serve(&StaticDir::new(&root).listing(true), "GET /files/docs/ HTTP/1.1", &[]).unwrap();
+        assert!(listing.contains("<title>Index of /files/docs/</title>"));
+        assert!(listing.contains("<a href=\"readme.md\">readme.md</a>"));
+    }
+
+    /// `public` with an `index.html` at the root
+    fn app(name: &str) -> PathBuf {
+        let root = public(name);
+        fs::write(root.join("index.html"), "<app>").unwrap();
+        fs::write(root.join("docs/home.html"), "<docs>").unwrap();
+        root
+    }
+
+    #[test]
+    fn serves_index_files() {
+        let root = app("index");
+        assert_eq!(body(&serve(&StaticDir::new(&root), "GET /files/ HTTP/1.1", &[]).unwrap()), "<app>");
+        assert!(serve(&StaticDir::new(&root), "GET /files/docs/ HTTP/1.1", &[]).is_none());
+        let home = StaticDir::new(&root).index("home.html");
+        assert_eq!(body(&serve(&home, "GET /files/docs/ HTTP/1.1", &[]).unwrap()), "<docs>");
+        assert!(serve(&StaticDir::new(&root).no_index(), "GET /files/ HTTP/1.1", &[]).is_none());
+    }
+
+    #[test]
+    fn serves_the_app_for_unknown_pages() {
+        let root = app("spa");
+        let spa = StaticDir::new(&root).spa(true).exclude("/files/api/");
+        let html = [("Accept", "text/html,application/xhtml+xml;q=0.9")];
+        let page = serve(&spa, "GET /files/teams/7 HTTP/1.1", &html).unwrap();
+        assert!(page.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert_eq!(body(&page), "<app>");
+        assert_eq!(body(&serve(&spa, "GET /files/file.txt HTTP/1.1", &html).unwrap()), "hello");
+        // Scripts and images that are missing still get a 404
+        assert!(serve(&spa, "GET /files/missing.js HTTP/1.1", &[("Accept", "*/*")]).is_none());
+        assert!(serve(&spa, "GET /files/api/teams HTTP/1.1", &html).is_none());
+        assert!(serve(&spa, "GET /files/api HTTP/1.1", &html).is_none());
+        assert!(serve(&spa, "POST /files/teams/7 HTTP/1.1", &html).is_none());
+        assert!(serve(&StaticDir::new(&root).spa(true).no_index(), "GET /files/teams/7 HTTP/1.1", &html).is_none());
+        assert!(serve(&StaticDir::new(public("spa-without-index")).spa(true), "GET /files/teams/7 HTTP/1.1", &html).is_none());
+    }
+
+    #[test]
+    fn only_answers_get_and_head() {
+        let dir = StaticDir::new(app("methods"));
+        for line in ["POST /files/file.txt HTTP/1.1", "DELETE /files/docs HTTP/1.1"] {
+            let text = serve(&dir, line, &[]).unwrap();
+            assert!(text.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"), "{line}");
+            assert_eq!(header(&text, "Allow"), Some("GET, HEAD"));
+        }
+        assert!(serve(&dir, "POST /files/missing.txt HTTP/1.1", &[]).is_none());
+    }
+
+    #[test]
+    fn serves_embedded_files() {
+        let dir = StaticDir::embedded(EMBEDDED);
+        let css = serve(&dir, "GET /files/css/site.css HTTP/1.1", &[]).unwrap();
+        assert!(css.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert_eq!(header(&css, "Content-Type"), Some("text/css; charset=utf-8"));
+        assert_eq!(header(&css, "ETag"), Some("\"a9b342a0ff2b8953\""));
+        assert_eq!(header(&css, "Last-Modified"), Some("Sun, 06 Nov 1994 08:49:37 GMT"));
+        assert_eq!(body(&css), "body {}");
+        let cached = serve(&dir, "GET /files/css/site.css HTTP/1.1", &[("If-None-Match", "\"a9b342a0ff2b8953\"")]).unwrap();
+        assert!(cached.starts_with("HTTP/1.1 304 Not Modified\r\n"));
+        assert_eq!(body(&serve(&dir, "GET /files/ HTTP/1.1", &[]).unwrap()), "<app>");
+        assert_eq!(body(&serve(&dir, "GET /files/docs/ HTTP/1.1", &[]).unwrap()), "<docs>");
+        let redirect = serve(&dir, "GET /files/docs HTTP/1.1", &[]).unwrap();
+        assert_eq!(header(&redirect, "Location"), Some("/files/docs/"));
+        assert!(serve(&dir, "GET /files/css/ HTTP/1.1", &[]).is_none());
+        assert!(serve(&dir, "GET /files/missing.css HTTP/1.1", &[]).is_none());
+        assert!(serve(&dir, "GET /files/css/../index.html HTTP/1.1", &[]).is_none());
+        let spa = StaticDir::embedded(EMBEDDED).spa(true);
+        assert_eq!(body(&serve(&spa, "GET /files/teams/7 HTTP/1.1", &[("Accept", "text/html")]).unwrap()), "<app>");
+    }
+
+    #[test]
+    fn serves_precompressed_files_the_client_accepts() {
+        let root = public("precompressed");
+        fs::write(root.join("file.txt.br"), "brotli").unwrap();
+        fs::write(root.join("file.txt.gz"), "gzip").unwrap();
+        let dir = StaticDir::new(&root).precompressed(true);
+        let get = |accept: &str| serve(&dir, "GET /files/file.txt HTTP/1.1", &[("Accept-Encoding", accept)]).unwrap();
+        let br = get("gzip, deflate, br");
+        assert_eq!(header(&br, "Content-Encoding"), Some("br"));
+        assert_eq!(header(&br, "Content-Type"), Some("text/plain; charset=utf-8"));
+        assert_eq!(header(&br, "Vary"), Some("Accept-Encoding"));
+        assert!(header(&br, "ETag").unwrap().ends_with("-br\""));
+        assert_eq!(body(&br), "brotli");
+        let gzip = get("br;q=0.5, gzip");
+        assert_eq!((header(&gzip, "Content-Encoding"), body(&gzip)), (Some("gzip"), "gzip"));
+        assert_ne!(header(&gzip, "ETag"), header(&br, "ETag"));
+        for accept in ["", "identity", "br;q=0, gzip;q=0"] {
+            let plain = get(accept);
+            assert_eq!((header(&plain, "Content-Encoding"), body(&plain)), (None, "hello"), "{accept:?}");
+            assert_eq!(header(&plain, "Vary"), Some("Accept-Encoding"));
+        }
+        let cached = serve(&dir, "GET /files/file.txt HTTP/1.1",
+                           &[("Accept-Encoding", "br"), ("If-None-Match", header(&br, "ETag").unwrap())]).unwrap();
+        assert!(cached.starts_with("HTTP/1.1 304 Not Modified\r\n"));
+        // Without sidecars, or with the option off, there is only one version
+        let readme = serve(&dir, "GET /files/docs/readme.md HTTP/1.1", &[("Accept-Encoding", "br")]).unwrap();
+        assert_eq!((header(&readme, "Content-Encoding"), header(&readme, "Vary")), (None, None));
+        let off = serve(&StaticDir::new(&root), "GET /files/file.txt HTTP/1.1", &[("Accept-Encoding", "br")]).unwrap();
+        assert_eq!((header(&off, "Content-Encoding"), header(&off, "Vary"), body(&off)), (None, None, "hello"));
+    }
+
+    #[test]
+    fn serves_precompressed_embedded_files() {
+        static FILES: EmbeddedDir = EmbeddedDir::new(&[
+            ("app.js", b"plain", "\"000000000000001a\"", "text/javascript", 0),
+            ("app.js.gz", b"gzip", "\"000000000000001b\"", "application/gzip", 0),
+        ]);
+        let dir = StaticDir::embedded(FILES).precompressed(true);
+        let gzip = serve(&dir, "GET /files/app.js HTTP/1.1", &[("Accept-Encoding", "gzip, br")]).unwrap();
+        assert_eq!(header(&gzip, "Content-Encoding"), Some("gzip"));
+        assert_eq!(header(&gzip, "Content-Type"), Some("text/javascript; charset=utf-8"));
+        assert_eq!(header(&gzip, "ETag"), Some("\"000000000000001b-gzip\""));
+        assert_eq!(body(&gzip), "gzip");
+        let plain = serve(&dir, "GET /files/app.js HTTP/1.1", &[("Accept-Encoding", "br")]).unwrap();
+        assert_eq!((header(&plain, "Content-Encoding"), header(&plain, "Vary"), body(&plain)), (None, Some("Accept-Encoding"), "plain"));
+    }
+
+    #[test]
+    fn fails_if_match_for_other_versions() {
+        let dir = StaticDir::new(public("if-match"));
+        let etag = header(&serve(&dir, "GET /files/file.txt HTTP/1.1", &[]).unwrap(), "ETag").unwrap().to_string();
+        let current = serve(&dir, "GET /files/file.txt HTTP/1.1", &[("If-Match", &etag)]).unwrap();
+        assert_eq!(body(&current), "hello");
+        let other = serve(&dir, "GET /files/file.txt HTTP/1.1", &[("If-Match", "\"other\"")]).unwrap();
+        assert!(other.starts_with("HTTP/1.1 412 Precondition Failed\r\n"));
+        assert_eq!(body(&other), "");
+    }
+}
@@ -0,0 +1,385 @@
+use std::fmt::{Display, Formatter};
+
+/// An HTTP status code
+///
+/// Every code in the IANA registry has a variant with its canonical reason phrase,
+/// and `Custom` sends any other code with a reason of your choosing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StatusCode {
+    // 1xx Informational
+    Continue,
+    SwitchingProtocols,
+    Processing,
+    EarlyHints,
+
+    // 2xx Success
+    Ok,
+    Created,
+    Accepted,
+    NonAuthoritativeInformation,
+    NoContent,
+    ResetContent,
+    PartialContent,
+    MultiStatus,
+    AlreadyReported,
+    ImUsed,
+
+    // 3xx Redirection
+    MultipleChoices,
+    MovedPermanently,
+    Found,
+    SeeOther,
+    NotModified,
+    UseProxy,
+    TemporaryRedirect,
+    PermanentRedirect,
+
+    // 4xx Client Error
+    BadRequest,
+    Unauthorized,
+    PaymentRequired,
+    Forbidden,
+    NotFound,
+    MethodNotAllowed,
+    NotAcceptable,
+    ProxyAuthenticationRequired,
+    RequestTimeout,
+    Conflict,
+    Gone,
+    LengthRequired,
+    PreconditionFailed,
+    ContentTooLarge,
+    UriTooLong,
+    UnsupportedMediaType,
+    RangeNotSatisfiable,
+    ExpectationFailed,
+    MisdirectedRequest,
+    UnprocessableContent,
+    Locked,
+    FailedDependency,
+    TooEarly,
+    UpgradeRequired,
+    PreconditionRequired,
+    TooManyRequests,
+    RequestHeaderFieldsTooLarge,
+    UnavailableForLegalReasons,
+
+    // 5xx Server Error
+    InternalServerError,
+    NotImplemented,
+    BadGateway,
+    ServiceUnavailable,
+    GatewayTimeout,
+    HttpVersionNotSupported,
+    VariantAlsoNegotiates,
+    InsufficientStorage,
+    LoopDetected,
+    NotExtended,
+    NetworkAuthenticationRequired,
+
+    /// Any code from 100 to 999, with its reason phrase, built with `StatusCode::custom`
+    Custom(CustomStatus),
+}
+
+/// The code and reason phrase of a `StatusCode::Custom`, checked by `StatusCode::custom`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CustomStatus {
+    code: u16,
+    reason: String,
+}
+
+impl StatusCode {
+    /// A status with any code and reason phrase, e.g. `StatusCode::custom(299, "Mostly OK")`
+    ///
+    /// # Panics
+    ///
+    /// If `code` is not a three-digit number, or `reason` has characters other than tabs,
+    /// spaces and visible ASCII, which could end the status line early
+    pub fn custom(code: u16, reason: &str) -> StatusCode {
+        assert!((100..1000).contains(&code), "Status code {code} is not a three-digit number");
+        assert!(
+            reason.bytes().all(|b| b == b'\t' || b == b' ' || b.is_ascii_graphic()),
+            "Reason phrase {reason:?} has characters that are not allowed in a status line"
+        );
+        StatusCode::Custom(CustomStatus { code, reason: reason.to_string() })
+    }
+
+    /// The status for a numeric code; unregistered codes become `Custom` with an empty reason
+    ///
+    /// # Panics
+    ///
+    /// If `code` is not a three-digit number
+    pub fn from_code(code: u16) -> StatusCode {
+        match code {
+            100 => StatusCode::Continue,
+            101 => StatusCode::SwitchingProtocols,
+            102 => StatusCode::Processing,
+            103 => StatusCode::EarlyHints,
+            200 => StatusCode::Ok,
+            201 => StatusCode::Created,
+            202 => StatusCode::Accepted,
+            203 => StatusCode::NonAuthoritativeInformation,
+            204 => StatusCode::NoContent,
+            205 => StatusCode::ResetContent,
+            206 => StatusCode::PartialContent,
+            207 => StatusCode::MultiStatus,
+            208 => StatusCode::AlreadyReported,
+            226 => StatusCode::ImUsed,
+            300 => StatusCode::MultipleChoices,
+            301 => StatusCode::MovedPermanently,
+            302 => StatusCode::Found,
+            303 => StatusCode::SeeOther,
+            304 => StatusCode::NotModified,
+            305 => StatusCode::UseProxy,
+            307 => StatusCode::TemporaryRedirect,
+            308 => StatusCode::PermanentRedirect,
+            400 => StatusCode::BadRequest,
+            401 => StatusCode::Unauthorized,
+            402 => StatusCode::PaymentRequired,
+            403 => StatusCode::Forbidden,
+            404 => StatusCode::NotFound,
+            405 => StatusCode::MethodNotAllowed,
+            406 => StatusCode::NotAcceptable,
+            407 => StatusCode::ProxyAuthenticationRequired,
+            408 => StatusCode::RequestTimeout,
+            409 => StatusCode::Conflict,
+            410 => StatusCode::Gone,
+            411 => StatusCode::LengthRequired,
+            412 => StatusCode::PreconditionFailed,
+            413 => StatusCode::ContentTooLarge,
+            414 => StatusCode::UriTooLong,
+            415 => StatusCode::UnsupportedMediaType,
+            416 => StatusCode::RangeNotSatisfiable,
+            417 => StatusCode::ExpectationFailed,
+            421 => StatusCode::MisdirectedRequest,
+            422 => StatusCode::UnprocessableContent,
+            423 => StatusCode::Locked,
+            424 => StatusCode::FailedDependency,
+            425 => StatusCode::TooEarly,
+            426 => StatusCode::UpgradeRequired,
+            428 => StatusCode::PreconditionRequired,
+            429 => StatusCode::TooManyRequests,
+            431 => StatusCode::RequestHeaderFieldsTooLarge,
+            451 => StatusCode::UnavailableForLegalReasons,
+            500 => StatusCode::InternalServerError,
+            501 => StatusCode::NotImplemented,
+            502 => StatusCode::BadGateway,
+            503 => StatusCode::ServiceUnavailable,
+            504 => StatusCode::GatewayTimeout,
+            505 => StatusCode::HttpVersionNotSupported,
+            506 => StatusCode::VariantAlsoNegotiates,
+            507 => StatusCode::InsufficientStorage,
+            508 => StatusCode::LoopDetected,
+            510 => StatusCode::NotExtended,
+            511 => StatusCode::NetworkAuthenticationRequired,
+            code => StatusCode::custom(code, ""),
+        }
+    }
+
+    pub fn code(&self) -> u16 {
+        match self {
+            StatusCode::Continue => 100,
+            StatusCode::SwitchingProtocols => 101,
+            StatusCode::Processing => 102,
+            StatusCode::EarlyHints => 103,
+            StatusCode::Ok => 200,
+            StatusCode::Created => 201,
+            StatusCode::Accepted => 202,
+            StatusCode::NonAuthoritativeInformation => 203,
+            StatusCode::NoContent => 204,
+            StatusCode::ResetContent => 205,
+            StatusCode::PartialContent => 206,
+            StatusCode::MultiStatus => 207,
+            StatusCode::AlreadyReported => 208,
+            StatusCode::ImUsed => 226,
+            StatusCode::MultipleChoices => 300,
+            StatusCode::MovedPermanently => 301,
+            StatusCode::Found => 302,
+            StatusCode::SeeOther => 303,
+            StatusCode::NotModified => 304,
+            StatusCode::UseProxy => 305,
+            StatusCode::TemporaryRedirect => 307,
+            StatusCode::PermanentRedirect => 308,
+            StatusCode::BadRequest => 400,
+            StatusCode::Unauthorized => 401,
+            StatusCode::PaymentRequired => 402,
+            StatusCode::Forbidden => 403,
+            StatusCode::NotFound => 404,
+            StatusCode::MethodNotAllowed => 405,
+            StatusCode::NotAcceptable => 406,
+            StatusCode::ProxyAuthenticationRequired => 407,
+            StatusCode::RequestTimeout => 408,
+            StatusCode::Conflict => 409,
+            StatusCode::Gone => 410,
+            StatusCode::LengthRequired => 411,
+            StatusCode::PreconditionFailed => 412,
+            StatusCode::ContentTooLarge => 413,
+            StatusCode::UriTooLong => 414,
+            StatusCode::UnsupportedMediaType => 415,
+            StatusCode::RangeNotSatisfiable => 416,
+            StatusCode::ExpectationFailed => 417,
+            StatusCode::MisdirectedRequest => 421,
+            StatusCode::UnprocessableContent => 422,
+            StatusCode::Locked => 423,
+            StatusCode::FailedDependency => 424,
+            StatusCode::TooEarly => 425,
+            StatusCode::UpgradeRequired => 426,
+            StatusCode::PreconditionRequired => 428,
+            StatusCode::TooManyRequests => 429,
+            StatusCode::RequestHeaderFieldsTooLarge => 431,
+            StatusCode::UnavailableForLegalReasons => 451,
+            StatusCode::InternalServerError => 500,
+            StatusCode::NotImplemented => 501,
+            StatusCode::BadGateway => 502,
+            StatusCode::ServiceUnavailable => 503,
+            StatusCode::GatewayTimeout => 504,
+            StatusCode::HttpVersionNotSupported => 505,
+            StatusCode::VariantAlsoNegotiates => 506,
+            StatusCode::InsufficientStorage => 507,
+            StatusCode::LoopDetected => 508,
+            StatusCode::NotExtended => 510,
+            StatusCode::NetworkAuthenticationRequired => 511,
+            StatusCode::Custom(custom) => custom.code,
+        }
+    }
+
+    /// The canonical reason phrase, like `Not Found`
+    pub fn reason(&self) -> &str {
+        match self {
+            StatusCode::Continue => "Continue",
+            StatusCode::SwitchingProtocols => "Switching Protocols",
+            StatusCode::Processing => "Processing",
+            StatusCode::EarlyHints => "Early Hints",
+            StatusCode::Ok => "OK",
+            StatusCode::Created => "Created",
+            StatusCode::Accepted => "Accepted",
+            StatusCode::NonAuthoritativeInformation => "Non-Authoritative Information",
+            StatusCode::NoContent => "No Content",
+            StatusCode::ResetContent => "Reset Content",
+            StatusCode::PartialContent => "Partial Content",
+            StatusCode::MultiStatus => "Multi-Status",
+            StatusCode::AlreadyReported => "Already Reported",
+            StatusCode::ImUsed => "IM Used",
+            StatusCode::MultipleChoices => "Multiple Choices",
+            StatusCode::MovedPermanently => "Moved Permanently",
+            StatusCode::Found => "Found",
+            StatusCode::SeeOther => "See Other",
+            StatusCode::NotModified => "Not Modified",
+            StatusCode::UseProxy => "Use Proxy",
+            StatusCode::TemporaryRedirect => "Temporary Redirect",
+            StatusCode::PermanentRedirect => "Permanent Redirect",
+            StatusCode::BadRequest => "Bad Request",
+            StatusCode::Unauthorized => "Unauthorized",
+            StatusCode::PaymentRequired => "Payment Required",
+            StatusCode::Forbidden => "Forbidden",
+            StatusCode::NotFound => "Not Found",
+            StatusCode::MethodNotAllowed => "Method Not Allowed",
+            StatusCode::NotAcceptable => "Not Acceptable",
+            StatusCode::ProxyAuthenticationRequired => "Proxy Authentication Required",
+            StatusCode::RequestTimeout => "Request Timeout",
+            StatusCode::Conflict => "Conflict",
+            StatusCode::Gone => "Gone",
+            StatusCode::LengthRequired => "Length Required",
+            StatusCode::PreconditionFailed => "Precondition Failed",
+            StatusCode::ContentTooLarge => "Content Too Large",
+            StatusCode::UriTooLong => "URI Too Long",
+            StatusCode::UnsupportedMediaType => "Unsupported Media Type",
+            StatusCode::RangeNotSatisfiable => "Range Not Satisfiable",
+            StatusCode::ExpectationFailed => "Expectation Failed",
+            StatusCode::MisdirectedRequest => "Misdirected Request",
+            StatusCode::UnprocessableContent => "Unprocessable Content",
+            StatusCode::Locked => "Locked",
+            StatusCode::FailedDependency => "Failed Dependency",
+            StatusCode::TooEarly => "Too Early",
+            StatusCode::UpgradeRequired => "Upgrade Required",
+            StatusCode::PreconditionRequired => "Precondition Required",
+            StatusCode::TooManyRequests => "Too Many Requests",
+            StatusCode::RequestHeaderFieldsTooLarge => "Request Header Fields Too Large",
+            StatusCode::UnavailableForLegalReasons => "Unavailable For Legal Reasons",
+            StatusCode::InternalServerError => "Internal Server Error",
+            StatusCode::NotImplemented => "Not Implemented",
+            StatusCode::BadGateway => "Bad Gateway",
+            StatusCode::ServiceUnavailable => "Service Unavailable",
+            StatusCode::GatewayTimeout => "Gateway Timeout",
+            StatusCode::HttpVersionNotSupported => "HTTP Version Not Supported",
+            StatusCode::VariantAlsoNegotiates => "Variant Also Negotiates",
+            StatusCode::InsufficientStorage => "Insufficient Storage",
+            StatusCode::LoopDetected => "Loop Detected",
+            StatusCode::NotExtended => "Not Extended",
+            StatusCode::NetworkAuthenticationRequired => "Network Authentication Required",
+            StatusCode::Custom(custom) => &custom.reason,
+        }
+    }
+
+    /// The status line for a response in the given HTTP version, like `HTTP/1.1 404 Not Found`
+    pub(crate) fn status_line(&self, version: &str) -> String {
+        format!("{version} {} {}", self.code(), self.reason())
+    }
+
+    pub fn is_informational(&self) -> bool {
+        (100..200).contains(&self.code())
+    }
+
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.code())
+    }
+
+    pub fn is_redirection(&self) -> bool {
+        (300..400).contains(&self.code())
+    }
+
+    pub fn is_client_error(&self) -> bool {
+        (400..500).contains(&self.code())
+    }
+
+    pub fn is_server_error(&self) -> bool {
+        (500..600).contains(&self.code())
+    }
+}
+
+/// `404 Not Found`
+impl Display for StatusCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.code(), self.reason())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_custom_statuses() {
+        let status = StatusCode::custom(299, "Mostly\tOK");
+        assert_eq!(status.code(), 299);
+        assert_eq!(status.status_line("HTTP/1.1"), "HTTP/1.1 299 Mostly\tOK");
+        assert!(status.is_success());
+        assert_eq!(StatusCode::from_code(404), StatusCode::NotFound);
+        assert_eq!(StatusCode::from_code(599), StatusCode::custom(599, ""));
+        assert_eq!(StatusCode::from_code(599).to_string(), "599 ");
+    }
+
+    #[test]
+    #[should_panic(expected = "not allowed in a status line")]
+    fn rejects_line_breaks_in_reasons() {
+        StatusCode::custom(200, "OK\r\nSet-Cookie: session=stolen");
+    }
+
+    #[test]
+    #[should_panic(expected = "not allowed in a status line")]
+    fn rejects_non_ascii_reasons() {
+        StatusCode::custom(200, "Très bien");
+    }
+
+    #[test]
+    #[should_panic(expected = "not a three-digit number")]
+    fn rejects_short_codes() {
+        StatusCode::custom(99, "Too Short");
+    }
+
+    #[test]
+    #[should_panic(expected = "not a three-digit number")]
+    fn rejects_long_codes_from_numbers() {
+        StatusCode::from_code(1000);
+    }
+}
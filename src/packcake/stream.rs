@@ -0,0 +1,245 @@
+use std::io::Write;
+use crate::packcake::compress::{self, Encoder};
+use crate::packcake::{is_valid_header, Response};
+
+/// Data is sent as a chunk once this much has been buffered, or when the handler flushes
+const CHUNK_SIZE: usize = 8 * 1024;
+
+/// Writes the body of a response as it is produced, from `Response::stream_body`
+///
+/// Writes are buffered; `flush` sends what has been written so far. The body ends when the
+/// writer is finished or dropped. With compression enabled on the server, text bodies are
+/// compressed as they are written, and `flush` still delivers everything written so far.
+///
+/// ```ignore
+/// let mut body = response.stream_body()?;
+/// for row in rows {
+///     writeln!(body, "{row}")?;
+/// }
+/// body.trailer("X-Row-Count", &rows.len().to_string());
+/// body.finish()?;
+/// ```
+pub struct BodyWriter<'a> {
+    response: &'a mut Response,
+    /// Whether the body is sent with `Transfer-Encoding: chunked`
+    chunked: bool,
+    /// False for `HEAD` requests and statuses that have no content
+    has_body: bool,
+    head_sent: bool,
+    finished: bool,
+    buffer: Vec<u8>,
+    trailers: Vec<(String, String)>,
+    /// Compresses the buffer before it is sent
+    encoder: Option<Encoder>,
+}
+
+impl<'a> BodyWriter<'a> {
+    pub(crate) fn new(response: &'a mut Response) -> BodyWriter<'a> {
+        let has_body = !response.head && !response.is_bodiless();
+        let length = response.get_header("Content-Length").and_then(|length| length.parse().ok());
+        let encoder = compress::prepare(response, length).map(|encoding| {
+            // The compressed length is not known up front
+            response.remove_header("Content-Length");
+            Encoder::new(encoding)
+        });
+        let chunked = !response.is_bodiless()
+            && response.get_header("Content-Length").is_none()
+            && response.version != "HTTP/1.0";
+        if chunked {
+            response.header("Transfer-Encoding", "chunked");
+        }
+        BodyWriter {
+            response,
+            chunked,
+            has_body,
+            head_sent: false,
+            finished: false,
+            buffer: Vec::with_capacity(CHUNK_SIZE),
+            trailers: Vec::new(),
+            encoder,
+        }
+    }
+
+    /// Whether the body is sent with `Transfer-Encoding: chunked`
+    pub fn is_chunked(&self) -> bool {
+        self.chunked
+    }
+
+    /// Adds a trailer, sent after the body
+    ///
+    /// Trailers are only sent with chunked bodies, and clients only read those declared
+    /// up front with a `Trailer` header, set on the response before streaming. Invalid trailers
+    /// are ignored the same way as invalid headers.
+    pub fn trailer(&mut self, name: &str, value: &str) {
+        if !is_valid_header(name, value) {
+            println!("Ignoring trailer {name:?} with an invalid name or value {value:?}");
+            return;
+        }
+        self.trailers.push((name.to_string(), value.to_string()));
+    }
+
+    /// Sends the rest of the body and ends it
+    pub fn finish(mut self) -> std::io::Result<()> {
+        self.end()
+    }
+
+    fn end(&mut self) -> std::io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = true;
+        if let Some(encoder) = &mut self.encoder {
+            encoder.write(&self.buffer);
+            encoder.finish();
+            self.buffer = encoder.take_output();
+        }
+        self.send_buffer()?;
+        if self.chunked && self.has_body {
+            let trailers = self.trailers.iter().map(|(h, v)| format!("{h}: {v}\r\n")).collect::<String>();
+            self.response.stream.write_all(format!("0\r\n{trailers}\r\n").as_bytes())?;
+        }
+        self.response.stream.flush()
+    }
+
+    /// Sends the head if it has not been sent yet, then whatever has been buffered
+    fn send_buffer(&mut self) -> std::io::Result<()> {
+        if !self.head_sent {
+            self.head_sent = true;
+            let head = self.response.head_bytes();
+            self.response.stream.write_all(&head)?;
+        }
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        if self.has_body {
+            if self.chunked {
+                self.response.stream.write_all(format!("{:X}\r\n", self.buffer.len()).as_bytes())?;
+                self.buffer.extend_from_slice(b"\r\n");
+            }
+            self.response.stream.write_all(&self.buffer)?;
+        }
+        self.buffer.clear();
+        Ok(())
+    }
+}
+
+impl Write for BodyWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.finished {
+            return Err(std::io::Error::new(std::io::ErrorKind::BrokenPipe, "body already finished"));
+        }
+        self.buffer.extend_from_slice(buf);
+        if self.buffer.len() >= CHUNK_SIZE {
+            if let Some(encoder) = &mut self.encoder {
+                encoder.write(&self.buffer);
+                self.buffer = encoder.take_output();
+            }
+            self.send_buffer()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if let Some(encoder) = &mut self.encoder {
+            encoder.write(&self.buffer);
+            encoder.flush();
+            self.buffer = encoder.take_output();
+        }
+        self.send_buffer()?;
+        self.response.stream.flush()
+    }
+}
+
+impl Drop for BodyWriter<'_> {
+    fn drop(&mut self) {
+        if let Err(error) = self.end() {
+            println!("Could not write response: {error}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packcake::ResponseError;
+    use crate::packcake::testing::{body, header, request, respond_text};
+
+    #[test]
+    fn frames_chunks() {
+        let text = respond_text(&request("GET / HTTP/1.1", &[], ""), |_, response| {
+            let mut body = response.stream_body().unwrap();
+            assert!(body.is_chunked());
+            body.write_all(b"hello").unwrap();
+            body.flush().unwrap();
+            body.write_all(b", world").unwrap();
+            body.finish().unwrap();
+        });
+        assert_eq!(header(&text, "Transfer-Encoding"), Some("chunked"));
+        assert_eq!(header(&text, "Content-Length"), None);
+        assert_eq!(body(&text), "5\r\nhello\r\n7\r\n, world\r\n0\r\n\r\n");
+    }
+
+    #[test]
+    fn sends_large_writes_in_chunks() {
+        let text = respond_text(&request("GET / HTTP/1.1", &[], ""), |_, response| {
+            let mut body = response.stream_body().unwrap();
+            body.write_all(&vec![b'a'; CHUNK_SIZE + 1]).unwrap();
+            drop(body);
+        });
+        assert!(body(&text).starts_with(&format!("{:X}\r\naaaa", CHUNK_SIZE + 1)));
+        assert!(body(&text).ends_with("a\r\n0\r\n\r\n"));
+    }
+
+    #[test]
+    fn sends_trailers_after_the_last_chunk() {
+        let text = respond_text(&request("GET / HTTP/1.1", &[], ""), |_, response| {
+            response.header("Trailer", "X-Row-Count");
+            let mut body = response.stream_body().unwrap();
+            body.write_all(b"row\n").unwrap();
+            body.trailer("X-Row-Count", "1");
+            body.trailer("X-Bad", "1\r\nX-Injected: yes");
+            body.finish().unwrap();
+        });
+        assert_eq!(header(&text, "Trailer"), Some("X-Row-Count"));
+        assert_eq!(body(&text), "4\r\nrow\n\r\n0\r\nX-Row-Count: 1\r\n\r\n");
+    }
+
+    #[test]
+    fn sends_as_is_with_a_content_length_or_to_http_1_0() {
+        let sized = respond_text(&request("GET / HTTP/1.1", &[], ""), |_, response| {
+            response.header("Content-Length", "5");
+            let mut body = response.stream_body().unwrap();
+            assert!(!body.is_chunked());
+            body.write_all(b"hello").unwrap();
+        });
+        assert_eq!(header(&sized, "Transfer-Encoding"), None);
+        assert_eq!(body(&sized), "hello");
+        let old = respond_text(&request("GET / HTTP/1.0", &[], ""), |_, response| {
+            let mut body = response.stream_body().unwrap();
+            body.write_all(b"hello").unwrap();
+        });
+        assert!(old.starts_with("HTTP/1.0 200 OK\r\n"));
+        assert_eq!(header(&old, "Transfer-Encoding"), None);
+        assert_eq!(body(&old), "hello");
+    }
+
+    #[test]
+    fn sends_only_the_head_for_head_requests() {
+        let text = respond_text(&request("HEAD / HTTP/1.1", &[], ""), |_, response| {
+            let mut body = response.stream_body().unwrap();
+            body.write_all(b"hello").unwrap();
+            body.trailer("X-Row-Count", "1");
+        });
+        assert!(text.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert_eq!(header(&text, "Transfer-Encoding"), Some("chunked"));
+        assert_eq!(body(&text), "");
+    }
+
+    #[test]
+    fn refuses_a_second_body() {
+        respond_text(&request("GET / HTTP/1.1", &[], ""), |_, response| {
+            response.send("sent");
+            assert!(matches!(response.stream_body(), Err(ResponseError::AlreadySent)));
+        });
+    }
+}
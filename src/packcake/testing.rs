@@ -0,0 +1,71 @@
+//! Helpers for tests that run a handler against a real connection
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use crate::packcake::{EmbeddedDir, Request, Response, StatusCode};
+
+/// A request with the given request line, like `GET /path HTTP/1.1`, headers and body
+pub(crate) fn request(line: &str, headers: &[(&str, &str)], body: &str) -> Request {
+    let headers = headers.iter().map(|(h, v)| (h.to_string(), v.to_string())).collect::<HashMap<_, _>>();
+    Request::new(line, headers, body.to_string())
+}
+
+/// Both ends of a loopback connection: the server's, then the client's
+pub(crate) fn connection() -> (TcpStream, TcpStream) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (server, _) = listener.accept().unwrap();
+    (server, client)
+}
+
+/// Runs `handler` for `request` like the server does, and returns the raw response
+pub(crate) fn respond(request: &Request, handler: impl FnOnce(&Request, &mut Response)) -> Vec<u8> {
+    let (server, mut client) = connection();
+    let mut response = Response::from_stream(server, request);
+    handler(request, &mut response);
+    response.commit(&StatusCode::InternalServerError);
+    drop(response);
+    let mut output = Vec::new();
+    client.read_to_end(&mut output).unwrap();
+    output
+}
+
+/// `respond`, with the response as text
+pub(crate) fn respond_text(request: &Request, handler: impl FnOnce(&Request, &mut Response)) -> String {
+    String::from_utf8_lossy(&respond(request, handler)).into_owned()
+}
+
+/// The body of a raw response
+pub(crate) fn body(response: &str) -> &str {
+    response.split_once("\r\n\r\n").map_or("", |(_, body)| body)
+}
+
+/// The value of `header` in a raw response
+pub(crate) fn header<'a>(response: &'a str, header: &str) -> Option<&'a str> {
+    let head = response.split("\r\n\r\n").next()?;
+    head.split("\r\n")
+        .skip(1)
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.eq_ignore_ascii_case(header))
+        .map(|(_, value)| value.trim())
+}
+
+
+/// An empty directory for the test `name`, under the system's temporary directory
+pub(crate) fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("packcake-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// A few embedded files, as the build helper would generate them
+pub(crate) static EMBEDDED: EmbeddedDir = EmbeddedDir::new(&[
+    ("css/site.css", b"body {}", "\"a9b342a0ff2b8953\"", "text/css", 784111777),
+    ("docs/index.html", b"<docs>", "\"00000000000000d0\"", "text/html", 0),
+    ("index.html", b"<app>", "\"00000000000000a0\"", "text/html", 0),
+    ("logo.png", &[0x89, b'P', b'N', b'G', 0xFF], "\"00000000000000b0\"", "image/png", 0),
+]);
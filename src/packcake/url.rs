@@ -0,0 +1,159 @@
+/// Decodes the percent-encoded octets in `input`.
+///
+/// When `plus_as_space` is set, `+` is decoded as a space (as in query strings and forms).
+/// Malformed escapes are kept as they are, and invalid UTF-8 is replaced.
+pub fn percent_decode(input: &str, plus_as_space: bool) -> String {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => {
+                match (hex_value(bytes[i + 1]), hex_value(bytes[i + 2])) {
+                    (Some(high), Some(low)) => {
+                        decoded.push(high << 4 | low);
+                        i += 3;
+                        continue;
+                    }
+                    _ => decoded.push(b'%'),
+                }
+            }
+            b'+' if plus_as_space => decoded.push(b' '),
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Percent-encodes everything but unreserved characters, so `input` can be used as one path segment
+pub fn percent_encode(input: &str) -> String {
+    let mut encoded = String::with_capacity(input.len());
+    for byte in input.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => encoded.push(byte as char),
+            byte => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
+fn hex_value(byte: u8) -> Option<u8> {
+    match byte {
+        b'0'..=b'9' => Some(byte - b'0'),
+        b'a'..=b'f' => Some(byte - b'a' + 10),
+        b'A'..=b'F' => Some(byte - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// An ordered list of decoded `key=value` pairs, as found in a query string.
+///
+/// Keys may repeat, and keys without a `=` (flags) have an empty value.
+#[derive(Clone, Debug, Default)]
+pub struct Params {
+    pairs: Vec<(String, String)>,
+}
+
+impl Params {
+    /// Parses an `application/x-www-form-urlencoded` string such as `a=1&b=two+words&flag`
+    pub fn parse(input: &str) -> Params {
+        let mut pairs = Vec::new();
+        for pair in input.split('&') {
+            if pair.is_empty() {
+                continue;
+            }
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            pairs.push((percent_decode(key, true), percent_decode(value, true)));
+        }
+        Params { pairs }
+    }
+
+    /// The first value for `key`
+    pub fn get(&self, key: &str) -> Option<&String> {
+        self.pairs.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    /// Every value for `key`, in the order they appeared
+    pub fn get_all(&self, key: &str) -> Vec<&String> {
+        self.pairs.iter().filter(|(k, _)| k == key).map(|(_, v)| v).collect()
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.pairs.iter().any(|(k, _)| k == key)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.pairs.iter().map(|(k, v)| (k, v))
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_escapes() {
+        assert_eq!(percent_decode("/a%20b/%7Euser", false), "/a b/~user");
+        assert_eq!(percent_decode("%e2%82%ac%E2%82%AC", false), "€€");
+        assert_eq!(percent_decode("caf%C3%A9", false), "café");
+        // An encoded slash stays part of the segment's text
+        assert_eq!(percent_decode("a%2Fb", false), "a/b");
+    }
+
+    #[test]
+    fn decodes_plus_only_when_asked() {
+        assert_eq!(percent_decode("two+words", true), "two words");
+        assert_eq!(percent_decode("two+words", false), "two+words");
+        assert_eq!(percent_decode("1%2B1", true), "1+1");
+    }
+
+    #[test]
+    fn keeps_invalid_escapes() {
+        assert_eq!(percent_decode("100%", false), "100%");
+        assert_eq!(percent_decode("%4", false), "%4");
+        assert_eq!(percent_decode("%zz%4g", false), "%zz%4g");
+        assert_eq!(percent_decode("%%41", false), "%A");
+        assert_eq!(percent_decode("%FF%FE", false), "\u{FFFD}\u{FFFD}");
+    }
+
+    #[test]
+    fn parses_query_strings() {
+        let params = Params::parse("a=1&b=two+words&flag&tag=x&tag=y&&c%3Dd=%26");
+        assert_eq!(params.get("a").unwrap(), "1");
+        assert_eq!(params.get("b").unwrap(), "two words");
+        assert_eq!(params.get("flag").unwrap(), "");
+        assert!(params.contains("flag"));
+        assert_eq!(params.get("tag").unwrap(), "x");
+        assert_eq!(params.get_all("tag"), ["x", "y"]);
+        assert_eq!(params.get("c=d").unwrap(), "&");
+        assert_eq!(params.get("missing"), None);
+        assert!(params.get_all("missing").is_empty());
+        assert_eq!(params.len(), 6);
+        assert_eq!(params.iter().map(|(k, _)| k.as_str()).collect::<Vec<_>>(), ["a", "b", "flag", "tag", "tag", "c=d"]);
+    }
+
+    #[test]
+    fn parses_empty_query_strings() {
+        assert!(Params::parse("").is_empty());
+        assert!(Params::parse("&&").is_empty());
+        // Only the first `=` splits
+        assert_eq!(Params::parse("a=b=c").get("a").unwrap(), "b=c");
+    }
+
+    #[test]
+    fn encodes_path_segments() {
+        assert_eq!(percent_encode("a-b_c.d~e"), "a-b_c.d~e");
+        assert_eq!(percent_encode("a b/c?d"), "a%20b%2Fc%3Fd");
+        assert_eq!(percent_encode("é"), "%C3%A9");
+        assert_eq!(percent_decode(&percent_encode("100% /x"), false), "100% /x");
+    }
+}
@@ -0,0 +1,650 @@
+use std::fmt::{Display, Formatter};
+use std::io::{ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::time::Duration;
+use crate::packcake::extract::Rejection;
+use crate::packcake::handler::Handler;
+use crate::packcake::sha1::sha1;
+use crate::packcake::{base64, Request, Response, StatusCode};
+
+/// Appended to the client's key to compute `Sec-WebSocket-Accept`, from RFC 6455
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// The largest message a `WebSocket` accepts unless told otherwise
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+
+/// How long `close` waits for the client to answer with its own close frame
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+const CONTINUATION: u8 = 0x0;
+const TEXT: u8 = 0x1;
+const BINARY: u8 = 0x2;
+const CLOSE: u8 = 0x8;
+const PING: u8 = 0x9;
+const PONG: u8 = 0xA;
+
+/// A message received from, or sent to, a WebSocket client
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    /// Already answered with a pong
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+    /// The client closed the connection; `None` if it gave no status code
+    Close(Option<CloseFrame>),
+}
+
+/// The status code and reason of a close frame
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CloseFrame {
+    pub code: u16,
+    pub reason: String,
+}
+
+impl CloseFrame {
+    pub const NORMAL: u16 = 1000;
+    pub const GOING_AWAY: u16 = 1001;
+    pub const PROTOCOL_ERROR: u16 = 1002;
+    pub const UNSUPPORTED_DATA: u16 = 1003;
+    pub const INVALID_DATA: u16 = 1007;
+    pub const POLICY_VIOLATION: u16 = 1008;
+    pub const MESSAGE_TOO_BIG: u16 = 1009;
+    pub const INTERNAL_ERROR: u16 = 1011;
+
+    /// Whether a client may send `code`; the others are reserved or only used locally
+    fn is_valid_code(code: u16) -> bool {
+        matches!(code, 1000..=1003 | 1007..=1011 | 3000..=4999)
+    }
+}
+
+/// Why a WebSocket could not receive or send
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WsError {
+    /// The connection has been closed
+    Closed,
+    /// The client broke the protocol; the connection was closed with `code`
+    Protocol {
+        code: u16,
+        message: String,
+    },
+    /// Reading from or writing to the connection failed
+    Io(ErrorKind),
+}
+
+impl Display for WsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WsError::Closed => write!(f, "WebSocket is closed"),
+            WsError::Protocol { code, message } => write!(f, "WebSocket protocol error ({code}): {message}"),
+            WsError::Io(kind) => write!(f, "WebSocket connection failed: {kind}"),
+        }
+    }
+}
+
+impl From<std::io::Error> for WsError {
+    fn from(error: std::io::Error) -> WsError {
+        WsError::Io(error.kind())
+    }
+}
+
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+/// A WebSocket connection, handed to the handler of a `ws` route after the handshake
+///
+/// `recv` reads the next message, putting fragmented messages back together and answering pings.
+/// The handler runs on a worker thread of the server for as long as the connection is open.
+///
+/// ```ignore
+/// fn chat(_request: &Request, mut socket: WebSocket) {
+///     while let Ok(message) = socket.recv() {
+///         match message {
+///             Message::Text(text) => { let _ = socket.send_text(&text); }
+///             Message::Close(_) => break,
+///             _ => {}
+///         }
+///     }
+/// }
+/// ```
+pub struct WebSocket {
+    reader: Box<dyn Read + Send>,
+    stream: TcpStream,
+    max_message_size: usize,
+    /// The opcode and data of a fragmented message that is not complete yet
+    fragments: Option<(u8, Vec<u8>)>,
+    close_sent: bool,
+    close_received: bool,
+}
+
+impl WebSocket {
+    fn new(reader: Box<dyn Read + Send>, stream: TcpStream) -> WebSocket {
+        WebSocket {
+            reader,
+            stream,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            fragments: None,
+            close_sent: false,
+            close_received: false,
+        }
+    }
+
+    /// Limits the size of received messages (default 16 MiB); bigger ones close the connection with 1009
+    pub fn set_max_message_size(&mut self, size: usize) {
+        self.max_message_size = size;
+    }
+
+    /// Whether either side has started closing the connection
+    pub fn is_closed(&self) -> bool {
+        self.close_sent || self.close_received
+    }
+
+    /// Waits for the next message
+    ///
+    /// A close from the client is answered and returned as `Message::Close`. Every call after that
+    /// fails with `WsError::Closed`.
+    pub fn recv(&mut self) -> Result<Message, WsError> {
+        loop {
+            if self.is_closed() {
+                return Err(WsError::Closed);
+            }
+            let frame = match self.read_frame() {
+                Ok(frame) => frame,
+                Err(WsError::Protocol { code, message }) => return Err(self.fail(code, &message)),
+                Err(error) => {
+                    self.close_received = true;
+                    return Err(error);
+                }
+            };
+            let message = match frame.opcode {
+                TEXT | BINARY if self.fragments.is_some() =>
+                    return Err(self.fail(CloseFrame::PROTOCOL_ERROR, "new message before the last one was complete")),
+                TEXT | BINARY if frame.fin => self.message(frame.opcode, frame.payload),
+                TEXT | BINARY => {
+                    self.fragments = Some((frame.opcode, frame.payload));
+                    continue;
+                }
+                CONTINUATION => {
+                    let Some((opcode, mut data)) = self.fragments.take() else {
+                        return Err(self.fail(CloseFrame::PROTOCOL_ERROR, "continuation without a message to continue"));
+                    };
+                    if data.len() + frame.payload.len() > self.max_message_size {
+                        return Err(self.fail(CloseFrame::MESSAGE_TOO_BIG, "message is too big"));
+                    }
+                    data.extend_from_slice(&frame.payload);
+                    if !frame.fin {
+                        self.fragments = Some((opcode, data));
+                        continue;
+                    }
+                    self.message(opcode, data)
+                }
+                PING => {
+                    self.write_frame(PONG, &frame.payload)?;
+                    Ok(Message::Ping(frame.payload))
+                }
+                PONG => Ok(Message::Pong(frame.payload)),
+                CLOSE => self.close_frame(&frame.payload).map(|close| {
+                    self.close_received = true;
+                    // Echo the code back to finish the closing handshake
+                    let payload = close.as_ref().map(|close| close.code.to_be_bytes().to_vec()).unwrap_or_default();
+                    let _ = self.write_frame(CLOSE, &payload);
+                    self.close_sent = true;
+                    Message::Close(close)
+                }),
+                opcode => Err(WsError::Protocol {
+                    code: CloseFrame::PROTOCOL_ERROR,
+                    message: format!("unknown opcode {opcode:#x}"),
+                }),
+            };
+            return match message {
+                Err(WsError::Protocol { code, message }) => Err(self.fail(code, &message)),
+                message => message,
+            };
+        }
+    }
+
+    /// Sends a text message
+    pub fn send_text(&mut self, text: &str) -> Result<(), WsError> {
+        self.write_frame(TEXT, text.as_bytes())
+    }
+
+    /// Sends a binary message
+    pub fn send_binary(&mut self, data: &[u8]) -> Result<(), WsError> {
+        self.write_frame(BINARY, data)
+    }
+
+    /// Sends a ping; the client answers with a pong carrying the same data, of at most 125 bytes
+    pub fn ping(&mut self, data: &[u8]) -> Result<(), WsError> {
+        self.write_frame(PING, &data[..data.len().min(125)])
+    }
+
+    /// Sends any message; `Message::Close` closes the connection like `close`
+    pub fn send(&mut self, message: Message) -> Result<(), WsError> {
+        match message {
+            Message::Text(text) => self.send_text(&text),
+            Message::Binary(data) => self.send_binary(&data),
+            Message::Ping(data) => self.ping(&data),
+            Message::Pong(data) => self.write_frame(PONG, &data[..data.len().min(125)]),
+            Message::Close(close) => {
+                let close = close.unwrap_or(CloseFrame { code: CloseFrame::NORMAL, reason: String::new() });
+                self.close(close.code, &close.reason)
+            }
+        }
+    }
+
+    /// Closes the connection, and waits a few seconds for the client to confirm
+    ///
+    /// Messages that arrive in the meantime are dropped. The reason is cut to fit in a control frame.
+    pub fn close(&mut self, code: u16, reason: &str) -> Result<(), WsError> {
+        if self.close_sent {
+            return Err(WsError::Closed);
+        }
+        let mut payload = code.to_be_bytes().to_vec();
+        let mut end = reason.len().min(123);
+        while !reason.is_char_boundary(end) {
+            end -= 1;
+        }
+        payload.extend_from_slice(&reason.as_bytes()[..end]);
+        self.write_frame(CLOSE, &payload)?;
+        self.close_sent = true;
+        let _ = self.stream.set_read_timeout(Some(CLOSE_TIMEOUT));
+        while !self.close_received {
+            match self.read_frame() {
+                Ok(frame) if frame.opcode == CLOSE => self.close_received = true,
+                Ok(_) => {}
+                Err(_) => break,
+            }
+        }
+        Ok(())
+    }
+
+    /// Closes the connection with `code` after the client broke the protocol
+    fn fail(&mut self, code: u16, message: &str) -> WsError {
+        if !self.close_sent {
+            let mut payload = code.to_be_bytes().to_vec();
+            payload.extend_from_slice(message.as_bytes());
+            payload.truncate(125);
+            let _ = self.write_frame(CLOSE, &payload);
+            self.close_sent = true;
+        }
+        self.close_received = true;
+        WsError::Protocol {
+            code,
+            message: message.to_string(),
+        }
+    }
+
+    fn message(&self, opcode: u8, data: Vec<u8>) -> Result<Message, WsError> {
+        if opcode == BINARY {
+            return Ok(Message::Binary(data));
+        }
+        String::from_utf8(data).map(Message::Text).map_err(|_| WsError::Protocol {
+            code: CloseFrame::INVALID_DATA,
+            message: String::from("text message is not valid UTF-8"),
+        })
+    }
+
+    fn close_frame(&self, payload: &[u8]) -> Result<Option<CloseFrame>, WsError> {
+        let protocol_error = |message: &str| WsError::Protocol {
+            code: CloseFrame::PROTOCOL_ERROR,
+            message: message.to_string(),
+        };
+        match payload.len() {
+            0 => Ok(None),
+            1 => Err(protocol_error("close frame with a one byte payload")),
+            _ => {
+                let code = u16::from_be_bytes([payload[0], payload[1]]);
+                if !CloseFrame::is_valid_code(code) {
+                    return Err(protocol_error(&format!("invalid close code {code}")));
+                }
+                let reason = String::from_utf8(payload[2..].to_vec()).map_err(|_| WsError::Protocol {
+                    code: CloseFrame::INVALID_DATA,
+                    message: String::from("close reason is not valid UTF-8"),
+                })?;
+                Ok(Some(CloseFrame { code, reason }))
+            }
+        }
+    }
+
+    fn read_frame(&mut self) -> Result<Frame, WsError> {
+        let protocol_error = |message: &str| WsError::Protocol {
+            code: CloseFrame::PROTOCOL_ERROR,
+            message: message.to_string(),
+        };
+        let mut head = [0u8; 2];
+        self.reader.read_exact(&mut head)?;
+        let fin = head[0] & 0x80 != 0;
+        let opcode = head[0] & 0x0F;
+        if head[0] & 0x70 != 0 {
+            return Err(protocol_error("reserved bits are set"));
+        }
+        if head[1] & 0x80 == 0 {
+            return Err(protocol_error("frames from the client must be masked"));
+        }
+        let length = match head[1] & 0x7F {
+            126 => {
+                let mut length = [0u8; 2];
+                self.reader.read_exact(&mut length)?;
+                u16::from_be_bytes(length) as u64
+            }
+            127 => {
+                let mut length = [0u8; 8];
+                self.reader.read_exact(&mut length)?;
+                let length = u64::from_be_bytes(length);
+                if length >> 63 != 0 {
+                    return Err(protocol_error("frame length has the most significant bit set"));
+                }
+                length
+            }
+            length => length as u64,
+        };
+        if opcode >= CLOSE && (!fin || length > 125) {
+            return Err(protocol_error("control frames must not be fragmented or longer than 125 bytes"));
+        }
+        if length > self.max_message_size as u64 {
+            return Err(WsError::Protocol {
+                code: CloseFrame::MESSAGE_TOO_BIG,
+                message: String::from("message is too big"),
+            });
+        }
+        let mut mask = [0u8; 4];
+        self.reader.read_exact(&mut mask)?;
+        let mut payload = vec![0u8; length as usize];
+        self.reader.read_exact(&mut payload)?;
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+        Ok(Frame { fin, opcode, payload })
+    }
+
+    /// Writes one unfragmented, unmasked frame
+    fn write_frame(&mut self, opcode: u8, payload: &[u8]) -> Result<(), WsError> {
+        if self.close_sent {
+            return Err(WsError::Closed);
+        }
+        let mut frame = Vec::with_capacity(payload.len() + 10);
+        frame.push(0x80 | opcode);
+        match payload.len() {
+            length @ 0..=125 => frame.push(length as u8),
+            length @ 126..=0xFFFF => {
+                frame.push(126);
+                frame.extend_from_slice(&(length as u16).to_be_bytes());
+            }
+            length => {
+                frame.push(127);
+                frame.extend_from_slice(&(length as u64).to_be_bytes());
+            }
+        }
+        frame.extend_from_slice(payload);
+        self.stream.write_all(&frame)?;
+        self.stream.flush()?;
+        Ok(())
+    }
+}
+
+/// Reads messages until the connection is closed; the last one is the `Message::Close`, if any
+impl Iterator for WebSocket {
+    type Item = Message;
+
+    fn next(&mut self) -> Option<Message> {
+        self.recv().ok()
+    }
+}
+
+/// Closes the connection normally if the handler did not
+impl Drop for WebSocket {
+    fn drop(&mut self) {
+        if !self.is_closed() {
+            let _ = self.close(CloseFrame::NORMAL, "");
+        }
+    }
+}
+
+/// The `Sec-WebSocket-Accept` for a handshake request, or why it is not one
+fn accept_key(request: &Request, response: &mut Response) -> Result<String, Rejection> {
+    let has_token = |header: &str, token: &str| request.get_header(header)
+        .is_some_and(|value| value.split(',').any(|t| t.trim().eq_ignore_ascii_case(token)));
+    if request.get_method() != "GET" || request.get_version() != "HTTP/1.1" {
+        return Err(Rejection::bad_request("WebSocket handshakes must be HTTP/1.1 GET requests"));
+    }
+    if !has_token("Upgrade", "websocket") || !has_token("Connection", "upgrade") {
+        response.header("Upgrade", "websocket");
+        return Err(Rejection::new(StatusCode::UpgradeRequired, "Expected a WebSocket handshake"));
+    }
+    if request.get_header("Sec-WebSocket-Version").map(|v| v.trim()) != Some("13") {
+        response.header("Sec-WebSocket-Version", "13");
+        return Err(Rejection::new(StatusCode::UpgradeRequired, "Unsupported Sec-WebSocket-Version, expected 13"));
+    }
+    let key = request.get_header("Sec-WebSocket-Key").map(|key| key.trim()).unwrap_or_default();
+    if base64::decode(key).is_none_or(|nonce| nonce.len() != 16) {
+        return Err(Rejection::bad_request("Missing or invalid Sec-WebSocket-Key"));
+    }
+    Ok(base64::encode(&sha1(format!("{key}{GUID}").as_bytes())))
+}
+
+/// Wraps a WebSocket handler into one that performs the handshake first
+pub(crate) fn into_handler<F>(handler: F) -> Handler
+where F: Fn(&Request, WebSocket) + Send + Sync + 'static {
+    Arc::new(move |request: &Request, response: &mut Response| {
+        let accept = match accept_key(request, response) {
+            Ok(accept) => accept,
+            Err(rejection) => return rejection.send(response),
+        };
+        response.status(StatusCode::SwitchingProtocols);
+        response.header("Upgrade", "websocket");
+        response.header("Connection", "Upgrade");
+        response.header("Sec-WebSocket-Accept", &accept);
+        response.streamed = true;
+        let head = response.head_bytes();
+        let stream = match response.stream.try_clone() {
+            Ok(stream) => stream,
+            Err(error) => return println!("Could not upgrade to WebSocket: {error}"),
+        };
+        if let Err(error) = response.stream.write_all(&head).and_then(|_| response.stream.flush()) {
+            return println!("Could not upgrade to WebSocket: {error}");
+        }
+        let reader = request.body_reader().take_upgraded();
+        handler(request, WebSocket::new(reader, stream));
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use crate::packcake::testing::{connection, header, request, respond_text};
+
+    /// A frame as a client sends it, masked
+    fn masked(fin: bool, opcode: u8, payload: &[u8]) -> Vec<u8> {
+        let mask = [0x37, 0xFA, 0x21, 0x3D];
+        let mut frame = vec![if fin { 0x80 | opcode } else { opcode }];
+        match payload.len() {
+            length @ 0..=125 => frame.push(0x80 | length as u8),
+            length => {
+                frame.push(0x80 | 126);
+                frame.extend_from_slice(&(length as u16).to_be_bytes());
+            }
+        }
+        frame.extend_from_slice(&mask);
+        frame.extend(payload.iter().enumerate().map(|(i, byte)| byte ^ mask[i % 4]));
+        frame
+    }
+
+    /// A WebSocket that receives `frames`, and the client's end of the connection it writes to
+    fn socket(frames: &[Vec<u8>]) -> (WebSocket, TcpStream) {
+        let (server, client) = connection();
+        (WebSocket::new(Box::new(Cursor::new(frames.concat())), server), client)
+    }
+
+    /// The opcode and payload of every frame the client received, after the socket is dropped
+    fn received(socket: WebSocket, mut client: TcpStream) -> Vec<(u8, Vec<u8>)> {
+        drop(socket);
+        let mut bytes = Vec::new();
+        client.read_to_end(&mut bytes).unwrap();
+        let mut frames = Vec::new();
+        let mut rest = &bytes[..];
+        while !rest.is_empty() {
+            let length = (rest[1] & 0x7F) as usize;
+            assert!(length <= 125, "unexpected long frame");
+            frames.push((rest[0] & 0x0F, rest[2..2 + length].to_vec()));
+            rest = &rest[2 + length..];
+        }
+        frames
+    }
+
+    fn protocol_code(result: Result<Message, WsError>) -> u16 {
+        match result {
+            Err(WsError::Protocol { code, .. }) => code,
+            other => panic!("not a protocol error: {other:?}"),
+        }
+    }
+
+    /// The raw response to a handshake with `headers`
+    fn handshake(headers: &[(&str, &str)]) -> String {
+        let handler = into_handler(|_, _| {});
+        respond_text(&request("GET /ws HTTP/1.1", headers, ""), |request, response| handler(request, response))
+    }
+
+    const HANDSHAKE: [(&str, &str); 4] = [
+        ("Upgrade", "websocket"),
+        ("Connection", "keep-alive, Upgrade"),
+        ("Sec-WebSocket-Version", "13"),
+        ("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ=="),
+    ];
+
+    #[test]
+    fn computes_the_accept_key() {
+        let text = handshake(&HANDSHAKE);
+        assert!(text.starts_with("HTTP/1.1 101 Switching Protocols\r\n"));
+        assert_eq!(header(&text, "Sec-WebSocket-Accept"), Some("s3pPLMBiTxaQ9kYGzzhZRbK+xOo="));
+        assert_eq!(header(&text, "Upgrade"), Some("websocket"));
+    }
+
+    #[test]
+    fn rejects_invalid_handshakes() {
+        let no_upgrade = handshake(&HANDSHAKE[1..]);
+        assert!(no_upgrade.starts_with("HTTP/1.1 426 Upgrade Required\r\n"));
+        assert_eq!(header(&no_upgrade, "Upgrade"), Some("websocket"));
+        let mut old_version = HANDSHAKE;
+        old_version[2].1 = "8";
+        let old_version = handshake(&old_version);
+        assert!(old_version.starts_with("HTTP/1.1 426 Upgrade Required\r\n"));
+        assert_eq!(header(&old_version, "Sec-WebSocket-Version"), Some("13"));
+        let mut short_key = HANDSHAKE;
+        short_key[3].1 = "c2hvcnQ=";
+        assert!(handshake(&short_key).starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        assert!(handshake(&HANDSHAKE[..3]).starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    }
+
+    #[test]
+    fn reads_masked_messages() {
+        let (mut messages, _client) = socket(&[masked(true, TEXT, b"hello"), masked(true, BINARY, &[0, 1, 2])]);
+        assert_eq!(messages.recv(), Ok(Message::Text(String::from("hello"))));
+        assert_eq!(messages.recv(), Ok(Message::Binary(vec![0, 1, 2])));
+        assert_eq!(messages.recv(), Err(WsError::Io(ErrorKind::UnexpectedEof)));
+    }
+
+    #[test]
+    fn rejects_unmasked_frames() {
+        let (mut unmasked, client) = socket(&[vec![0x81, 0x02, b'h', b'i']]);
+        assert_eq!(protocol_code(unmasked.recv()), CloseFrame::PROTOCOL_ERROR);
+        assert_eq!(unmasked.recv(), Err(WsError::Closed));
+        let frames = received(unmasked, client);
+        assert_eq!(frames.len(), 1);
+        assert_eq!((frames[0].0, &frames[0].1[..2]), (CLOSE, &CloseFrame::PROTOCOL_ERROR.to_be_bytes()[..]));
+    }
+
+    #[test]
+    fn answers_pings_in_the_middle_of_a_fragmented_message() {
+        let (mut fragmented, client) = socket(&[
+            masked(false, TEXT, b"Hel"),
+            masked(true, PING, b"are you there"),
+            masked(false, CONTINUATION, b"lo, "),
+            masked(true, CONTINUATION, "wörld".as_bytes()),
+        ]);
+        assert_eq!(fragmented.recv(), Ok(Message::Ping(b"are you there".to_vec())));
+        assert_eq!(fragmented.recv(), Ok(Message::Text(String::from("Hello, wörld"))));
+        let frames = received(fragmented, client);
+        assert_eq!(frames[0], (PONG, b"are you there".to_vec()));
+    }
+
+    #[test]
+    fn rejects_broken_fragments() {
+        let (mut interrupted, _client) = socket(&[masked(false, TEXT, b"a"), masked(true, TEXT, b"b")]);
+        assert_eq!(protocol_code(interrupted.recv()), CloseFrame::PROTOCOL_ERROR);
+        let (mut orphan, _client) = socket(&[masked(true, CONTINUATION, b"a")]);
+        assert_eq!(protocol_code(orphan.recv()), CloseFrame::PROTOCOL_ERROR);
+    }
+
+    #[test]
+    fn rejects_oversized_and_fragmented_control_frames() {
+        let (mut oversized, _client) = socket(&[masked(true, PING, &[0; 126])]);
+        assert_eq!(protocol_code(oversized.recv()), CloseFrame::PROTOCOL_ERROR);
+        let (mut fragmented, _client) = socket(&[masked(false, PING, b"a")]);
+        assert_eq!(protocol_code(fragmented.recv()), CloseFrame::PROTOCOL_ERROR);
+    }
+
+    #[test]
+    fn limits_the_message_size() {
+        let (mut single, _client) = socket(&[masked(true, BINARY, &[0; 11])]);
+        single.set_max_message_size(10);
+        assert_eq!(protocol_code(single.recv()), CloseFrame::MESSAGE_TOO_BIG);
+        let (mut fragmented, _client) = socket(&[masked(false, BINARY, &[0; 6]), masked(true, CONTINUATION, &[0; 6])]);
+        fragmented.set_max_message_size(10);
+        assert_eq!(protocol_code(fragmented.recv()), CloseFrame::MESSAGE_TOO_BIG);
+    }
+
+    #[test]
+    fn rejects_invalid_utf8() {
+        let (mut text, _client) = socket(&[masked(true, TEXT, &[0x68, 0xFF])]);
+        assert_eq!(protocol_code(text.recv()), CloseFrame::INVALID_DATA);
+        // A character may be split across fragments; only the whole message has to be valid
+        let (mut split, _client) = socket(&[masked(false, TEXT, &[0xC3]), masked(true, CONTINUATION, &[0xB6])]);
+        assert_eq!(split.recv(), Ok(Message::Text(String::from("ö"))));
+    }
+
+    #[test]
+    fn answers_close_frames() {
+        let mut payload = CloseFrame::GOING_AWAY.to_be_bytes().to_vec();
+        payload.extend_from_slice(b"bye");
+        let (mut closing, client) = socket(&[masked(true, CLOSE, &payload)]);
+        let close = CloseFrame { code: CloseFrame::GOING_AWAY, reason: String::from("bye") };
+        assert_eq!(closing.recv(), Ok(Message::Close(Some(close))));
+        assert!(closing.is_closed());
+        assert_eq!(closing.recv(), Err(WsError::Closed));
+        assert_eq!(received(closing, client), vec![(CLOSE, CloseFrame::GOING_AWAY.to_be_bytes().to_vec())]);
+        let (mut empty, _client) = socket(&[masked(true, CLOSE, &[])]);
+        assert_eq!(empty.recv(), Ok(Message::Close(None)));
+    }
+
+    #[test]
+    fn validates_close_codes() {
+        for code in [0u16, 999, 1004, 1005, 1006, 1012, 1015, 2999, 5000] {
+            let (mut invalid, _client) = socket(&[masked(true, CLOSE, &code.to_be_bytes())]);
+            assert_eq!(protocol_code(invalid.recv()), CloseFrame::PROTOCOL_ERROR, "{code}");
+        }
+        for code in [1000u16, 1003, 1007, 1011, 3000, 4999] {
+            let (mut valid, _client) = socket(&[masked(true, CLOSE, &code.to_be_bytes())]);
+            assert!(matches!(valid.recv(), Ok(Message::Close(Some(_)))), "{code}");
+        }
+        let (mut one_byte, _client) = socket(&[masked(true, CLOSE, &[0x03])]);
+        assert_eq!(protocol_code(one_byte.recv()), CloseFrame::PROTOCOL_ERROR);
+        let (mut bad_reason, _client) = socket(&[masked(true, CLOSE, &[0x03, 0xE8, 0xFF])]);
+        assert_eq!(protocol_code(bad_reason.recv()), CloseFrame::INVALID_DATA);
+    }
+
+    #[test]
+    fn writes_unmasked_frames() {
+        let (mut sending, client) = socket(&[]);
+        sending.send_text("hi").unwrap();
+        sending.ping(&[1; 200]).unwrap();
+        let frames = received(sending, client);
+        assert_eq!(frames[0], (TEXT, b"hi".to_vec()));
+        assert_eq!(frames[1], (PING, vec![1; 125]));
+        assert_eq!(frames[2], (CLOSE, CloseFrame::NORMAL.to_be_bytes().to_vec()));
+    }
+}